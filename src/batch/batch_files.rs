@@ -0,0 +1,172 @@
+/***************************************************************************
+ * Identifies the ror data dumps in the data folder, for a batch import, and
+ * puts them into version order. Only files with names that start with the
+ * version and date (as required for a single import) and that have one of
+ * the supported extensions are considered. If more than one file is found
+ * for a version (e.g. both the zip archive and the extracted json file) the
+ * json file is preferred, then a zip archive, then a csv file, and the
+ * others are reported as skipped. Also provides the table of results
+ * written to the log at the end of the batch.
+ ***************************************************************************/
+
+use std::fs;
+use std::path::PathBuf;
+use crate::AppError;
+use crate::setup::{is_compliant_file_name, get_data_version, get_data_date};
+use crate::setup::data_version::DataVersion;
+
+// Supported extensions, in order of preference.
+
+const EXTENSIONS: [&str; 5] = [".json", ".json.gz", ".zip", ".csv", ".csv.gz"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchFile {
+    pub file_name: String,
+    pub data_version: DataVersion,
+    pub data_date: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchResult {
+    Imported,
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchOutcome {
+    pub file_name: String,
+    pub vcode: String,
+    pub result: BatchResult,
+    pub secs: f64,
+}
+
+
+pub fn get_folder_file_names(data_folder: &PathBuf) -> Result<Vec<String>, AppError> {
+    let mut file_names = Vec::new();
+    for entry in fs::read_dir(data_folder)? {
+        let entry = entry?;
+        if entry.path().is_file() {
+            file_names.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    Ok(file_names)
+}
+
+
+fn extension_rank(file_name: &str) -> Option<usize> {
+
+    // The longest matching extension is used, so that '.json.gz' is not taken as '.gz'.
+
+    let lc_name = file_name.to_lowercase();
+    EXTENSIONS.iter().enumerate()
+        .filter(|(_, ext)| lc_name.ends_with(*ext))
+        .max_by_key(|(_, ext)| ext.len())
+        .map(|(i, _)| i)
+}
+
+
+pub fn select_batch_files(file_names: &[String]) -> (Vec<BatchFile>, Vec<BatchOutcome>) {
+
+    // Returns the files to be imported, in version order, and the
+    // outcomes for any dumps that are skipped.
+
+    let mut candidates: Vec<(BatchFile, usize)> = Vec::new();
+    let mut skipped = Vec::new();
+
+    for file_name in file_names {
+        let rank = match extension_rank(file_name) {
+            Some(r) if is_compliant_file_name(file_name) => r,
+            _ => continue,
+        };
+        let version = DataVersion::parse(&get_data_version(file_name));
+        let data_date = get_data_date(file_name);
+        match version {
+            Some(data_version) if !data_date.is_empty() => {
+                candidates.push((BatchFile { file_name: file_name.clone(), data_version, data_date }, rank));
+            },
+            _ => skipped.push(skipped_outcome(file_name, "", "version or date could not be read from the name")),
+        }
+    }
+
+    candidates.sort_by(|a, b| a.0.data_version.cmp(&b.0.data_version)
+                                .then(a.1.cmp(&b.1))
+                                .then(a.0.file_name.cmp(&b.0.file_name)));
+
+    let mut selected: Vec<BatchFile> = Vec::new();
+    for (bf, _) in candidates {
+        match selected.last() {
+            Some(prev) if prev.data_version == bf.data_version => {
+                let reason = format!("another file ({}) is used for this version", prev.file_name);
+                skipped.push(skipped_outcome(&bf.file_name, &bf.data_version.code(), &reason));
+            },
+            _ => selected.push(bf),
+        }
+    }
+    (selected, skipped)
+}
+
+
+pub fn skipped_outcome(file_name: &str, vcode: &str, reason: &str) -> BatchOutcome {
+    BatchOutcome {
+        file_name: file_name.to_string(),
+        vcode: vcode.to_string(),
+        result: BatchResult::Skipped(reason.to_string()),
+        secs: 0.0,
+    }
+}
+
+
+pub fn get_results_table(outcomes: &[BatchOutcome]) -> Vec<String> {
+
+    let mut lines = vec![format!("{:<60.60}{:<12}{:<10}{:>10}  {}", "File", "Version", "Result", "Secs", "Notes")];
+    for o in outcomes {
+        let (result, notes) = match &o.result {
+            BatchResult::Imported => ("imported", ""),
+            BatchResult::Skipped(s) => ("skipped", s.as_str()),
+            BatchResult::Failed(s) => ("FAILED", s.as_str()),
+        };
+        lines.push(format!("{:<60.60}{:<12}{:<10}{:>10.1}  {}", o.file_name, o.vcode, result, o.secs, notes));
+    }
+    lines
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_batch_files_selected_in_version_order() {
+        let names: Vec<String> = ["v1.100-2025-06-01-ror-data.zip", "v1.59-2025-01-10-ror-data.zip",
+                                  "v1.59-2025-01-10-ror-data_schema_v2.json", "v1.58-2024-12-11-ror-data.csv.gz",
+                                  "ror 10-18 101010 batch import.log", "v1.59 summary at 10-18 101010.txt",
+                                  "v1.60-2025-02-30-ror-data.json"]
+                                  .iter().map(|s| s.to_string()).collect();
+        let (selected, skipped) = select_batch_files(&names);
+
+        let files: Vec<&str> = selected.iter().map(|f| f.file_name.as_str()).collect();
+        assert_eq!(files, vec!["v1.58-2024-12-11-ror-data.csv.gz", "v1.59-2025-01-10-ror-data_schema_v2.json",
+                               "v1.100-2025-06-01-ror-data.zip"]);
+        assert_eq!(selected[1].data_date, "2025-01-10");
+
+        let skipped_files: Vec<&str> = skipped.iter().map(|o| o.file_name.as_str()).collect();
+        assert_eq!(skipped_files, vec!["v1.60-2025-02-30-ror-data.json", "v1.59-2025-01-10-ror-data.zip"]);
+    }
+
+    #[test]
+    fn check_results_table() {
+        let outcomes = vec![
+            BatchOutcome { file_name: "v1.58-2024-12-11-ror-data.zip".to_string(), vcode: "v1.58".to_string(),
+                           result: BatchResult::Imported, secs: 12.34 },
+            BatchOutcome { file_name: "v1.59-2025-01-10-ror-data.zip".to_string(), vcode: "v1.59".to_string(),
+                           result: BatchResult::Failed("bad json".to_string()), secs: 1.0 },
+        ];
+        let lines = get_results_table(&outcomes);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].contains("imported") && lines[1].contains("12.3"));
+        assert!(lines[2].contains("FAILED") && lines[2].ends_with("bad json"));
+    }
+}
@@ -0,0 +1,119 @@
+/***************************************************************************
+ * The batch module. Referenced in main by 'mod batch'.
+ * Imports, processes and summarises every ror data dump found in the data
+ * folder, in version order, so that a new installation can be populated
+ * (or an existing one brought up to date) in a single run. Versions that
+ * have already been summarised are skipped unless --force is used. A file
+ * that fails is recorded and the batch continues with the next file; the
+ * outcome for each file is listed in a table at the end of the (single)
+ * batch log.
+ ***************************************************************************/
+
+mod batch_files;
+
+use log::{info, error};
+use sqlx::{Pool, Postgres};
+use std::time::Instant;
+use crate::error_defs::{AppError, CustomError};
+use crate::setup::InitParams;
+use crate::{import, process, summarise, history};
+use batch_files::{BatchFile, BatchOutcome, BatchResult};
+
+
+pub async fn run_batch(params: &InitParams, pool: &Pool<Postgres>) -> Result<(), AppError>
+{
+    let file_names = match batch_files::get_folder_file_names(&params.data_folder) {
+        Ok(f) => f,
+        Err(e) => {
+            error!("An error occured while reading the data folder for the batch import: {}", e);
+            return Err(e)
+        },
+    };
+    let (batch, mut outcomes) = batch_files::select_batch_files(&file_names);
+    info!("{} data files found for the batch import, in {}", batch.len(), params.data_folder.display());
+
+    let summarised = get_summarised_versions(pool).await?;
+    for bf in batch {
+        let vcode = bf.data_version.code();
+        if summarised.contains(&vcode) && !params.flags.force_import {
+            info!("{} has already been summarised - {} is skipped", vcode, bf.file_name);
+            outcomes.push(batch_files::skipped_outcome(&bf.file_name, &vcode, "already summarised (use --force to repeat)"));
+            continue;
+        }
+
+        info!("");
+        info!("BATCH IMPORT OF {} ({})", bf.file_name, vcode);
+        info!("");
+        let start = Instant::now();
+        let result = match import_file(&get_file_params(params, &bf), pool).await {
+            Ok(()) => BatchResult::Imported,
+            Err(e) => {
+                error!("The batch import of {} failed: {}", bf.file_name, e);
+                BatchResult::Failed(e.to_string().trim().replace('\n', " "))
+            },
+        };
+        outcomes.push(BatchOutcome { file_name: bf.file_name, vcode, result, secs: start.elapsed().as_secs_f64() });
+    }
+
+    info!("");
+    info!("BATCH IMPORT RESULTS");
+    info!("");
+    for line in batch_files::get_results_table(&outcomes) {
+        info!("{}", line);
+    }
+    info!("");
+
+    let num_failed = outcomes.iter().filter(|o| matches!(o.result, BatchResult::Failed(_))).count();
+    if num_failed > 0 {
+        let msg = format!("{} of the files in the batch could not be imported - see the log for details", num_failed);
+        return Err(AppError::CsErr(CustomError::new(&msg)));
+    }
+    Ok(())
+}
+
+
+fn get_file_params(params: &InitParams, bf: &BatchFile) -> InitParams {
+
+    // The version and date are always taken from the file name, and the format
+    // from its extension. The file is (re)imported even if it has been imported
+    // before, as the version still needs to be processed and summarised.
+
+    let mut file_params = params.clone();
+    file_params.source_file_name = bf.file_name.clone();
+    file_params.data_version = Some(bf.data_version.clone());
+    file_params.data_date = bf.data_date.clone();
+    file_params.source_format = "".to_string();
+    file_params.flags.force_import = true;
+    file_params
+}
+
+
+async fn get_summarised_versions(pool: &Pool<Postgres>) -> Result<Vec<String>, AppError> {
+
+    let sql = r#"select to_regclass('smm.version_summaries') is not null"#;
+    let exists: bool = sqlx::query_scalar(sql).fetch_one(pool).await?;
+    let vcodes: Vec<String> = match exists {
+        true => sqlx::query_scalar(r#"select vcode from smm.version_summaries"#).fetch_all(pool).await?,
+        false => Vec::new(),
+    };
+    Ok(vcodes)
+}
+
+
+async fn import_file(params: &InitParams, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // The same sequence as for a single file imported with -a.
+
+    let checksum = import::get_source_checksum(params)?;
+    if import::import_required(params, &checksum, pool).await? {
+        import::create_ror_tables(pool).await?;
+        import::import_data(params, &checksum, pool).await?;
+        import::summarise_import(pool).await?;
+
+        process::create_src_tables(pool).await?;
+        process::process_data(&params.data_version, params.unmapped_categories, &params.dup_name_rules_path, pool).await?;
+        summarise::summarise_data(&params.delta_threshold, pool).await?;
+        history::store_snapshot(params.snapshot_retention, pool).await?;
+    }
+    Ok(())
+}
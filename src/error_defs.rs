@@ -1,5 +1,5 @@
 // The errors module, that defines a general 'AppError' struct.
-// This allows various types of error (I/O, sql, dotenv, serde,  etc.) to be transformed
+// This allows various types of error (I/O, sql, dotenv, serde, zip, csv, etc.) to be transformed
 // into the same error type, allowing function signatures returning a result type 
 // to propogate any error up the call stack by simply using the '?' operator.
 // Also defines a 'custom error' type to deal with cases not covered by 
@@ -15,6 +15,8 @@ pub enum AppError {
     IoErr(std::io::Error),
     SdErr(serde_json::Error),
     LgErr(log::SetLoggerError),
+    ZpErr(zip::result::ZipError),
+    CvErr(csv::Error),
     CsErr(CustomError),
 }
 
@@ -29,6 +31,8 @@ impl fmt::Display for AppError { // Error message for users.
             AppError::IoErr(ref err) => write!(f, "io error: {}", err),
             AppError::SdErr(ref err) => write!(f, "serde json error: {}", err),
             AppError::LgErr(ref err) => write!(f, "log set config error: {}", err),
+            AppError::ZpErr(ref err) => write!(f, "zip archive error: {}", err),
+            AppError::CvErr(ref err) => write!(f, "csv error: {}", err),
             AppError::CsErr(ref err) => write!(f, "file error: {}", err),
         }
     }
@@ -81,6 +85,18 @@ impl From<log::SetLoggerError> for AppError {
     }
 }
 
+impl From<zip::result::ZipError> for AppError {
+    fn from(err: zip::result::ZipError) -> AppError {
+        AppError::ZpErr(err)
+    }
+}
+
+impl From<csv::Error> for AppError {
+    fn from(err: csv::Error) -> AppError {
+        AppError::CvErr(err)
+    }
+}
+
 impl From<CustomError> for AppError {
     fn from(err: CustomError) -> AppError {
         AppError::CsErr(err)
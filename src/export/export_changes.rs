@@ -0,0 +1,284 @@
+/***************************************************************************
+ * Writes a 'changed records' feed, for downstream systems that hold a copy
+ * of the ror data and want to apply incremental updates rather than reload
+ * everything. The feed lists the organisations created, modified or deleted
+ * since a given earlier version, as a jsonl file with one line per record:
+ * {"operation": "create" | "update" | "delete", "id": <ror id>, "record": ...}
+ * where record is the full (v2 shaped) record, or null for deletions.
+ * Created and deleted records are found by comparing the ids in the ror
+ * tables with those in the fingerprints of the earlier version (see
+ * smm_org_changes), modified records by their admin.last_modified date
+ * being later than the date of the earlier version (or, for records from
+ * v1 data, which have no admin dates, by a change in their fingerprint). The records are
+ * rebuilt from the ror tables, with any 'extras' merged back in, so are
+ * only as exact as those tables - e.g. coordinates are held as reals, and
+ * an external id that is the only one of its type is always 'preferred'.
+ * A manifest, in json, gives the two versions, the number of each type of
+ * operation, and the name and SHA-256 checksum of the jsonl file.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use futures::TryStreamExt;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use chrono::{Local, NaiveDate};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use crate::error_defs::{AppError, CustomError};
+use crate::setup::data_version::DataVersion;
+use crate::summarise::smm_org_changes::get_fingerprints_sql;
+
+#[derive(Serialize, Debug, Default)]
+pub struct ChangesManifest {
+    pub from_version: String,
+    pub from_date: String,
+    pub to_version: String,
+    pub to_date: String,
+    pub generated_at: String,
+    pub changes_file: String,
+    pub changes_file_sha256: String,
+    pub num_created: usize,
+    pub num_updated: usize,
+    pub num_deleted: usize,
+}
+
+
+pub async fn generate_changes(output_folder: &PathBuf, since: &DataVersion,
+                              pool: &Pool<Postgres>) -> Result<ChangesManifest, AppError> {
+
+    let (to_vcode, to_date) = get_current_version(pool).await?;
+    let from_vcode = since.code();
+    let from_date = get_since_date(since, &to_vcode, pool).await?;
+
+    let datetime_string = Local::now().format("%m-%d %H%M%S").to_string();
+    let file_name = format!("{} changes since {} {}.jsonl", to_vcode, from_vcode, datetime_string);
+    let file_path: PathBuf = [output_folder, &PathBuf::from(&file_name)].iter().collect();
+    let mut writer = BufWriter::new(File::create(&file_path)?);
+    let mut hasher = Sha256::new();
+
+    let mut manifest = ChangesManifest {
+        from_version: from_vcode.clone(),
+        from_date: from_date.format("%Y-%m-%d").to_string(),
+        to_version: to_vcode.clone(),
+        to_date,
+        generated_at: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        changes_file: file_name,
+        ..Default::default()
+    };
+
+    // The records are streamed from the database, as the changes since
+    // a much earlier version may include most of the organisations.
+
+    let sql = get_changes_sql();
+    let mut rows = sqlx::query_as::<_, (String, String, Option<String>)>(&sql)
+                   .bind(&from_vcode).bind(from_date).fetch(pool);
+    while let Some((op, full_id, record)) = rows.try_next().await? {
+        match op.as_str() {
+            "create" => manifest.num_created += 1,
+            "update" => manifest.num_updated += 1,
+            _ => manifest.num_deleted += 1,
+        }
+        let line = changes_line(&op, &full_id, record.as_deref()) + "\n";
+        hasher.update(line.as_bytes());
+        writer.write_all(line.as_bytes())?;
+    }
+    writer.flush()?;
+    manifest.changes_file_sha256 = format!("{:x}", hasher.finalize());
+
+    let manifest_name = format!("{} changes since {} {} manifest.json", to_vcode, from_vcode, datetime_string);
+    let manifest_path: PathBuf = [output_folder, &PathBuf::from(manifest_name)].iter().collect();
+    let manifest_json = serde_json::to_string_pretty(&manifest)?;
+    std::fs::write(manifest_path, manifest_json)?;
+
+    Ok(manifest)
+}
+
+
+async fn get_current_version(pool: &Pool<Postgres>) -> Result<(String, String), AppError> {
+
+    // The version of the data currently in the ror tables.
+
+    let sql = r#"select to_regclass('ror.version_details') is not null"#;
+    let exists: bool = sqlx::query_scalar(sql).fetch_one(pool).await?;
+    let row: Option<(String, String)> = match exists {
+        true => sqlx::query_as(r#"select version, data_date from ror.version_details"#)
+                .fetch_optional(pool).await?,
+        false => None,
+    };
+    match row {
+        Some(r) => Ok(r),
+        None => {
+            let msg = "No data has been imported into the ror tables - the changed records cannot be listed";
+            Err(AppError::CsErr(CustomError::new(msg)))
+        },
+    }
+}
+
+
+async fn get_since_date(since: &DataVersion, to_vcode: &str, pool: &Pool<Postgres>) -> Result<NaiveDate, AppError> {
+
+    // The earlier version must precede the current one, have a known date,
+    // and have had its fingerprints stored, when it was processed.
+
+    let vcode = since.code();
+    let is_earlier = match DataVersion::parse(to_vcode) {
+        Some(dv) => *since < dv,
+        None => false,
+    };
+    if !is_earlier {
+        let msg = format!("Version {} is not earlier than the version of the imported data ({})", vcode, to_vcode);
+        return Err(AppError::CsErr(CustomError::new(&msg)));
+    }
+
+    let sql = r#"select exists(select 1 from smm.org_fingerprints where vcode = $1)"#;
+    let fingerprinted: bool = sqlx::query_scalar(sql).bind(&vcode).fetch_one(pool).await?;
+    if !fingerprinted {
+        let msg = format!("The ids of the organisations in {} are not available. They are only stored \
+                           when a version is processed (-p or -a).", vcode);
+        return Err(AppError::CsErr(CustomError::new(&msg)));
+    }
+
+    let sql = r#"select vdate from smm.version_registry where vcode = $1"#;
+    let vdate: Option<NaiveDate> = sqlx::query_scalar(sql).bind(&vcode).fetch_optional(pool).await?.flatten();
+    match vdate {
+        Some(d) => Ok(d),
+        None => {
+            let msg = format!("No date is registered for version {}", vcode);
+            Err(AppError::CsErr(CustomError::new(&msg)))
+        },
+    }
+}
+
+
+fn get_changes_sql() -> String {
+
+    // $1 is the earlier version's code, $2 its date. The names are regrouped
+    // so that each has its list of types, with 'ror_display' restored, and the
+    // external ids so that each type has its list of ids and preferred id.
+    // The extras of admin and locations include those of their nested objects.
+
+    // Records from v1 data have no last modified date. They are treated as modified
+    // if their fingerprint differs from the one stored for the earlier version.
+
+    format!("with fp as ({}),\n", get_fingerprints_sql()) + r#"
+    ch as (
+        select c.id, 'create' as op, 1 as seq from ror.core_data c
+        where not exists (select 1 from smm.org_fingerprints f where f.vcode = $1 and f.id = c.id)
+        union all
+        select c.id, 'update', 2 from ror.core_data c
+        inner join ror.admin_data a on c.id = a.id
+        inner join smm.org_fingerprints f on f.vcode = $1 and f.id = c.id
+        where a.last_modified > $2
+        or (a.last_modified is null and exists (select 1 from fp where fp.id = c.id
+            and (fp.status, fp.names, fp.locations, fp.relationships)
+            is distinct from (f.status, f.names, f.locations, f.relationships)))
+        union all
+        select f.id, 'delete', 3 from smm.org_fingerprints f
+        where f.vcode = $1 and not exists (select 1 from ror.core_data c where c.id = f.id)
+    ),
+    nm as (
+        select id, jsonb_agg(n order by n->>'value') as names from (
+            select id, jsonb_build_object('value', value, 'lang', lang, 'types',
+                   case when bool_or(coalesce(is_ror_name, false)) then '["ror_display"]'::jsonb else '[]'::jsonb end
+                   || jsonb_agg(name_type order by name_type)) || coalesce(extras, '{}') as n
+            from ror.names where id in (select id from ch)
+            group by id, value, lang, extras) g
+        group by id),
+    tp as (
+        select id, jsonb_agg(org_type order by org_type) as types
+        from ror.type where id in (select id from ch) group by id),
+    lc as (
+        select id, jsonb_agg(jsonb_build_object('geonames_id', geonames_id, 'geonames_details',
+               jsonb_build_object('continent_code', continent_code, 'continent_name', continent_name,
+               'country_code', country_code, 'country_name', country_name,
+               'country_subdivision_code', country_subdivision_code, 'country_subdivision_name', country_subdivision_name,
+               'lat', lat, 'lng', lng, 'name', name) || coalesce(extras->'geonames_details', '{}'))
+               || (coalesce(extras, '{}') - 'geonames_details') order by geonames_id) as locations
+        from ror.locations where id in (select id from ch) group by id),
+    ei as (
+        select id, jsonb_agg(e order by e->>'type') as external_ids from (
+            select id, jsonb_build_object('type', id_type, 'all', jsonb_agg(id_value order by id_value),
+                   'preferred', max(id_value) filter (where is_preferred))
+                   || coalesce((array_agg(extras) filter (where extras is not null))[1], '{}') as e
+            from ror.external_ids where id in (select id from ch)
+            group by id, id_type) g
+        group by id),
+    lk as (
+        select id, jsonb_agg(jsonb_build_object('type', link_type, 'value', value) || coalesce(extras, '{}')
+               order by link_type, value) as links
+        from ror.links where id in (select id from ch) group by id),
+    rl as (
+        select id, jsonb_agg(jsonb_build_object('type', rel_type, 'label', related_label,
+               'id', 'https://ror.org/' || related_id) || coalesce(extras, '{}')
+               order by rel_type, related_id) as relationships
+        from ror.relationships where id in (select id from ch) group by id),
+    dm as (
+        select id, jsonb_agg(value order by value) as domains
+        from ror.domains where id in (select id from ch) group by id)
+    select ch.op, coalesce(c.ror_full_id, 'https://ror.org/' || ch.id),
+    case when c.id is null then null else (jsonb_build_object('id', c.ror_full_id, 'status', c.status, 'established', c.established,
+        'names', coalesce(nm.names, '[]'), 'types', coalesce(tp.types, '[]'),
+        'locations', coalesce(lc.locations, '[]'), 'external_ids', coalesce(ei.external_ids, '[]'),
+        'links', coalesce(lk.links, '[]'), 'relationships', coalesce(rl.relationships, '[]'),
+        'domains', coalesce(dm.domains, '[]'),
+        'admin', case when a.last_modified is null then null else jsonb_build_object(
+            'created', jsonb_build_object('date', to_char(a.created, 'YYYY-MM-DD'), 'schema_version', a.cr_schema)
+                       || coalesce(a.extras->'created', '{}'),
+            'last_modified', jsonb_build_object('date', to_char(a.last_modified, 'YYYY-MM-DD'), 'schema_version', a.lm_schema)
+                       || coalesce(a.extras->'last_modified', '{}'))
+            || (coalesce(a.extras, '{}') - 'created' - 'last_modified') end)
+    || coalesce(c.extras, '{}'))::varchar end as record
+    from ch
+    left join ror.core_data c on ch.id = c.id
+    left join ror.admin_data a on ch.id = a.id
+    left join nm on ch.id = nm.id
+    left join tp on ch.id = tp.id
+    left join lc on ch.id = lc.id
+    left join ei on ch.id = ei.id
+    left join lk on ch.id = lk.id
+    left join rl on ch.id = rl.id
+    left join dm on ch.id = dm.id
+    order by ch.seq, ch.id"#
+}
+
+
+fn changes_line(op: &str, full_id: &str, record: Option<&str>) -> String {
+
+    // The record is already json, so is inserted as it stands.
+    // A deleted organisation is no longer in the ror tables, so never has a record.
+
+    let record = match op {
+        "delete" => "null",
+        _ => record.unwrap_or("null"),
+    };
+    format!(r#"{{"operation":{},"id":{},"record":{}}}"#, serde_json::Value::from(op),
+            serde_json::Value::from(full_id), record)
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_changes_lines_are_valid_json() {
+        let line = changes_line("update", "https://ror.org/04ttjf776", Some(r#"{"id": "https://ror.org/04ttjf776", "status": "active"}"#));
+        let v: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(v["operation"], "update");
+        assert_eq!(v["record"]["status"], "active");
+
+        let line = changes_line("delete", "https://ror.org/04ttjf776", None);
+        assert_eq!(line, r#"{"operation":"delete","id":"https://ror.org/04ttjf776","record":null}"#);
+    }
+
+    #[test]
+    fn check_deleted_ids_have_null_records() {
+        let line = changes_line("delete", "https://ror.org/02bfwt286", Some(r#"{"id": null, "status": null, "names": []}"#));
+        let v: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(v["id"], "https://ror.org/02bfwt286");
+        assert!(v["record"].is_null());
+    }
+}
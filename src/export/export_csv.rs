@@ -1,16 +1,22 @@
 use sqlx::{Pool, Postgres};
 use std::path::PathBuf;
 use crate::error_defs::{AppError, CustomError};
-use chrono::Local;
+use chrono::{Local, NaiveDate};
+use crate::summarise::smm_org_changes::CHANGE_TYPES;
 
-pub async fn generate_csv(output_folder : &PathBuf, data_version: &String, pool : &Pool<Postgres>) -> Result<(), AppError>
+pub async fn generate_csv(output_folder : &PathBuf, data_version: &String, baseline_date: NaiveDate, 
+                          pool : &Pool<Postgres>) -> Result<(), AppError>
 {
     let datetime_string = Local::now().format("%m-%d %H%M%S").to_string();
 
-    // 1) Version Summary 
+    // 1) Version Summary (with the days counted from the baseline date)
 
     let table_type = "summary".to_string();
-    let select_statement = r#"select * from smm.version_summaries where vcode = '"#.to_string() + data_version + r#"'"#;
+    let select_statement = format!(r#"select vcode, vdate, (vdate - '{}'::date) as vdays, 
+                           num_orgs, num_names, num_types, num_links, num_ext_ids, num_rels, num_locations, num_domains,
+                           prev_vcode, {}
+                           from smm.version_summaries where vcode = '{}'"#, baseline_date.format("%Y-%m-%d"), 
+                           get_change_cols(""), data_version);
     generate_file(output_folder, data_version, &select_statement, &datetime_string, &table_type, pool).await?;
 
     // 2) Attribute Summaries
@@ -54,89 +60,110 @@ pub async fn generate_csv(output_folder : &PathBuf, data_version: &String, pool
                         + data_version + r#"' order by org_type, rel_type"#;
     generate_file(output_folder, data_version, &select_statement, &datetime_string, &table_type, pool).await?;
 
+    // 8) Changes to individual organisations since the previous version
+
+    let table_type = "org changes".to_string();
+    let select_statement = r#"select * from smm.org_changes where vcode = '"#.to_string() 
+                        + data_version + r#"' order by change_type, id"#;
+    generate_file(output_folder, data_version, &select_statement, &datetime_string, &table_type, pool).await?;
+
     Ok(())
 }
 
 
 
-pub async fn generate_all_versions_csv(output_folder : &PathBuf, pool : &Pool<Postgres>) -> Result<(), AppError>
+pub async fn generate_all_versions_csv(output_folder : &PathBuf, baseline_date: NaiveDate, pool : &Pool<Postgres>) -> Result<(), AppError>
 {
 
     let datetime_string = Local::now().format("%m-%d %H%M%S").to_string();
     let data_version = "All versions".to_string();
 
+    // Versions are taken from the registry, which gives their order (by the numeric
+    // parts of the version code) and excludes any not to be included in the series.
+    // Days are counted from the baseline date.
+
+    let vs_cols = format!(r#"vs.vcode, vs.vdate, (vs.vdate - '{}'::date) as vdays"#, baseline_date.format("%Y-%m-%d"));
+    let vs_from = r#"smm.version_summaries vs 
+                             inner join smm.version_registry vr
+                             on vs.vcode = vr.vcode"#;
+    let vs_where = r#"where not vr.excluded_from_series"#;
+    let vs_order = r#"order by vr.major, vr.minor, vr.patch, vs.vcode"#;
+
     // 1) Version Summary 
 
     let table_type = "summary".to_string();
-    let select_statement = r#"select * from smm.version_summaries where vcode <> 'v1.57' order by vcode"#.to_string();
+    let select_statement = format!(r#"select {}, 
+                             vs.num_orgs, vs.num_names, vs.num_types, vs.num_links, vs.num_ext_ids, 
+                             vs.num_rels, vs.num_locations, vs.num_domains, vs.prev_vcode, {}
+                             from {} 
+                             {} {}"#, vs_cols, get_change_cols("vs."), vs_from, vs_where, vs_order);
     generate_file(output_folder, &data_version, &select_statement, &datetime_string, &table_type, pool).await?;
 
     // 2) Attribute Summaries
     
     let table_type = "attributes".to_string();
-    let select_statement = r#"select vs.vcode, vs.vdate, vs.vdays, 
+    let select_statement = format!(r#"select {}, 
                              s.att_type, s.att_name, s.id, s.name, s.number_atts, s.pc_of_atts, s.number_orgs, s.pc_of_orgs 
-                             from smm.version_summaries vs 
+                             from {} 
                              inner join smm.attributes_summary s
                              on vs.vcode = s.vcode
-                             where vs.vcode <> 'v1.57' order by vcode, att_name, id"#.to_string();
+                             {} {}, att_name, id"#, vs_cols, vs_from, vs_where, vs_order);
     generate_file(output_folder, &data_version, &select_statement, &datetime_string, &table_type, pool).await?;
 
     // 3) Count distributions
 
     let table_type = "counts".to_string();
-    let select_statement = r#"select vs.vcode, vs.vdate, vs.vdays, 
+    let select_statement = format!(r#"select {}, 
                              s.count_type, s.count_type, s.num_of_orgs, s.pc_of_orgs 
-                             from smm.version_summaries vs 
+                             from {} 
                              inner join smm.count_distributions s
                              on vs.vcode = s.vcode
-                             where vs.vcode <> 'v1.57' order by vcode, count_type, count"#.to_string();
+                             {} {}, count_type, count"#, vs_cols, vs_from, vs_where, vs_order);
     generate_file(output_folder, &data_version, &select_statement, &datetime_string, &table_type, pool).await?;
 
     // 4) Ranked count distributions
 
     let table_type = "ranked_counts".to_string();
-    let select_statement = r#"select vs.vcode, vs.vdate, vs.vdays, 
+    let select_statement = format!(r#"select {}, 
                              s.dist_type, s.rank, s.entity, s.number, s.pc_of_entities, s.pc_of_base_set 
-                             from smm.version_summaries vs 
+                             from {} 
                              inner join smm.ranked_distributions s
                              on vs.vcode = s.vcode
-                             where vs.vcode <> 'v1.57' order by vcode, dist_type, rank"#.to_string();
+                             {} {}, dist_type, rank"#, vs_cols, vs_from, vs_where, vs_order);
     generate_file(output_folder, &data_version, &select_statement, &datetime_string, &table_type, pool).await?;
 
     // 5) Singletons
 
     let table_type = "singletons".to_string();
-    let select_statement = r#"select vs.vcode, vs.vdate, vs.vdays, 
+    let select_statement = format!(r#"select {}, 
                              s.id, s.description, s.number, s.pc
-                             from smm.version_summaries vs 
+                             from {} 
                              inner join smm.singletons s
                              on vs.vcode = s.vcode
-                             where vs.vcode <> 'v1.57' order by vcode"#.to_string();
+                             {} {}"#, vs_cols, vs_from, vs_where, vs_order);
     generate_file(output_folder, &data_version, &select_statement, &datetime_string, &table_type, pool).await?;
 
     // 6) Org types and WOLC
 
     let table_type = "orgtypes and names wolc".to_string();
-    let select_statement = r#"select vs.vcode, vs.vdate, vs.vdays, 
+    let select_statement = format!(r#"select {}, 
                              s.org_type, s.name_type, s.names_num, s.names_wlc, s.names_wolc, s.names_wlc_pc, s.names_wolc_pc
-                             from smm.version_summaries vs 
+                             from {} 
                              inner join smm.org_type_and_lang_code s
                              on vs.vcode = s.vcode
-                             where vs.vcode <> 'v1.57' order by vcode, org_type, name_type"#.to_string();
+                             {} {}, org_type, name_type"#, vs_cols, vs_from, vs_where, vs_order);
     generate_file(output_folder, &data_version, &select_statement, &datetime_string, &table_type, pool).await?;
 
 
     // 7) Orgs types and relationships
 
     let table_type = "orgtypes and relationships".to_string();
-    let select_statement = r#"select vs.vcode, vs.vdate, vs.vdays, 
+    let select_statement = format!(r#"select {}, 
                              s.org_type, s.rel_type, s.num_links, s.num_orgs, s.num_orgs_total, s.num_orgs_pc
-                             from smm.version_summaries vs 
+                             from {} 
                              inner join smm.org_type_and_relationships s
                              on vs.vcode = s.vcode
-                             where vs.vcode <> 'v1.57' order by vcode, org_type, rel_type"#.to_string();
-                             r#"select * from smm.org_type_and_relationships where vcode <> 'v1.57' order by vcode, org_type, rel_type"#;
+                             {} {}, org_type, rel_type"#, vs_cols, vs_from, vs_where, vs_order);
     generate_file(output_folder, &data_version, &select_statement, &datetime_string, &table_type, pool).await?;
 
     Ok(())
@@ -144,6 +171,11 @@ pub async fn generate_all_versions_csv(output_folder : &PathBuf, pool : &Pool<Po
 
 
 
+fn get_change_cols(prefix: &str) -> String {
+    CHANGE_TYPES.iter().map(|(_, col, _)| format!("{}{}", prefix, col)).collect::<Vec<String>>().join(", ")
+}
+
+
 async fn  generate_file(output_folder: &PathBuf, data_version: &String, select_statement: &String,
                     datetime_string: &String, table_type: &String, pool : &Pool<Postgres>) -> Result<(), AppError> {
 
@@ -0,0 +1,57 @@
+/***************************************************************************
+ * Writes out the relationship issues found when the current src data was
+ * processed (see src_relationship_issues), as a csv file, one row per
+ * issue, with the relationship type given by name and a suggested repair.
+ * The rows are ordered by issue type and then organisation id, so that the
+ * file can be split up easily to make curation requests to ROR. Like the
+ * trends csv file it is written by the program rather than by Postgres.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use std::path::PathBuf;
+use chrono::Local;
+use log::info;
+use crate::AppError;
+
+
+#[derive(sqlx::FromRow)]
+struct RelIssue {
+    issue_type: String,
+    id: String,
+    ror_name: String,
+    relationship: String,
+    related_id: String,
+    related_name: String,
+    target_name: Option<String>,
+    suggestion: String,
+}
+
+
+pub async fn generate_rel_issues(output_folder: &PathBuf, pool: &Pool<Postgres>) -> Result<PathBuf, AppError> {
+
+    let vcode: String = sqlx::query_scalar("select version from src.version_details").fetch_one(pool).await?;
+
+    let sql = r#"select i.issue_type, i.id, i.ror_name, t.name as relationship, i.related_id,
+    i.related_name, i.target_name, i.suggestion
+    from src.relationship_issues i
+    inner join lup.ror_org_rels t
+    on i.rel_type = t.id
+    order by i.issue_type, i.id, i.rel_type, i.related_id"#;
+    let issues: Vec<RelIssue> = sqlx::query_as(sql).fetch_all(pool).await?;
+
+    let datetime_string = Local::now().format("%m-%d %H%M%S").to_string();
+    let file_name = format!("{} relationship issues at {}.csv", vcode, datetime_string);
+    let file_path: PathBuf = [output_folder, &PathBuf::from(file_name)].iter().collect();
+
+    let mut wtr = csv::Writer::from_path(&file_path)?;
+    wtr.write_record(["issue_type", "id", "ror_name", "relationship", "related_id",
+                      "related_name", "related_ror_name", "suggestion"])?;
+    for i in issues.iter() {
+        wtr.write_record([&i.issue_type, &i.id, &i.ror_name, &i.relationship, &i.related_id,
+                          &i.related_name, i.target_name.as_deref().unwrap_or_default(), &i.suggestion])?;
+    }
+    wtr.flush()?;
+
+    info!("{} relationship issues in {} written out", issues.len(), vcode);
+    Ok(file_path)
+}
@@ -3,7 +3,6 @@ use chrono::NaiveDate;
 #[derive(sqlx::FromRow)]
 pub struct VSummary {
     pub vdate: NaiveDate,
-    pub vdays: i32,
     pub num_orgs: i32,
     pub num_names: i32,
     pub num_types: i32,
@@ -75,3 +74,15 @@ pub struct OrgAndLangCode{
 
 
 
+
+#[derive(sqlx::FromRow)]
+pub struct MetricDeltaRow{
+    pub metric_group: String,
+    pub metric: String,
+    pub measure: String,
+    pub prev_value: Option<i64>,
+    pub value: Option<i64>,
+    pub abs_delta: i64,
+    pub pc_delta: Option<f32>,
+    pub flagged: bool,
+}
@@ -3,14 +3,17 @@ use std::{collections::HashMap, path::PathBuf};
 use crate::AppError;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
-use chrono::{NaiveDateTime, Local};
+use chrono::{NaiveDate, NaiveDateTime, Local};
 use super::export_structs::{VSummary, TypeRow, DistribRow, RankedRow, 
-                            SingletonRow, Singleton, OrgAndLangCode, OrgAndRel};
+                            SingletonRow, Singleton, OrgAndLangCode, OrgAndRel,
+                            MetricDeltaRow};
 use log::info;
+use crate::summarise::smm_org_changes::CHANGE_TYPES;
+use crate::summarise::smm_version_deltas::SUMMARY_METRICS;
 
 
 pub async fn generate_text(output_folder : &PathBuf, output_file_name: &String, 
-            data_version: &String, pool : &Pool<Postgres>) -> Result<(), AppError>
+            data_version: &String, baseline_date: NaiveDate, pool : &Pool<Postgres>) -> Result<(), AppError>
 {
     // If data version and date not given explicitly derive them from the data version table
     // as being the version, date of the currently stored version
@@ -27,7 +30,9 @@ pub async fn generate_text(output_folder : &PathBuf, output_file_name: &String,
     let output_file_str = output_file_path.to_str().unwrap();
             
     let singvals:HashMap<String, Singleton> = collect_singleton_values(&vcode, pool).await?;
-    write_header_and_summary(output_file_str, &vcode, pool).await?;
+    write_header_and_summary(output_file_str, &vcode, baseline_date, pool).await?;
+    write_metric_changes(output_file_str, &vcode, pool).await?;
+    write_change_summary(output_file_str, &vcode, pool).await?;
     write_explanation(output_file_str).await?;
     write_name_info(output_file_str, &vcode, pool, &singvals).await?;
     write_name_wolc_info(output_file_str, &vcode, pool, &singvals).await?;
@@ -60,7 +65,8 @@ async fn collect_singleton_values(vcode: &String, pool: &Pool<Postgres>) -> Resu
 
 }
 
-async fn write_header_and_summary(output_file_str: &str, vcode: &String, pool: &Pool<Postgres>) -> Result<(), AppError> {
+async fn write_header_and_summary(output_file_str: &str, vcode: &String, baseline_date: NaiveDate,
+                                  pool: &Pool<Postgres>) -> Result<(), AppError> {
     
     // get import date from the ror table
     let sql = "SELECT import_datetime from ror.version_details;";
@@ -71,7 +77,8 @@ async fn write_header_and_summary(output_file_str: &str, vcode: &String, pool: &
     let header_txt = get_hdr_line("SUMMARY OF ROR DATASET")
                    + "\n\n\tVersion: " + vcode  
                    + "\n\tDate: " + &summ.vdate.to_string() 
-                   + "\n\tDays since 29/04/24: " + &summ.vdays.to_string()
+                   + "\n\tDays since " + &baseline_date.format("%d/%m/%y").to_string() + ": " 
+                   + &(summ.vdate - baseline_date).num_days().to_string()
                    + "\n"
                    + "\n\tTime data imported: " + &import_dt.format("%Y-%m-%d %H:%M:%S").to_string()
                    + "\n\tReport generated: " + &Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
@@ -92,6 +99,81 @@ async fn write_header_and_summary(output_file_str: &str, vcode: &String, pool: &
     Ok(())
 }
 
+async fn write_metric_changes(output_file_str: &str, vcode: &String, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // The entity numbers compared with those of the previous version in the series,
+    // followed by any other metrics flagged as having changed beyond the threshold.
+
+    let sql = r#"SELECT prev_vcode, threshold_pc, min_base from smm.version_deltas WHERE vcode = $1"#;
+    let vdelta: Option<(Option<String>, f32, i32)> = sqlx::query_as(sql).bind(vcode).fetch_optional(pool).await?;
+
+    let txt = match vdelta {
+        Some((Some(prev), threshold_pc, min_base)) => {
+            let sql = r#"SELECT metric_group, metric, measure, prev_value, value, abs_delta, pc_delta, flagged 
+                         from smm.metric_deltas WHERE vcode = $1 
+                         order by abs(pc_delta) desc nulls first, metric_group, metric, measure"#;
+            let deltas: Vec<MetricDeltaRow> = sqlx::query_as(sql).bind(vcode).fetch_all(pool).await?;
+
+            let mut txt = format!("\n\tCHANGES SINCE PREVIOUS VERSION ({})\n\t", prev) 
+                        + "----------------------------------------------------------------------------------"
+                        + &get_delta_hdr();
+            for (col, label) in SUMMARY_METRICS {
+                if let Some(d) = deltas.iter().find(|d| d.metric_group == "summary" && d.metric == col) {
+                    txt += &get_delta_line(label, d.prev_value, d.value, d.abs_delta, d.pc_delta);
+                }
+            }
+
+            txt += &format!("\n\n\tOther metrics changed by {}% or more (from at least {})", threshold_pc, min_base);
+            let flagged: Vec<&MetricDeltaRow> = deltas.iter()
+                          .filter(|d| d.flagged && d.metric_group != "summary").collect();
+            if flagged.is_empty() {
+                txt += "\n\tNone";
+            }
+            else {
+                txt += &get_delta_hdr();
+                for d in flagged {
+                    let topic = format!("{} ({})", d.metric, d.measure);
+                    txt += &get_delta_line(&topic, d.prev_value, d.value, d.abs_delta, d.pc_delta);
+                }
+            }
+            txt + "\n"
+        },
+        _ => "\n\tCHANGES SINCE PREVIOUS VERSION\n\t".to_string() 
+                + "----------------------------------------------------------------------------------"
+                + "\n\tNo earlier version is available to compare with.\n",
+    };
+    append_to_file(output_file_str, &txt)
+}
+
+async fn write_change_summary(output_file_str: &str, vcode: &String, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // The numbers of each type of change to organisations since the previous version in the series.
+
+    let sql = r#"SELECT prev_vcode from smm.version_summaries WHERE vcode = $1"#;
+    let prev_vcode: Option<String> = sqlx::query_scalar(sql).bind(vcode).fetch_one(pool).await?;
+
+    let change_txt = match prev_vcode {
+        Some(prev) => {
+            let sql = r#"SELECT change_type, count(*) from smm.org_changes 
+                         WHERE vcode = $1 group by change_type"#;
+            let counts: HashMap<String, i64> = sqlx::query_as::<_, (String, i64)>(sql)
+                                               .bind(vcode).fetch_all(pool).await?
+                                               .into_iter().collect();
+            let mut txt = format!("\n\tORGANISATION CHANGES SINCE {}\n\t", prev) 
+                        + "----------------------------------------------------------------------------------";
+            for (change_type, _, description) in CHANGE_TYPES {
+                let n = counts.get(change_type).copied().unwrap_or(0);
+                txt += &get_data_line(description, n as i32);
+            }
+            txt + "\n"
+        },
+        None => "\n\tORGANISATION CHANGES\n\t".to_string() 
+                + "----------------------------------------------------------------------------------"
+                + "\n\tNo earlier version is available to compare with.\n",
+    };
+    append_to_file(output_file_str, &change_txt)
+}
+
 async fn write_explanation(output_file_str: &str) -> Result<(), AppError> {
     
     append_to_file(output_file_str, &get_hdr_line("ABBREVIATIONS AND TOTALS"))?;
@@ -196,6 +278,12 @@ async fn write_ranked_name_info(output_file_str: &str, vcode: &String, pool: &Po
                    + &get_singleton_line(&s3.description, s3.number, s3.pc);
     append_to_file(output_file_str, &s_text)?;
 
+    // Singleton - Names with unknown language codes (not available for versions summarised before it was added)
+
+    if let Some(s) = singvals.get("names_ulc") {
+        append_to_file(output_file_str, &get_singleton_line(&s.description, s.number, s.pc))?;
+    }
+
     // Ranked languages other than English in use - Ranked Distributions, dist_type = 1.
 
     let tbl_hdr_text = "\n\n\t                                           number           %age           %age".to_string()
@@ -215,6 +303,14 @@ async fn write_ranked_name_info(output_file_str: &str, vcode: &String, pool: &Po
 
     append_to_file(output_file_str, &s_text)?;
 
+    // Singletons - Names mixing scripts (not available for versions summarised before they were added)
+
+    if let (Some(s7), Some(s8)) = (singvals.get("names_mxs"), singvals.get("orgs_mxs")) {
+        let s_text = get_sing_hdr() + &get_singleton_line(&s7.description, s7.number, s7.pc)
+                       + &get_singleton_line(&s8.description, s8.number, s8.pc);
+        append_to_file(output_file_str, &s_text)?;
+    }
+
     // Ranked scripts other than Latin in use - Ranked Distributions, dist_type = 2.
     
     let tbl_hdr_text = "\n\n\t                                           number          %age           %age".to_string()
@@ -344,6 +440,20 @@ async fn write_relationship_details(output_file_str: &str, vcode: &String, pool:
     let table_text = get_distrib_table("successor orgs", "'has successor' relationshipss", vcode, pool).await?;
     append_to_file(output_file_str, &table_text)?;
 
+    // Hierarchy distributions (not available for versions summarised before they were added)
+
+    if let (Some(s4), Some(s5)) = (singvals.get("hier_mpar"), singvals.get("hier_cyc")) {
+        let table_text = get_distrib_table("hierarchy depth", "levels above them (in hierarchy)", vcode, pool).await?;
+        append_to_file(output_file_str, &table_text)?;
+
+        let table_text = get_distrib_table("family size", "orgs in family (top orgs only)", vcode, pool).await?;
+        append_to_file(output_file_str, &table_text)?;
+
+        let s_text = get_sing_hdr() + &get_singleton_line(&s4.description, s4.number, s4.pc)
+                       + &get_singleton_line(&s5.description, s5.number, s5.pc);
+        append_to_file(output_file_str, &s_text)?;
+    }
+
     // Write out singleton values
 
     let s1 = &singvals["nrecip_pc"]; 
@@ -509,7 +619,7 @@ fn get_ranked_distrib_line(topic: &str, num: i32, pc1: f32, pc2: f32)-> String {
     }
 }
  
-fn get_hdr_line(topic: &str) -> String {
+pub fn get_hdr_line(topic: &str) -> String {
     "\n\n\t==================================================================================".to_string()
     + "\n\t" + topic 
     + "\n\t=================================================================================="
@@ -524,6 +634,18 @@ fn get_data_line(topic: &str, num: i32) -> String {
     "\n\t".to_string() + topic + &spacer + &num.to_string() 
 }
 
+fn get_delta_hdr() -> String {
+    format!("\n\t{:<50}{:>8}{:>8}{:>8}{:>8}\n", "", "previous", "current", "change", "%age")
+}
+
+fn get_delta_line(topic: &str, prev: Option<i64>, num: Option<i64>, delta: i64, pc: Option<f32>) -> String {
+    let pc_as_string = match pc {
+        Some(p) => format!("{:+.2}", p),
+        None => "-".to_string(),
+    };
+    format!("\n\t{:<50.50}{:>8}{:>8}{:>+8}{:>8}", topic, prev.unwrap_or(0), num.unwrap_or(0), delta, pc_as_string)
+}
+
 fn get_orglc_line(org_type: &str, name_type: &str, names_num: i32, names_wolc: i32, names_wolc_pc: f32) -> String {
     let spacer1 = " ".repeat(23 - org_type.chars().count());
     let spacer2 = " ".repeat(26 - name_type.chars().count()- names_num.to_string().len());
@@ -556,7 +678,7 @@ fn get_singleton_line(topic: &str, num: i32, pc: Option<f32>) -> String {
     }
 }
 
-fn append_to_file(output_file_path: &str, contents: &str) -> Result<(), AppError> {
+pub fn append_to_file(output_file_path: &str, contents: &str) -> Result<(), AppError> {
 
     let mut file = OpenOptions::new()
         .create(true)
@@ -0,0 +1,283 @@
+/***************************************************************************
+ * Produces outputs covering all versions in the series (i.e. registered
+ * and not excluded), in version order:
+ * a) A trend report, as a text file, with the main entity numbers of each
+ * version, the growth of each since the first version, and month-on-month
+ * and year-on-year growth rates. As versions are not released at regular
+ * intervals the month-on-month (year-on-year) rate of a version compares it
+ * with the earlier version dated closest to one month (one year) before it,
+ * as long as that is within 15 days of the date - otherwise none is given.
+ * b) A 'wide' csv file, with one row per version and one column per metric,
+ * using the same metrics as the version deltas (see smm_version_deltas),
+ * plus the percentages held with the singletons. Unlike the other csv
+ * files this one is written by the program rather than the Postgres server.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use chrono::{Local, Months, NaiveDate};
+use crate::AppError;
+use crate::error_defs::CustomError;
+use crate::summarise::smm_version_deltas::{get_metrics, MetricKey, SUMMARY_METRICS};
+use super::export_text::{append_to_file, get_hdr_line};
+
+struct VersionData {
+    vcode: String,
+    vdate: NaiveDate,
+    vdays: i32,
+    counts: HashMap<MetricKey, i64>,
+    pcs: HashMap<String, f32>,
+}
+
+// The furthest a version's date can be from the date a month or a year earlier
+// for it to be used in the month-on-month or year-on-year growth rates.
+
+const MAX_GAP_DAYS: i64 = 15;
+
+// A column of the wide csv file - either a count or a singleton's percentage.
+
+#[derive(Debug, PartialEq)]
+enum WideColumn {
+    Count(MetricKey),
+    SingletonPc(String),
+}
+
+
+pub async fn generate_trends(output_folder: &PathBuf, baseline_date: NaiveDate,
+                             pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let versions = get_version_data(baseline_date, pool).await?;
+    if versions.is_empty() {
+        let msg = "No versions have been summarised, so no trends can be reported";
+        return Err(AppError::CsErr(CustomError::new(msg)));
+    }
+
+    let datetime_string = Local::now().format("%m-%d %H%M%S").to_string();
+    let report_name = format!("All versions trends at {}.txt", datetime_string);
+    let report_path: PathBuf = [output_folder, &PathBuf::from(report_name)].iter().collect();
+    write_trend_report(report_path.to_str().unwrap(), &versions, baseline_date)?;
+
+    let csv_name = format!("All versions wide {}.csv", datetime_string);
+    let csv_path: PathBuf = [output_folder, &PathBuf::from(csv_name)].iter().collect();
+    write_wide_csv(&csv_path, &versions)?;
+    Ok(())
+}
+
+
+async fn get_version_data(baseline_date: NaiveDate, pool: &Pool<Postgres>) -> Result<Vec<VersionData>, AppError> {
+
+    let sql = r#"select vs.vcode, vs.vdate, (vs.vdate - $1::date) as vdays
+                 from smm.version_summaries vs
+                 inner join smm.version_registry vr on vs.vcode = vr.vcode
+                 where not vr.excluded_from_series
+                 order by vr.major, vr.minor, vr.patch, vs.vcode"#;
+    let rows: Vec<(String, NaiveDate, i32)> = sqlx::query_as(sql).bind(baseline_date).fetch_all(pool).await?;
+
+    let mut versions = Vec::with_capacity(rows.len());
+    for (vcode, vdate, vdays) in rows {
+        let counts = get_metrics(&vcode, pool).await?.into_iter()
+                     .map(|r| ((r.metric_group, r.metric, r.measure), r.value)).collect();
+        let sql = r#"select id, pc from smm.singletons where vcode = $1 and pc is not null"#;
+        let pcs = sqlx::query_as::<_, (String, f32)>(sql).bind(&vcode).fetch_all(pool).await?
+                  .into_iter().collect();
+        versions.push(VersionData { vcode, vdate, vdays, counts, pcs });
+    }
+    Ok(versions)
+}
+
+
+fn summary_value(v: &VersionData, col: &str) -> i64 {
+    let key = ("summary".to_string(), col.to_string(), "number".to_string());
+    v.counts.get(&key).copied().unwrap_or(0)
+}
+
+
+fn write_trend_report(output_file_str: &str, versions: &[VersionData], baseline_date: NaiveDate) -> Result<(), AppError> {
+
+    let first = &versions[0];
+    let last = &versions[versions.len() - 1];
+
+    let mut txt = get_hdr_line("TRENDS ACROSS ROR VERSIONS")
+                + &format!("\n\n\tVersions: {} ({} to {})", versions.len(), first.vcode, last.vcode)
+                + &format!("\n\tDates: {} to {}", first.vdate, last.vdate)
+                + &format!("\n\tDays counted from: {}", baseline_date.format("%d/%m/%y"))
+                + "\n\tReport generated: " + &Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    // Entity numbers of each version.
+
+    txt += "\n\n\tENTITY NUMBERS BY VERSION\n\t";
+    txt += "----------------------------------------------------------------------------------";
+    txt += &format!("\n\t{:<9}{:>6}", "version", "days");
+    for label in ["Orgs", "Names", "Types", "Links", "Ext Ids", "Rels", "Locs", "Domains"] {
+        txt += &format!("{:>8}", label);
+    }
+    txt += "\n";
+    for v in versions {
+        txt += &format!("\n\t{:<9}{:>6}", v.vcode, v.vdays);
+        for (col, _) in SUMMARY_METRICS {
+            txt += &format!("{:>8}", summary_value(v, col));
+        }
+    }
+
+    // Overall growth.
+
+    txt += &format!("\n\n\n\tGROWTH FROM {} TO {}\n\t", first.vcode, last.vcode);
+    txt += "----------------------------------------------------------------------------------";
+    txt += &format!("\n\t{:<34}{:>12}{:>12}{:>12}{:>12}\n", "", first.vcode, last.vcode, "change", "%age");
+    for (col, label) in SUMMARY_METRICS {
+        let (a, b) = (summary_value(first, col), summary_value(last, col));
+        txt += &format!("\n\t{:<34}{:>12}{:>12}{:>+12}{:>12}", label, a, b, b - a, format_pc(pc_change(a, b)));
+    }
+
+    // Month-on-month and year-on-year growth of the main entities.
+
+    let dates: Vec<NaiveDate> = versions.iter().map(|v| v.vdate).collect();
+    let rate_cols = [("num_orgs", "Orgs"), ("num_names", "Names"), ("num_ext_ids", "Ext Ids")];
+    txt += "\n\n\n\tGROWTH RATES (% CHANGE SINCE A MONTH (MoM) AND A YEAR (YoY) EARLIER)\n\t";
+    txt += "----------------------------------------------------------------------------------";
+    txt += &format!("\n\t{:<9}{:>11}", "", "");
+    for (_, label) in rate_cols {
+        txt += &format!("{:>20}", label);
+    }
+    txt += &format!("\n\t{:<9}{:>11}", "version", "date");
+    for _ in rate_cols {
+        txt += &format!("{:>10}{:>10}", "MoM", "YoY");
+    }
+    txt += "\n";
+    for (i, v) in versions.iter().enumerate() {
+        txt += &format!("\n\t{:<9}{:>11}", v.vcode, v.vdate.to_string());
+        let mom = comparison_index(&dates, i, 1);
+        let yoy = comparison_index(&dates, i, 12);
+        for (col, _) in rate_cols {
+            let b = summary_value(v, col);
+            let mom_pc = mom.and_then(|j| pc_change(summary_value(&versions[j], col), b));
+            let yoy_pc = yoy.and_then(|j| pc_change(summary_value(&versions[j], col), b));
+            txt += &format!("{:>10}{:>10}", format_pc(mom_pc), format_pc(yoy_pc));
+        }
+    }
+    txt += "\n";
+
+    append_to_file(output_file_str, &txt)
+}
+
+
+fn write_wide_csv(csv_path: &PathBuf, versions: &[VersionData]) -> Result<(), AppError> {
+
+    let columns = get_wide_columns(versions);
+    let mut wtr = csv::Writer::from_path(csv_path)?;
+
+    let mut header = vec!["vcode".to_string(), "vdate".to_string(), "vdays".to_string()];
+    header.extend(columns.iter().map(wide_column_name));
+    wtr.write_record(&header)?;
+
+    for v in versions {
+        let mut record = vec![v.vcode.clone(), v.vdate.to_string(), v.vdays.to_string()];
+        for c in columns.iter() {
+            let value = match c {
+                WideColumn::Count(key) => v.counts.get(key).map(|n| n.to_string()),
+                WideColumn::SingletonPc(id) => v.pcs.get(id).map(|pc| format!("{:.2}", pc)),
+            };
+            record.push(value.unwrap_or_default());
+        }
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+
+fn get_wide_columns(versions: &[VersionData]) -> Vec<WideColumn> {
+
+    // The entity numbers come first, then the other metrics found in any version,
+    // in group order, with each singleton's percentage following its number.
+
+    let mut columns: Vec<WideColumn> = SUMMARY_METRICS.iter()
+        .map(|(col, _)| WideColumn::Count(("summary".to_string(), col.to_string(), "number".to_string())))
+        .collect();
+
+    let keys: BTreeSet<&MetricKey> = versions.iter().flat_map(|v| v.counts.keys())
+                                     .filter(|k| k.0 != "summary").collect();
+    let pc_ids: BTreeSet<&String> = versions.iter().flat_map(|v| v.pcs.keys()).collect();
+    for key in keys {
+        columns.push(WideColumn::Count(key.clone()));
+        if key.0 == "singletons" && pc_ids.contains(&key.1) {
+            columns.push(WideColumn::SingletonPc(key.1.clone()));
+        }
+    }
+    columns
+}
+
+
+fn wide_column_name(column: &WideColumn) -> String {
+    match column {
+        WideColumn::Count((group, metric, measure)) => match group.as_str() {
+            "summary" | "singletons" => metric.clone(),
+            "ranked" => format!("ranked {} ({})", metric, measure),
+            _ => format!("{} ({})", metric, measure),
+        },
+        WideColumn::SingletonPc(id) => format!("{} pc", id),
+    }
+}
+
+
+fn comparison_index(dates: &[NaiveDate], i: usize, months: u32) -> Option<usize> {
+
+    // The version before version i dated closest to the given number of months earlier,
+    // if it is within the allowed number of days of that date.
+
+    let target = dates[i].checked_sub_months(Months::new(months))?;
+    (0..i).map(|j| (j, (dates[j] - target).num_days().abs()))
+          .filter(|(_, gap)| *gap <= MAX_GAP_DAYS)
+          .min_by_key(|(_, gap)| *gap)
+          .map(|(j, _)| j)
+}
+
+
+fn pc_change(from: i64, to: i64) -> Option<f64> {
+    if from > 0 { Some(100.0 * (to - from) as f64 / from as f64) } else { None }
+}
+
+
+fn format_pc(pc: Option<f64>) -> String {
+    match pc {
+        Some(p) => format!("{:+.2}", p),
+        None => "-".to_string(),
+    }
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn check_comparison_versions() {
+        let dates = vec![d("2024-01-10"), d("2024-02-05"), d("2024-02-20"), d("2024-03-12"), 
+                         d("2024-06-01"), d("2025-01-20")];
+        assert_eq!(comparison_index(&dates, 0, 1), None);
+        assert_eq!(comparison_index(&dates, 1, 1), Some(0));
+        assert_eq!(comparison_index(&dates, 3, 1), Some(1));
+        assert_eq!(comparison_index(&dates, 4, 1), None);
+        assert_eq!(comparison_index(&dates, 3, 12), None);
+        assert_eq!(comparison_index(&dates, 5, 12), Some(0));
+    }
+
+    #[test]
+    fn check_wide_column_names() {
+        let count = |g: &str, m: &str, ms: &str| WideColumn::Count((g.to_string(), m.to_string(), ms.to_string()));
+        assert_eq!(wide_column_name(&count("summary", "num_orgs", "number")), "num_orgs");
+        assert_eq!(wide_column_name(&count("singletons", "ror_wolc", "number")), "ror_wolc");
+        assert_eq!(wide_column_name(&WideColumn::SingletonPc("nrecip_pc".to_string())), "nrecip_pc pc");
+        assert_eq!(wide_column_name(&count("attributes", "external id types: wikidata", "number_orgs")),
+                   "external id types: wikidata (number_orgs)");
+        assert_eq!(pc_change(200, 150), Some(-25.0));
+        assert_eq!(pc_change(0, 150), None);
+    }
+}
@@ -1,21 +1,30 @@
 mod export_text;
 mod export_csv;
 mod export_structs;
+mod export_changes;
+mod export_trends;
+mod export_rel_issues;
 
 use log::{info, error};
 use sqlx::{Pool, Postgres};
 use std::path::PathBuf;
+use chrono::NaiveDate;
 use crate::error_defs::{AppError, CustomError};
+use crate::setup::data_version::DataVersion;
+use crate::summarise::{smm_version_registry, smm_org_changes, smm_version_deltas};
 
 pub async fn export_as_text(output_folder : &PathBuf, output_file_name: &String, 
-               data_version: &String, pool : &Pool<Postgres>) -> Result<(), AppError>
+               data_version: &Option<DataVersion>, baseline_date: NaiveDate, pool : &Pool<Postgres>) -> Result<(), AppError>
 {
     // Write out summary data for this dataset into the designated file
 
-    check_data_version_present_in_summ_data(data_version, pool).await?;
+    let vcode = get_vcode(data_version);
+    check_data_version_present_in_summ_data(&vcode, pool).await?;
+    smm_org_changes::ensure_tables(pool).await?;
+    smm_version_deltas::ensure_tables(pool).await?;
 
     let r = export_text::generate_text(output_folder, output_file_name, 
-            data_version, pool).await;
+            &vcode, baseline_date, pool).await;
     match r {
         Ok(()) => {
             info!("Data summary generated as text file"); 
@@ -29,13 +38,16 @@ pub async fn export_as_text(output_folder : &PathBuf, output_file_name: &String,
 }
 
 
-pub async fn export_as_csv(output_folder : &PathBuf, data_version: &String, pool : &Pool<Postgres>) -> Result<(), AppError>
+pub async fn export_as_csv(output_folder : &PathBuf, data_version: &Option<DataVersion>, baseline_date: NaiveDate,
+                           pool : &Pool<Postgres>) -> Result<(), AppError>
 {
     // Write out summary data for this as a set of csv files into the designated folder
 
-    check_data_version_present_in_summ_data(data_version, pool).await?;
+    let vcode = get_vcode(data_version);
+    check_data_version_present_in_summ_data(&vcode, pool).await?;
+    smm_org_changes::ensure_tables(pool).await?;
 
-    let r = export_csv::generate_csv(output_folder, data_version, pool).await;
+    let r = export_csv::generate_csv(output_folder, &vcode, baseline_date, pool).await;
     match r {
         Ok(()) => {
             info!("Data summary generated as csv files"); 
@@ -49,11 +61,14 @@ pub async fn export_as_csv(output_folder : &PathBuf, data_version: &String, pool
 }
 
 
-pub async fn export_all_as_csv(output_folder : &PathBuf, pool : &Pool<Postgres>) -> Result<(), AppError>
+pub async fn export_all_as_csv(output_folder : &PathBuf, baseline_date: NaiveDate, pool : &Pool<Postgres>) -> Result<(), AppError>
 {
-    // Write out summary data for all versions as a set of csv files into the designated folder
+    // Write out summary data for all (registered, non-excluded) versions 
+    // as a set of csv files into the designated folder
 
-    let r = export_csv::generate_all_versions_csv(output_folder, pool).await;
+    smm_version_registry::ensure_registry(pool).await?;
+    smm_org_changes::ensure_tables(pool).await?;
+    let r = export_csv::generate_all_versions_csv(output_folder, baseline_date, pool).await;
     match r {
         Ok(()) => {
             info!("Data summary generated as csv files"); 
@@ -67,6 +82,80 @@ pub async fn export_all_as_csv(output_folder : &PathBuf, pool : &Pool<Postgres>)
 }
 
 
+pub async fn export_trends(output_folder : &PathBuf, baseline_date: NaiveDate, pool : &Pool<Postgres>) -> Result<(), AppError>
+{
+    // Write out a trend report for all (registered, non-excluded) versions, as a text 
+    // file, and the metrics of those versions as a wide format csv file
+
+    smm_version_registry::ensure_registry(pool).await?;
+    let r = export_trends::generate_trends(output_folder, baseline_date, pool).await;
+    match r {
+        Ok(()) => {
+            info!("Trend report and wide format csv file generated"); 
+            Ok(())
+        },
+        Err(e) => {
+            error!("An error occured while writing out the trend report: {}", e);
+            Err(e)
+        },
+    }
+}
+
+
+pub async fn export_changes(output_folder : &PathBuf, since: &DataVersion, pool : &Pool<Postgres>) -> Result<(), AppError>
+{
+    // Write out the records created, modified or deleted since the given version,
+    // as a jsonl file, together with a manifest, into the designated folder
+
+    smm_version_registry::ensure_registry(pool).await?;
+    smm_org_changes::ensure_tables(pool).await?;
+    let r = export_changes::generate_changes(output_folder, since, pool).await;
+    match r {
+        Ok(m) => {
+            info!("Changed records since {} written to '{}': {} created, {} updated, {} deleted", 
+                   m.from_version, m.changes_file, m.num_created, m.num_updated, m.num_deleted); 
+            Ok(())
+        },
+        Err(e) => {
+            error!("An error occured while writing out the changed records: {}", e);
+            Err(e)
+        },
+    }
+}
+
+
+pub async fn export_relationship_issues(output_folder : &PathBuf, pool : &Pool<Postgres>) -> Result<(), AppError>
+{
+    // Write out the relationship issues found when the data now in the src tables was processed, 
+    // as a csv file, into the designated folder
+
+    let sql = "select to_regclass('src.relationship_issues') is not null";
+    let table_exists: bool = sqlx::query_scalar(sql).fetch_one(pool).await?;
+    if !table_exists {
+        let mut msg = "\n\nThe relationship issues table does not exist in the src schema.\n\n".to_string();
+        msg += "You will need to run -p (or -a) against the required version, \nto process the data and identify the issues.";
+        return Err(AppError::CsErr(CustomError::new(&msg)));
+    }
+
+    let r = export_rel_issues::generate_rel_issues(output_folder, pool).await;
+    match r {
+        Ok(p) => {
+            info!("Relationship issues written to '{}'", p.display()); 
+            Ok(())
+        },
+        Err(e) => {
+            error!("An error occured while writing out the relationship issues: {}", e);
+            Err(e)
+        },
+    }
+}
+
+
+fn get_vcode(data_version: &Option<DataVersion>) -> String {
+    data_version.as_ref().map(|v| v.code()).unwrap_or_default()
+}
+
+
 async fn check_data_version_present_in_summ_data(data_version: &String, pool: &Pool<Postgres>)-> Result<(), AppError> {
     
     let sql = r#"SELECT EXISTS(select vcode from smm.version_summaries where vcode = '"#.to_string() + &data_version + r#"')"#;
@@ -0,0 +1,50 @@
+use sqlx::{Pool, Postgres};
+use crate::AppError;
+
+// The src tables copied into each snapshot. The hst versions have the same columns,
+// preceded by the version code, and are partitioned by that code.
+
+pub const SNAPSHOT_TABLES: [&str; 9] = ["core_data", "admin_data", "names", "type", "locations",
+                                         "external_ids", "links", "relationships", "domains"];
+
+pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // Unlike the ror and src tables the hst tables are never dropped - they are
+    // created when the first snapshot is stored, using the src tables as templates.
+
+    let mut sql = r#"SET client_min_messages TO WARNING;
+    create schema if not exists hst;
+
+    create table if not exists hst.snapshots
+    (
+          vcode             varchar     not null primary key
+        , major             int         not null
+        , minor             int         not null
+        , patch             int         not null
+        , vdate             date        not null
+        , created_at        timestamp   not null default now()
+    );
+    "#.to_string();
+
+    for t in SNAPSHOT_TABLES {
+        sql += &format!(r#"
+    create table if not exists hst.{t}
+    (
+          vcode             varchar     not null
+        , like src.{t}
+    ) partition by list (vcode);
+    create index if not exists {t}_idx on hst.{t}(vcode, id);
+    "#);
+    }
+
+    // Snapshot tables created before v1 data was held without admin dates are brought into line.
+
+    sql += r#"
+    alter table hst.admin_data alter column created drop not null, alter column cr_schema drop not null,
+        alter column last_modified drop not null, alter column lm_schema drop not null;
+    "#;
+
+    sql += "SET client_min_messages TO NOTICE;";
+    sqlx::raw_sql(&sql).execute(pool).await?;
+    Ok(())
+}
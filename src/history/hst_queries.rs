@@ -0,0 +1,105 @@
+use sqlx::{Pool, Postgres};
+use crate::AppError;
+use crate::error_defs::CustomError;
+use crate::setup::data_version::DataVersion;
+use crate::ror_id::RorId;
+
+// The organisation is identified by a RorId, which may be parsed from the full 
+// ror id (https://ror.org/0abcdef12) or from the final 9 characters.
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct OrgAsOf {
+    pub vcode: String,
+    pub id: String,
+    pub ror_full_id: String,
+    pub ror_name: String,
+    pub status: Option<String>,
+    pub established: Option<i32>,
+    pub location: Option<String>,
+    pub country_code: Option<String>,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct NameAsOf {
+    pub value: String,
+    pub name_type: Option<String>,
+    pub is_ror_name: bool,
+    pub lang_code: Option<String>,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct RelationshipAsOf {
+    pub rel_type: Option<String>,
+    pub related_id: String,
+    pub related_name: String,
+}
+
+
+pub async fn get_snapshot_versions(pool: &Pool<Postgres>) -> Result<Vec<DataVersion>, AppError> {
+    let sql = r#"select vcode from hst.snapshots order by major, minor, patch, vcode"#;
+    let vcodes: Vec<String> = sqlx::query_scalar(sql).fetch_all(pool).await?;
+    Ok(vcodes.iter().filter_map(|v| DataVersion::parse(v)).collect())
+}
+
+
+pub async fn get_org_as_of(ror_id: &RorId, dv: &DataVersion, pool: &Pool<Postgres>) -> Result<Option<OrgAsOf>, AppError> {
+
+    // Returns None if the organisation was not in the data of that version.
+
+    let vcode = check_snapshot_exists(dv, pool).await?;
+    let sql = r#"select c.vcode, c.id, c.ror_full_id, c.ror_name, s.name as status, c.established,
+                 c.location, c.country_code
+                 from hst.core_data c
+                 left join lup.ror_status_types s on c.status = s.id
+                 where c.vcode = $1 and c.id = $2"#;
+    let org: Option<OrgAsOf> = sqlx::query_as(sql).bind(&vcode).bind(ror_id.as_str())
+                               .fetch_optional(pool).await?;
+    Ok(org)
+}
+
+
+pub async fn get_names_as_of(ror_id: &RorId, dv: &DataVersion, pool: &Pool<Postgres>) -> Result<Vec<NameAsOf>, AppError> {
+
+    let vcode = check_snapshot_exists(dv, pool).await?;
+    let sql = r#"select n.value, t.name as name_type, n.is_ror_name, n.lang_code
+                 from hst.names n
+                 left join lup.ror_name_types t on n.name_type = t.id
+                 where n.vcode = $1 and n.id = $2
+                 order by n.is_ror_name desc, n.name_type, n.value"#;
+    let names: Vec<NameAsOf> = sqlx::query_as(sql).bind(&vcode).bind(ror_id.as_str())
+                               .fetch_all(pool).await?;
+    Ok(names)
+}
+
+
+pub async fn get_relationships_as_of(ror_id: &RorId, dv: &DataVersion, pool: &Pool<Postgres>) -> Result<Vec<RelationshipAsOf>, AppError> {
+
+    let vcode = check_snapshot_exists(dv, pool).await?;
+    let sql = r#"select t.name as rel_type, r.related_id, r.related_name
+                 from hst.relationships r
+                 left join lup.ror_org_rels t on r.rel_type = t.id
+                 where r.vcode = $1 and r.id = $2
+                 order by r.rel_type, r.related_id"#;
+    let rels: Vec<RelationshipAsOf> = sqlx::query_as(sql).bind(&vcode).bind(ror_id.as_str())
+                                      .fetch_all(pool).await?;
+    Ok(rels)
+}
+
+
+async fn check_snapshot_exists(dv: &DataVersion, pool: &Pool<Postgres>) -> Result<String, AppError> {
+
+    let vcode = dv.code();
+    let sql = r#"select to_regclass('hst.snapshots') is not null"#;
+    let mut exists: bool = sqlx::query_scalar(sql).fetch_one(pool).await?;
+    if exists {
+        let sql = r#"select exists(select 1 from hst.snapshots where vcode = $1)"#;
+        exists = sqlx::query_scalar(sql).bind(&vcode).fetch_one(pool).await?;
+    }
+    if !exists {
+        let msg = format!("No snapshot of the data is held for version {}. Snapshots are only stored if \
+                           'snapshot_retention' is set, and may have been removed under the retention policy.", vcode);
+        return Err(AppError::CsErr(CustomError::new(&msg)));
+    }
+    Ok(vcode)
+}
+
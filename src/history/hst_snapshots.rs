@@ -0,0 +1,123 @@
+use sqlx::{Pool, Postgres, Transaction};
+use chrono::NaiveDate;
+use crate::AppError;
+use crate::error_defs::CustomError;
+use crate::setup::SnapshotRetention;
+use crate::setup::data_version::DataVersion;
+use super::hst_create_tables::SNAPSHOT_TABLES;
+
+pub async fn store_snapshot(pool: &Pool<Postgres>) -> Result<String, AppError> {
+
+    // The version is that of the data currently in the src tables. Any existing
+    // snapshot of the same version is replaced. Returns the version code.
+
+    let sql = r#"SELECT version, data_date from src.version_details;"#;
+    let (vcode, vdate_as_string): (String, String) = sqlx::query_as(sql).fetch_one(pool).await?;
+    let dv = match DataVersion::parse(&vcode) {
+        Some(dv) => dv,
+        None => {
+            let msg = format!("Version '{}' is not a valid version code - no snapshot can be stored", vcode);
+            return Err(AppError::CsErr(CustomError::new(&msg)));
+        },
+    };
+    let vdate = match NaiveDate::parse_from_str(&vdate_as_string, "%Y-%m-%d") {
+        Ok(d) => d,
+        Err(_) => {
+            let msg = format!("Date '{}' of version {} is not a valid date - no snapshot can be stored", vdate_as_string, vcode);
+            return Err(AppError::CsErr(CustomError::new(&msg)));
+        },
+    };
+
+    let mut tx = pool.begin().await?;
+    for t in SNAPSHOT_TABLES {
+        let part = partition_name(t, &vcode);
+        let sql = format!(r#"drop table if exists hst.{part};
+                    create table hst.{part} partition of hst.{t} for values in ('{vcode}');"#);
+        sqlx::raw_sql(&sql).execute(&mut *tx).await?;
+
+        // Only the columns present in both tables are copied, in case the
+        // src table has changed since the hst table was created.
+
+        let cols = get_shared_columns(t, &mut tx).await?.join(", ");
+        let sql = format!(r#"insert into hst.{t} (vcode, {cols}) select '{vcode}', {cols} from src.{t};"#);
+        sqlx::raw_sql(&sql).execute(&mut *tx).await?;
+    }
+
+    let sql = r#"insert into hst.snapshots (vcode, major, minor, patch, vdate)
+                 values ($1, $2, $3, $4, $5)
+                 on conflict (vcode) do update set vdate = excluded.vdate, created_at = now()"#;
+    sqlx::query(sql).bind(&vcode).bind(dv.major as i32).bind(dv.minor as i32)
+    .bind(dv.patch as i32).bind(vdate)
+    .execute(&mut *tx).await?;
+
+    tx.commit().await?;
+    Ok(vcode)
+}
+
+
+pub async fn apply_retention(retention: SnapshotRetention, pool: &Pool<Postgres>) -> Result<Vec<String>, AppError> {
+
+    // Removes the snapshots of all but the latest n versions, in version order.
+    // Returns the codes of the versions removed.
+
+    let keep = match retention {
+        SnapshotRetention::Latest(n) => n as i64,
+        _ => return Ok(Vec::new()),
+    };
+
+    let sql = r#"select vcode from hst.snapshots
+                 order by major desc, minor desc, patch desc, vcode desc
+                 offset $1"#;
+    let to_remove: Vec<String> = sqlx::query_scalar(sql).bind(keep).fetch_all(pool).await?;
+
+    for vcode in to_remove.iter() {
+        let mut tx = pool.begin().await?;
+        for t in SNAPSHOT_TABLES {
+            let sql = format!("drop table if exists hst.{};", partition_name(t, vcode));
+            sqlx::raw_sql(&sql).execute(&mut *tx).await?;
+        }
+        sqlx::query("delete from hst.snapshots where vcode = $1")
+        .bind(vcode).execute(&mut *tx).await?;
+        tx.commit().await?;
+    }
+    Ok(to_remove)
+}
+
+
+async fn get_shared_columns(table_name: &str, tx: &mut Transaction<'_, Postgres>) -> Result<Vec<String>, AppError> {
+    let sql = r#"select h.column_name::varchar from information_schema.columns h
+                 inner join information_schema.columns s
+                 on h.column_name = s.column_name
+                 and s.table_schema = 'src' and s.table_name = h.table_name
+                 where h.table_schema = 'hst' and h.table_name = $1
+                 and h.column_name <> 'vcode'
+                 order by h.ordinal_position"#;
+    let cols: Vec<String> = sqlx::query_scalar(sql).bind(table_name).fetch_all(&mut **tx).await?;
+    Ok(cols)
+}
+
+
+fn partition_name(table_name: &str, vcode: &str) -> String {
+
+    // e.g. names_v1_55_1 for the names of v1.55.1
+
+    let suffix: String = vcode.to_lowercase().chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}_{}", table_name, suffix)
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_partition_names() {
+        assert_eq!(partition_name("names", "v1.55"), "names_v1_55");
+        assert_eq!(partition_name("core_data", "v1.45.2"), "core_data_v1_45_2");
+        assert_eq!(partition_name("type", "v99"), "type_v99");
+    }
+}
@@ -0,0 +1,58 @@
+// The history module. Referenced in main by 'mod history'.
+// Optionally keeps a snapshot of the src data of each version in the hst schema, so that
+// the data of an organisation in an earlier version can be retrieved. The ror and src
+// schemas are rebuilt on every import, but the hst tables are only added to (and trimmed
+// according to the retention policy). Each hst table is partitioned by version code,
+// with one partition per version, so that removing a version simply drops its partitions.
+// The public query functions return an organisation's data 'as of' a given version.
+
+mod hst_create_tables;
+mod hst_snapshots;
+mod hst_queries;
+
+use log::{info, error};
+use sqlx::{Pool, Postgres};
+use crate::AppError;
+use crate::setup::SnapshotRetention;
+
+pub use hst_queries::{OrgAsOf, NameAsOf, RelationshipAsOf,
+                      get_org_as_of, get_names_as_of, get_relationships_as_of, get_snapshot_versions};
+
+pub async fn store_snapshot(retention: SnapshotRetention, pool : &Pool<Postgres>) -> Result<(), AppError>
+{
+    // Copy the current src data into the hst tables, as a snapshot of this version,
+    // and then remove any snapshots no longer required by the retention policy.
+
+    if retention == SnapshotRetention::Off {
+        return Ok(())
+    }
+
+    match hst_create_tables::create_tables(pool).await {
+        Ok(()) => {},
+        Err(e) => {
+            error!("An error occured while creating the hst schema tables: {}", e);
+            return Err(e)
+            },
+    };
+
+    match hst_snapshots::store_snapshot(pool).await {
+        Ok(vcode) => info!("Snapshot of the src data stored for {}", vcode),
+        Err(e) => {
+            error!("An error occured while storing the snapshot of the src data: {}", e);
+            return Err(e)
+            },
+    };
+
+    match hst_snapshots::apply_retention(retention, pool).await {
+        Ok(removed) => {
+            for vcode in removed {
+                info!("Snapshot for {} removed, under the retention policy", vcode);
+            }
+        },
+        Err(e) => {
+            error!("An error occured while removing older snapshots: {}", e);
+            return Err(e)
+            },
+    };
+    Ok(())
+}
@@ -5,16 +5,39 @@
 mod ror_json_models;
 mod ror_data_vectors;
 mod ror_create_tables;
+mod ror_json_reader;
+mod ror_source_file;
+mod ror_record_validator;
+mod ror_rejects;
+mod ror_copy_loader;
+mod ror_v1_models;
+mod ror_csv_reader;
+mod ror_import_history;
 
-use log::{info, error};
+use log::{info, warn, error};
 use std::path::PathBuf;
-use std::fs;
-use sqlx::{Pool, Postgres};
+use std::fs::File;
+use std::io::Read;
+use std::collections::BTreeMap;
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+use sqlx::{Pool, Postgres, Transaction};
+use serde::Deserialize;
 use crate::AppError;
+use crate::error_defs::CustomError;
+use crate::ror_id::RorId;
+use crate::setup::{log_helper, InitParams};
 use chrono::NaiveDate;
 
 use ror_json_models::RorRecord;
-use ror_data_vectors::{CoreDataVecs, RequiredDataVecs, NonRequiredDataVecs, extract_id_from};
+use ror_data_vectors::{CoreDataVecs, RequiredDataVecs, NonRequiredDataVecs, InvalidId};
+use ror_json_reader::{RorJsonReader, RecordSource};
+use ror_csv_reader::RorCsvReader;
+use ror_source_file::{SourceType, SourceFormat, SourceChecksum};
+use ror_import_history::PriorImport;
+use ror_rejects::RejectedRecords;
+use ror_copy_loader::{CopySpools, LoadConfig, TableLoadStats};
+use ror_v1_models::{SchemaVersion, V1RorRecord};
 
 pub async fn create_ror_tables(pool : &Pool<Postgres>) -> Result<(), AppError>
 {
@@ -28,109 +51,423 @@ pub async fn create_ror_tables(pool : &Pool<Postgres>) -> Result<(), AppError>
     Ok(())
 }
 
-pub async fn import_data(data_folder : &PathBuf, source_file_name: &String, 
-                        data_version: &String, data_date: &String, 
-                        pool : &Pool<Postgres>) -> Result<(), AppError>
+pub fn get_source_checksum(params: &InitParams) -> Result<SourceChecksum, AppError>
 {
-    // Record data version, date and elapsed days in single record table.
+    let source_file_path: PathBuf = [&params.data_folder, &PathBuf::from(&params.source_file_name)].iter().collect();
+    match ror_source_file::get_file_checksum(&source_file_path) {
+        Ok(c) => {
+            info!("Source file SHA-256: {} ({} bytes)", c.sha256, c.size);
+            Ok(c)
+        },
+        Err(e) => {
+            error!("An error occured while reading the source file to obtain its checksum: {}", e);
+            Err(e)
+        },
+    }
+}
+
+
+pub async fn import_required(params: &InitParams, checksum: &SourceChecksum, pool : &Pool<Postgres>) -> Result<bool, AppError>
+{
+    // Checks the source file against any file previously imported for the same version.
+    // Returns false if the same file has already been imported, its data is still in the 
+    // ror tables, and the import is not forced.
+
+    let vcode = get_vcode(params)?;
+    ror_import_history::ensure_history_table(pool).await?;
+    match ror_import_history::check_prior_import(&vcode, checksum, pool).await? {
+        PriorImport::NotFound => Ok(true),
+        PriorImport::SameFile => {
+            if params.flags.force_import {
+                info!("This file has already been imported as {}, but is being re-imported (--force)", vcode);
+                Ok(true)
+            }
+            else if ror_import_history::ror_tables_hold_version(&vcode, pool).await? {
+                info!("This file has already been imported as {} - the import is skipped", vcode);
+                info!("Use --force to import the file again");
+                Ok(false)
+            }
+            else {
+                info!("This file has already been imported as {}, but the ror tables now hold other data - it is imported again", vcode);
+                Ok(true)
+            }
+        },
+        PriorImport::DifferentFile { source_file, sha256 } => {
+            let mut msg = format!("\n\nA different file ({}, SHA-256 {}) has already been imported as {}.\n\n",
+                                  source_file, sha256, vcode);
+            msg += "Check the version of the source file. If the earlier import should be replaced, \ndelete the record for this version from smm.imported_files and re-run the import.";
+            Err(AppError::CsErr(CustomError::new(&msg)))
+        },
+    }
+}
+
+
+pub async fn remove_import_record(vcode: &str, pool : &Pool<Postgres>) -> Result<(), AppError>
+{
+    ror_import_history::remove_import_record(vcode, pool).await
+}
+
+
+fn get_vcode(params: &InitParams) -> Result<String, AppError>
+{
+    match &params.data_version {
+        Some(dv) => Ok(dv.code()),
+        None => {
+            let msg = "A data version is required to import the data";
+            Err(AppError::CsErr(CustomError::new(msg)))
+        },
+    }
+}
+
+
+pub async fn import_data(params: &InitParams, checksum: &SourceChecksum, pool : &Pool<Postgres>) -> Result<(), AppError>
+{
+    // The whole import is carried out within a single transaction. If any part 
+    // of it fails the transaction is rolled back, leaving the ror tables empty,
+    // rather than with an incomplete version of the data.
+
+    // Unless the import is 'strict', invalid records are set aside rather than 
+    // stopping the import - see ror_record_validator and ror_rejects.
+
+    // The table data is bulk loaded using COPY - see ror_copy_loader.
+
+    let mut rejects = RejectedRecords::new(log_helper::get_rejects_file_path(&params.log_folder, 
+                                                                   &params.source_file_name));
+    let load_config = LoadConfig { 
+        batch_size: params.import_batch_size, 
+        concurrency: params.import_concurrency,
+    };
+
+    let mut tx = pool.begin().await?;
+    match import_within_transaction(params, checksum, &mut rejects, &load_config, &mut tx, pool).await {
+        Ok(()) => {
+            tx.commit().await?;
+            Ok(())
+        },
+        Err(e) => {
+            error!("An error occured during the import: {}", e);
+            error!("The import transaction has been rolled back - no data has been stored in the ror tables");
+            tx.rollback().await?;
+            if load_config.concurrency > 1 {
+                ror_copy_loader::drop_staging_tables(pool).await?;
+            }
+            Err(e)
+        },
+    }
+}
+
+
+async fn import_within_transaction(params: &InitParams, checksum: &SourceChecksum, rejects: &mut RejectedRecords, 
+                        load_config: &LoadConfig, tx: &mut Transaction<'_, Postgres>, pool : &Pool<Postgres>) -> Result<(), AppError>
+{
+    let data_date = &params.data_date;
+    let source_file_name = &params.source_file_name;
+    let strict = params.flags.strict_import;
+
+    // Record data version, date, elapsed days and source file details in single record table.
     
+    let vcode = get_vcode(params)?;
     let end_of_period = NaiveDate::parse_from_str(data_date, "%Y-%m-%d").unwrap();
-    let start_of_period = NaiveDate::parse_from_str("2024-04-29", "%Y-%m-%d").unwrap();
-    let duration = end_of_period - start_of_period;
+    let duration = end_of_period - params.baseline_date;
  
-    let sql = r#"INSERT into ror.version_details (version, data_date, data_days)
-                    values ($1, $2, $3);"#;
-    sqlx::query(&sql).bind(data_version).bind(data_date).bind(duration.num_days())
-    .execute(pool).await?;
+    let sql = r#"INSERT into ror.version_details (version, data_date, data_days, 
+                    source_file, file_sha256, file_size)
+                    values ($1, $2, $3, $4, $5, $6);"#;
+    sqlx::query(sql).bind(&vcode).bind(data_date).bind(duration.num_days())
+    .bind(source_file_name).bind(&checksum.sha256).bind(checksum.size as i64)
+    .execute(&mut **tx).await?;
 
-    // Import data into matching tables. First obtain the raw data as text
-    // This also checks the file exists...by opening it and checking no error
+    // Open the source file. This may be the data (json or csv) file itself, a gzipped version 
+    // of that file, or the zip archive downloaded from Zenodo, in which case the v2 data 
+    // file is located within it. In each case the records are then streamed from 
+    // the (decompressed) data, one at a time, so that the whole file is never held in memory.
+    // Progress is reported in terms of the bytes read, as a proportion of the file size.
 
-    let source_file_path: PathBuf = [data_folder, &PathBuf::from(source_file_name)].iter().collect();
-    let data: String = match fs::read_to_string(source_file_path)
+    let source_file_path: PathBuf = [&params.data_folder, &PathBuf::from(source_file_name)].iter().collect();
+    let mut source_file = match File::open(&source_file_path)
     {
-        Ok(d) => {
-            info!("Got the data from the file");
-            d
+        Ok(f) => {
+            info!("Opened the source file for reading");
+            f
         }, 
         Err(e) => {
-            error!("An error occured while opening or reading from the source file: {}", e);
+            error!("An error occured while opening the source file: {}", e);
             return Err(AppError::IoErr(e))
             },
     };
+    let file_size = source_file.metadata()?.len();
+    let batch_size = load_config.batch_size;
+    let format = ror_source_file::get_source_format(source_file_name, &params.source_format);
+    if format == SourceFormat::Csv {
+        info!("Source data is being read as csv");
+    }
 
-    // Parse into an internal JSON structure
-
-    let res:Vec<RorRecord> = match serde_json::from_str(&data)
-    {
-        Ok(r) => {
-            info!("Parsed the data into ROR json objects");
-            r
-        }, 
-        Err(e) => {
-            error!("An error occured while attempting tp parse the source data into json: {}", e);
-            return Err(AppError::SdErr(e))
-            },
+    let spools = match ror_source_file::get_source_type(source_file_name) {
+        SourceType::Plain => {
+            let reader = get_record_source(source_file, format);
+            import_records(reader, file_size, batch_size, strict, rejects, tx).await?
+        },
+        SourceType::Gzip => {
+            let data_size = ror_source_file::get_gz_uncompressed_size(&mut source_file)?;
+            info!("Decompressing gzipped data as it is read");
+            let reader = get_record_source(GzDecoder::new(source_file), format);
+            import_records(reader, data_size, batch_size, strict, rejects, tx).await?
+        },
+        SourceType::Zip => {
+            let mut archive = match ZipArchive::new(source_file) {
+                Ok(a) => a,
+                Err(e) => {
+                    error!("An error occured while opening the source file as a zip archive: {}", e);
+                    return Err(AppError::ZpErr(e))
+                },
+            };
+            let entry_index = ror_source_file::find_data_entry(&mut archive, format)?;
+            let entry = archive.by_index(entry_index)?;
+            let entry_size = entry.size();  // uncompressed size
+            info!("Reading {} from within the zip archive", entry.name());
+            let reader = get_record_source(entry, format);
+            import_records(reader, entry_size, batch_size, strict, rejects, tx).await?
+        },
     };
-    
-    info!("{} records found", res.len());
 
+    // The record count includes any rejected records. It is stored with the version details,
+    // and, with the file's checksum, in the permanent record of imported files.
+
+    let record_count = spools.core_data.row_count + rejects.count as u64;
+    sqlx::query("UPDATE ror.version_details set record_count = $1")
+    .bind(record_count as i32).execute(&mut **tx).await?;
+    ror_import_history::record_import(&vcode, source_file_name, checksum, record_count, tx).await?;
+
+    // Load the spooled table data, and record the time taken for each table.
+
+    info!("Loading the ror tables");
+    let load_stats = ror_copy_loader::load_tables(spools, load_config, tx, pool).await?;
+    store_load_stats(&load_stats, tx).await?;
+
+    // The csv data does not include the names of related organisations, so these 
+    // are added from the ror_display names of those organisations.
+
+    if format == SourceFormat::Csv {
+        add_related_labels(tx).await?;
+    }
+    Ok(())
+}
+
+
+fn get_record_source<'a, R: Read + 'a>(source: R, format: SourceFormat) -> Box<dyn RecordSource + 'a> {
+    match format {
+        SourceFormat::Json => Box::new(RorJsonReader::new(source)),
+        SourceFormat::Csv => Box::new(RorCsvReader::new(source)),
+    }
+}
+
+
+async fn add_related_labels(tx: &mut Transaction<'_, Postgres>) -> Result<(), AppError>
+{
+    let sql = r#"UPDATE ror.relationships r
+                 SET related_label = n.value
+                 FROM ror.names n
+                 WHERE r.related_id = n.id
+                 AND n.is_ror_name = true
+                 AND n.name_type = 'label'
+                 AND r.related_label = '';"#;
+    let res = sqlx::raw_sql(sql).execute(&mut **tx).await?;
+    info!("{} related organisation labels added from the names data", res.rows_affected());
+    Ok(())
+}
+
+
+async fn store_extra_fields(counts: &BTreeMap<String, usize>, tx: &mut Transaction<'_, Postgres>) -> Result<(), AppError>
+{
+    // Any fields found in the source that are not part of the known schema are 
+    // listed, with the number of times each occurs. Their values are in the 
+    // 'extras' column of the relevant ror table.
+
+    for (field_path, occurrences) in counts {
+        warn!("Field not in the known ror schema found: {} ({} occurrences)", field_path, occurrences);
+        sqlx::query(r#"INSERT into ror.extra_fields (field_path, occurrences)
+                    values ($1, $2);"#)
+        .bind(field_path).bind(*occurrences as i32)
+        .execute(&mut **tx).await?;
+    }
+    Ok(())
+}
+
+
+async fn store_invalid_ids(invalid_ids: &[InvalidId], tx: &mut Transaction<'_, Postgres>) -> Result<(), AppError>
+{
+    // Ids that fail the ror id checks are listed. An invalid record id also causes the 
+    // record to be rejected, whilst a relationship with an invalid related id is dropped.
+
+    if invalid_ids.is_empty() {
+        return Ok(())
+    }
+    let ids: Vec<Option<&str>> = invalid_ids.iter().map(|v| v.id.as_deref()).collect();
+    let fields: Vec<&str> = invalid_ids.iter().map(|v| v.field.as_str()).collect();
+    let values: Vec<&str> = invalid_ids.iter().map(|v| v.value.as_str()).collect();
+    let reasons: Vec<&str> = invalid_ids.iter().map(|v| v.reason.as_str()).collect();
+
+    sqlx::query(r#"INSERT into ror.invalid_ids (id, field, value, reason)
+                select * from unnest($1::varchar[], $2::varchar[], $3::varchar[], $4::varchar[]);"#)
+    .bind(&ids).bind(&fields).bind(&values).bind(&reasons)
+    .execute(&mut **tx).await?;
+
+    warn!("{} invalid ror ids found - details are in ror.invalid_ids", invalid_ids.len());
+    Ok(())
+}
+
+
+async fn store_load_stats(load_stats: &Vec<TableLoadStats>, tx: &mut Transaction<'_, Postgres>) -> Result<(), AppError>
+{
+    for s in load_stats {
+        sqlx::query(r#"INSERT into ror.load_stats (table_name, row_count, load_secs)
+                    values ($1, $2, $3);"#)
+        .bind(s.table_name).bind(s.row_count as i64).bind(s.load_secs)
+        .execute(&mut **tx).await?;
+    }
+    Ok(())
+}
+
+
+async fn import_records(mut reader: Box<dyn RecordSource + '_>, file_size: u64, vector_size: usize, 
+                                 strict: bool, rejects: &mut RejectedRecords,
+                                 tx: &mut Transaction<'_, Postgres>) -> Result<CopySpools, AppError>
+{
     // Set up vector variables.
     // Vectors are grouped into structs for ease of reference.
 
-    let vector_size = 250;
+    let mut spools = CopySpools::new()?;
     let mut cdv: CoreDataVecs = CoreDataVecs::new(vector_size);
     let mut rdv: RequiredDataVecs = RequiredDataVecs::new(vector_size);
     let mut ndv: NonRequiredDataVecs = NonRequiredDataVecs::new(vector_size);
 
-    // Run through each record and store contents in relevant vectors.
-    // After every (vector_size) records store vector contents to database
-    // and clear vectors, but continue looping through records.
+    // Run through each record, check it, and store contents in relevant vectors.
+    // After every (vector_size) valid records write vector contents to the table 
+    // spool files and clear vectors, but continue reading records.
     
     let mut n = 0;
-    for (i, r) in res.iter().enumerate() {
+    let mut i = 0;
+    let mut record_num = 0;
+    let mut extra_field_counts: BTreeMap<String, usize> = BTreeMap::new();
+    let mut invalid_ids: Vec<InvalidId> = Vec::new();
+    let mut schema_version: Option<SchemaVersion> = None;
+    while let Some(res) = reader.next() {
     
-        let db_id = extract_id_from(&r.id).to_string();
+        record_num += 1;
+        let raw = match res {
+            Ok(v) => v,
+            Err(e) => {
+                error!("An error occured while attempting to read record {} of the source data: {}", record_num, e);
+                return Err(e)
+            },
+        };
 
-        cdv.add_core_data(r, &db_id); 
-        rdv.add_required_data(r, &db_id); 
-        ndv.add_non_required_data(r, &db_id); 
-        
-        //if i > 705 { break;  }
+        // The schema version (v1 or v2) is determined from the first record. 
+        // v1 records are converted to the v2 structure as they are read.
+
+        let version = *schema_version.get_or_insert_with(|| {
+            let v = ror_v1_models::detect_schema_version(&raw);
+            info!("Source data identified as being in ror schema {}", if v == SchemaVersion::V1 {"v1"} else {"v2"});
+            v
+        });
+        let rec = match version {
+            SchemaVersion::V2 => RorRecord::deserialize(&raw),
+            SchemaVersion::V1 => V1RorRecord::deserialize(&raw).map(|r| r.into_v2()),
+        };
+
+        let reasons = match rec {
+            Ok(mut r) => {
+                let reasons = ror_record_validator::get_invalid_reasons(&r);
+
+                // The id is held in its full url form, whichever form it has in the source. 
+                // An invalid id is listed, as well as being one of the reasons for the rejection.
+
+                match RorId::parse(&r.id) {
+                    Ok(ror_id) if reasons.is_empty() => {
+                        r.id = ror_id.url();
+                        r.count_extra_fields(&mut extra_field_counts);
+                        let db_id = ror_id.to_string();
+                        cdv.add_core_data(&r, &db_id); 
+                        rdv.add_required_data(&r, &db_id); 
+                        ndv.add_non_required_data(&r, &db_id); 
+                    },
+                    Ok(_) => {},
+                    Err(e) => invalid_ids.push(InvalidId::new(None, "id", &r.id, &e)),
+                }
+                reasons
+            },
+            Err(e) => vec![format!("record could not be read as a ror record ({})", e)],
+        };
+
+        if !reasons.is_empty() {
+            if strict {
+                let msg = format!("record {} of the source data is invalid: {}", record_num, reasons.join("; "));
+                error!("{} (strict import)", msg);
+                return Err(AppError::CsErr(CustomError::new(&msg)))
+            }
+            rejects.store(record_num, &raw, &reasons, tx).await?;
+            continue;
+        }
 
-        if (i + 1) % vector_size == 0 {  
+        i += 1;
+
+        if i % vector_size == 0 {  
             
+            // Report progress each time another 5000 records have been processed.
+
             n += vector_size;
-            if n % 5000 == 0 { 
-                info!("{} records processed", n);
+            if n / 5000 > (n - vector_size) / 5000 { 
+                info!("{} records processed, {} of {} bytes read ({:.1}%)", n, reader.bytes_read(), 
+                                file_size, get_pc_of_bytes(reader.bytes_read(), file_size));
             }
             
-            // store records to DB and clear vectors
-            cdv.store_data(&pool).await;
+            // write records to spools and clear vectors
+            cdv.write_copy_rows(&mut spools)?;
             cdv = CoreDataVecs::new(vector_size);
-            rdv.store_data(&pool).await;
+            rdv.write_copy_rows(&mut spools)?;
             rdv = RequiredDataVecs::new(vector_size);
-            ndv.store_data(&pool).await;
+            ndv.write_copy_rows(&mut spools)?;
+            invalid_ids.append(&mut ndv.invalid_ids);
             ndv = NonRequiredDataVecs::new(vector_size);
         }
     }
     
-    //store any residual vector contents
+    //write any residual vector contents
 
-    cdv.store_data(&pool).await;
-    rdv.store_data(&pool).await;
-    ndv.store_data(&pool).await;
+    cdv.write_copy_rows(&mut spools)?;
+    rdv.write_copy_rows(&mut spools)?;
+    ndv.write_copy_rows(&mut spools)?;
+    invalid_ids.append(&mut ndv.invalid_ids);
 
     info!("Total records processed: {}", n + cdv.db_ids.len());
 
-    Ok(())
+    store_extra_fields(&extra_field_counts, tx).await?;
+    store_invalid_ids(&invalid_ids, tx).await?;
+
+    rejects.finish()?;
+    if rejects.count > 0 {
+        warn!("{} invalid records were rejected and have not been imported", rejects.count);
+        warn!("Details are in ror.rejected_records and in {}", rejects.file_path().display());
+    }
+
+    Ok(spools)
+
+}
 
+
+fn get_pc_of_bytes(bytes_read: u64, file_size: u64) -> f64 {
+    if file_size == 0 
+    { 100.0 }
+    else {
+        (bytes_read as f64) * 100.0 / (file_size as f64)
+    }
 }
 
 
 pub async fn summarise_import(pool : &Pool<Postgres>) -> Result<(), AppError>
 {
-    // Goes through each table and get total record number.
+    // Goes through each table and get total record number, 
+    // and (for the main data tables) the rate at which they were loaded.
 
     info!("");
     info!("************************************");
@@ -147,6 +484,10 @@ pub async fn summarise_import(pool : &Pool<Postgres>) -> Result<(), AppError>
     write_record_num("type", pool).await?;
     write_record_num("relationships", pool).await?;
     write_record_num("domains", pool).await?;
+    write_record_num("rejected_records", pool).await?;
+    write_record_num("invalid_ids", pool).await?;
+    write_invalid_ids(pool).await?;
+    write_extra_fields(pool).await?;
     
     info!("");
     info!("************************************");
@@ -161,10 +502,65 @@ pub async fn write_record_num (table_name: &str, pool: &Pool<Postgres>) -> Resul
     let res: i64 = sqlx::query_scalar(&sql)
     .fetch_one(pool)
     .await?;
-    info!("Total records in ror.{}: {}", table_name, res);
+
+    // Tables bulk loaded by COPY also have the load time, and so the rate, reported.
+
+    let sql = r#"SELECT row_count, load_secs FROM ror.load_stats WHERE table_name = $1"#;
+    let stats: Option<(i64, f64)> = sqlx::query_as(sql).bind(table_name)
+    .fetch_optional(pool)
+    .await?;
+    match stats {
+        Some((row_count, load_secs)) if load_secs > 0.0 => {
+            info!("Total records in ror.{}: {} (loaded in {:.2} secs, {:.0} rows per second)", 
+                            table_name, res, load_secs, row_count as f64 / load_secs);
+        },
+        _ => info!("Total records in ror.{}: {}", table_name, res),
+    }
     Ok(())
 }
   
   
   
 
+
+pub async fn write_invalid_ids (pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+
+    // Only the first 50 are listed here - all are in the table.
+
+    let sql = r#"SELECT coalesce(id, '(rejected record)'), field, value, reason FROM ror.invalid_ids 
+                 ORDER BY field, id, value LIMIT 50"#;
+    let rows: Vec<(String, String, String, String)> = sqlx::query_as(sql)
+    .fetch_all(pool)
+    .await?;
+    if !rows.is_empty() {
+        info!("");
+        info!("Invalid ror ids (record id, field, value, reason):");
+        for (id, field, value, reason) in rows.iter() {
+            info!("{}, {}: '{}' - {}", id, field, value, reason);
+        }
+        let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ror.invalid_ids").fetch_one(pool).await?;
+        if total > rows.len() as i64 {
+            info!("... and {} more", total - rows.len() as i64);
+        }
+    }
+    Ok(())
+}
+
+
+pub async fn write_extra_fields (pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    let sql = r#"SELECT field_path, occurrences FROM ror.extra_fields ORDER BY field_path"#;
+    let rows: Vec<(String, i32)> = sqlx::query_as(sql)
+    .fetch_all(pool)
+    .await?;
+    if rows.is_empty() {
+        info!("No fields outside the known ror schema were found");
+    }
+    else {
+        info!("");
+        info!("Fields outside the known ror schema (stored in 'extras' columns):");
+        for (field_path, occurrences) in rows {
+            info!("{}: {}", field_path, occurrences);
+        }
+    }
+    Ok(())
+}
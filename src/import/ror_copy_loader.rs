@@ -0,0 +1,377 @@
+/***************************************************************************
+ * Bulk loads the ror schema tables using Postgres COPY FROM STDIN. As the
+ * source is read the rows for each table are written, in COPY's text format,
+ * to a temporary 'spool' file for that table. Once the source has been read
+ * each spool file is streamed to the database, as a single COPY per table,
+ * in chunks of (batch size) rows.
+ * With a concurrency of 1 (the default) the tables are loaded one after the
+ * other, within the import transaction. With a greater concurrency up to that
+ * number of tables are loaded at the same time, each on its own connection,
+ * into an unlogged staging table (ror.<table>_load). Those loads are outside
+ * the import transaction, so once all have succeeded the staged rows are moved
+ * into the ror tables, and the staging tables dropped, within the transaction.
+ * The import therefore still commits or rolls back as a whole. If a staged
+ * load fails, or the transaction is rolled back, the staging tables are
+ * dropped.
+ ***************************************************************************/
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::time::Instant;
+use chrono::NaiveDate;
+use futures::stream::{self, StreamExt};
+use log::{info, error};
+use regex::Regex;
+use sqlx::postgres::{PgConnection, PgDatabaseError};
+use sqlx::{Pool, Postgres, Transaction};
+use crate::error_defs::{AppError, CustomError};
+
+const STAGING_SUFFIX: &str = "_load";
+
+pub struct LoadConfig {
+    pub batch_size: usize,
+    pub concurrency: usize,
+}
+
+pub struct TableLoadStats {
+    pub table_name: &'static str,
+    pub row_count: u64,
+    pub load_secs: f64,
+}
+
+
+// Values are written in the COPY text format - with tabs between columns,
+// nulls as \N, and backslashes, tabs and line breaks escaped.
+
+pub trait CopyValue {
+    fn write_copy(&self, out: &mut String);
+}
+
+impl CopyValue for String {
+    fn write_copy(&self, out: &mut String) {
+        for c in self.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '\t' => out.push_str("\\t"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                _ => out.push(c),
+            }
+        }
+    }
+}
+
+impl CopyValue for i16 {
+    fn write_copy(&self, out: &mut String) { out.push_str(&self.to_string()); }
+}
+
+impl CopyValue for i64 {
+    fn write_copy(&self, out: &mut String) { out.push_str(&self.to_string()); }
+}
+
+impl CopyValue for f64 {
+    fn write_copy(&self, out: &mut String) { out.push_str(&self.to_string()); }
+}
+
+impl CopyValue for bool {
+    fn write_copy(&self, out: &mut String) { out.push(if *self {'t'} else {'f'}); }
+}
+
+impl CopyValue for NaiveDate {
+    fn write_copy(&self, out: &mut String) { out.push_str(&self.format("%Y-%m-%d").to_string()); }
+}
+
+impl<T: CopyValue> CopyValue for Option<T> {
+    fn write_copy(&self, out: &mut String) {
+        match self {
+            Some(v) => v.write_copy(out),
+            None => out.push_str("\\N"),
+        }
+    }
+}
+
+
+pub struct TableSpool {
+    pub table_name: &'static str,
+    columns: &'static str,
+    writer: BufWriter<File>,
+    line: String,
+    pub row_count: u64,
+}
+
+impl TableSpool {
+    pub fn new(table_name: &'static str, columns: &'static str) -> Result<Self, AppError> {
+        Ok(TableSpool {
+            table_name,
+            columns,
+            writer: BufWriter::new(tempfile::tempfile()?),
+            line: String::new(),
+            row_count: 0,
+        })
+    }
+
+    pub fn add_row(&mut self, values: &[&dyn CopyValue]) -> Result<(), AppError> {
+        self.line.clear();
+        for (i, v) in values.iter().enumerate() {
+            if i > 0 {
+                self.line.push('\t');
+            }
+            v.write_copy(&mut self.line);
+        }
+        self.line.push('\n');
+        self.writer.write_all(self.line.as_bytes())?;
+        self.row_count += 1;
+        Ok(())
+    }
+}
+
+
+// One spool per ror table. The first column of every table is the ror id.
+
+pub struct CopySpools {
+    pub core_data: TableSpool,
+    pub admin_data: TableSpool,
+    pub names: TableSpool,
+    pub types: TableSpool,
+    pub locations: TableSpool,
+    pub relationships: TableSpool,
+    pub links: TableSpool,
+    pub external_ids: TableSpool,
+    pub domains: TableSpool,
+}
+
+impl CopySpools {
+    pub fn new() -> Result<Self, AppError> {
+        Ok(CopySpools {
+            core_data: TableSpool::new("core_data", "id, ror_full_id, status, established, extras")?,
+            admin_data: TableSpool::new("admin_data", "id, created, cr_schema, last_modified, lm_schema, extras")?,
+            names: TableSpool::new("names", "id, value, name_type, is_ror_name, lang, extras")?,
+            types: TableSpool::new("type", "id, org_type")?,
+            locations: TableSpool::new("locations", "id, geonames_id, name, lat, lng, continent_code, continent_name,
+                                        country_code, country_name, country_subdivision_code, country_subdivision_name, extras")?,
+            relationships: TableSpool::new("relationships", "id, rel_type, related_id, related_label, extras")?,
+            links: TableSpool::new("links", "id, link_type, value, extras")?,
+            external_ids: TableSpool::new("external_ids", "id, id_type, id_value, is_preferred, extras")?,
+            domains: TableSpool::new("domains", "id, value")?,
+        })
+    }
+
+    fn into_vec(self) -> Vec<TableSpool> {
+        vec![self.core_data, self.admin_data, self.names, self.types, self.locations,
+             self.relationships, self.links, self.external_ids, self.domains]
+    }
+
+    #[cfg(test)]
+    pub fn get_all_rows(self) -> Result<Vec<(&'static str, Vec<String>)>, AppError> {
+
+        // Returns the rows written to each spool, to allow the spooled data to be checked.
+
+        let mut all_rows = Vec::new();
+        for spool in self.into_vec() {
+            let mut file = spool.writer.into_inner().map_err(|e| e.into_error())?;
+            file.seek(SeekFrom::Start(0))?;
+            let rows = BufReader::new(file).lines().collect::<Result<Vec<String>, _>>()?;
+            all_rows.push((spool.table_name, rows));
+        }
+        Ok(all_rows)
+    }
+}
+
+
+pub async fn load_tables(spools: CopySpools, config: &LoadConfig, tx: &mut Transaction<'_, Postgres>,
+                         pool: &Pool<Postgres>) -> Result<Vec<TableLoadStats>, AppError> {
+
+    let batch_size = config.batch_size.max(1);
+    let mut stats: Vec<TableLoadStats> = Vec::new();
+
+    if config.concurrency <= 1 {
+        for spool in spools.into_vec() {
+            stats.push(copy_table(spool, "", batch_size, tx).await?);
+        }
+    }
+    else {
+        info!("Loading up to {} ror tables concurrently, via staging tables", config.concurrency);
+        let spools = spools.into_vec();
+        let tables: Vec<(&'static str, &'static str)> = spools.iter().map(|s| (s.table_name, s.columns)).collect();
+        let results: Vec<Result<TableLoadStats, AppError>> = stream::iter(spools)
+            .map(|spool| async move {
+                let mut conn = pool.acquire().await?;
+                let sql = format!(r#"DROP TABLE IF EXISTS ror.{0}{1};
+                                     CREATE UNLOGGED TABLE ror.{0}{1} (LIKE ror.{0} INCLUDING DEFAULTS);"#, 
+                                     spool.table_name, STAGING_SUFFIX);
+                sqlx::raw_sql(&sql).execute(&mut *conn).await?;
+                copy_table(spool, STAGING_SUFFIX, batch_size, &mut conn).await
+            })
+            .buffer_unordered(config.concurrency)
+            .collect().await;
+
+        for res in results {
+            match res {
+                Ok(s) => stats.push(s),
+                Err(e) => {
+                    error!("A concurrent table load failed - the staging tables are being dropped");
+                    drop_staging_tables(pool).await?;
+                    return Err(e)
+                },
+            }
+        }
+
+        // The staged rows become part of the import transaction only when moved.
+
+        for (table_name, columns) in tables.iter() {
+            let sql = format!(r#"INSERT INTO ror.{0} ({1}) SELECT {1} FROM ror.{0}{2};
+                                 DROP TABLE ror.{0}{2};"#, table_name, columns, STAGING_SUFFIX);
+            sqlx::raw_sql(&sql).execute(&mut **tx).await?;
+        }
+    }
+
+    Ok(stats)
+}
+
+
+async fn copy_table(spool: TableSpool, suffix: &str, batch_size: usize, conn: &mut PgConnection) -> Result<TableLoadStats, AppError> {
+
+    let start = Instant::now();
+    let mut file = spool.writer.into_inner().map_err(|e| e.into_error())?;
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(file);
+
+    let sql = format!("COPY ror.{}{} ({}) FROM STDIN", spool.table_name, suffix, spool.columns);
+    let mut copy_in = conn.copy_in_raw(&sql).await?;
+
+    // Each row is a single line, as any line breaks in the data have been escaped.
+
+    let mut chunk: Vec<u8> = Vec::new();
+    let mut rows_in_chunk = 0;
+    let mut send_res: Result<(), sqlx::Error> = Ok(());
+    loop {
+        let n = reader.read_until(b'\n', &mut chunk)?;
+        if n > 0 {
+            rows_in_chunk += 1;
+        }
+        if (n == 0 || rows_in_chunk == batch_size) && !chunk.is_empty() {
+            if let Err(e) = copy_in.send(chunk.as_slice()).await {
+                send_res = Err(e);
+                break;
+            }
+            chunk.clear();
+            rows_in_chunk = 0;
+        }
+        if n == 0 {
+            break;
+        }
+    }
+
+    let res = match send_res {
+        Ok(()) => copy_in.finish().await,
+        Err(e) => {
+            let _ = copy_in.abort("sending data failed").await;
+            Err(e)
+        },
+    };
+
+    match res {
+        Ok(row_count) => Ok(TableLoadStats {
+            table_name: spool.table_name,
+            row_count,
+            load_secs: start.elapsed().as_secs_f64(),
+        }),
+        Err(e) => Err(copy_error(e, reader.into_inner(), spool.table_name, batch_size)),
+    }
+}
+
+
+fn copy_error(e: sqlx::Error, mut spool_file: File, table_name: &str, batch_size: usize) -> AppError {
+
+    // Postgres reports the failing line of the COPY data, e.g. "COPY names, line 1234".
+    // If it is available the line is used to identify the batch and the ror id involved.
+
+    let line_num = e.as_database_error()
+        .and_then(|d| d.try_downcast_ref::<PgDatabaseError>())
+        .and_then(|pe| pe.r#where())
+        .and_then(get_copy_line_num);
+
+    let msg = match line_num {
+        Some(n) => {
+            let id = get_id_at_line(&mut spool_file, n).unwrap_or("unknown".to_string());
+            format!("batch {} failed to load into ror.{} ({}), at line {}, for ror id: {}",
+                    (n - 1) / batch_size + 1, table_name, e, n, id)
+        },
+        None => format!("failed to load into ror.{} ({})", table_name, e),
+    };
+    AppError::CsErr(CustomError::new(&msg))
+}
+
+
+fn get_copy_line_num(context: &str) -> Option<usize> {
+    let re = Regex::new(r"COPY [^,]+, line (\d+)").unwrap();
+    re.captures(context).and_then(|c| c[1].parse::<usize>().ok()).filter(|n| *n > 0)
+}
+
+
+fn get_id_at_line(spool_file: &mut File, line_num: usize) -> Option<String> {
+    spool_file.seek(SeekFrom::Start(0)).ok()?;
+    let line = BufReader::new(spool_file).lines().nth(line_num - 1)?.ok()?;
+    line.split('\t').next().map(|s| s.to_string())
+}
+
+
+pub async fn drop_staging_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // Also used after a rollback, as the staging tables are then restored with the
+    // rows they held when the transaction began.
+
+    let table_names = ["core_data", "admin_data", "names", "type", "locations",
+                       "relationships", "links", "external_ids", "domains"];
+    for table_name in table_names.iter() {
+        let sql = format!("DROP TABLE IF EXISTS ror.{}{};", table_name, STAGING_SUFFIX);
+        sqlx::raw_sql(&sql).execute(pool).await?;
+    }
+    Ok(())
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn check_copy_text_escaping() {
+        let mut out = String::new();
+        "a\tb\\c\nd".to_string().write_copy(&mut out);
+        assert_eq!(out, "a\\tb\\\\c\\nd");
+
+        let mut out = String::new();
+        let none: Option<String> = None;
+        none.write_copy(&mut out);
+        assert_eq!(out, "\\N");
+    }
+
+    #[test]
+    fn check_spool_rows_are_written() {
+        let mut spool = TableSpool::new("names", "id, value, name_type, is_ror_name, lang").unwrap();
+        spool.add_row(&[&"04ttjf776".to_string(), &"Test\tOrg".to_string(), &"label".to_string(),
+                        &Some(true), &None::<String>]).unwrap();
+        spool.add_row(&[&"02bfwt286".to_string(), &"Other Org".to_string(), &"alias".to_string(),
+                        &None::<bool>, &Some("en".to_string())]).unwrap();
+        assert_eq!(spool.row_count, 2);
+
+        let mut file = spool.writer.into_inner().unwrap();
+        let mut contents = String::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "04ttjf776\tTest\\tOrg\tlabel\tt\t\\N\n02bfwt286\tOther Org\talias\t\\N\ten\n");
+
+        assert_eq!(get_id_at_line(&mut file, 2), Some("02bfwt286".to_string()));
+    }
+
+    #[test]
+    fn check_copy_line_num_is_found() {
+        assert_eq!(get_copy_line_num("COPY names, line 1234, column lang: \"xx\""), Some(1234));
+        assert_eq!(get_copy_line_num("COPY core_data, line 7"), Some(7));
+        assert_eq!(get_copy_line_num("SQL statement"), None);
+    }
+}
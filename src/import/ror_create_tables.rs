@@ -12,6 +12,10 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
           version           varchar     not null
         , data_date         varchar     not null
         , data_days         int         not null
+        , source_file       varchar     null
+        , file_sha256       char(64)    null
+        , file_size         bigint      null
+        , record_count      int         null
         , import_datetime   timestamp   not null  default current_timestamp
     );
 
@@ -22,16 +26,18 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
         , ror_full_id       varchar     not null  
         , status            varchar     not null
         , established       int         null
+        , extras            jsonb       null
     );
 
     drop table if exists ror.admin_data;
     create table ror.admin_data
     (
           id                varchar     not null primary key
-        , created           date        not null
-        , cr_schema         varchar     not null
-        , last_modified     date        not null
-        , lm_schema         varchar     not null  
+        , created           date        null
+        , cr_schema         varchar     null
+        , last_modified     date        null
+        , lm_schema         varchar     null  
+        , extras            jsonb       null
     );
 
     drop table if exists ror.names;
@@ -42,6 +48,7 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
         , name_type         varchar     not null
         , is_ror_name       bool        null
         , lang              varchar     null
+        , extras            jsonb       null
     );
     create index src_names_idx on ror.names(id);
 
@@ -60,6 +67,7 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
         , country_name      varchar     null	
         , country_subdivision_code      varchar     null
         , country_subdivision_name      varchar     null	
+        , extras            jsonb       null
     );
     create index src_locations_idx on ror.locations(id);
 
@@ -70,6 +78,7 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
         , id_type           varchar     not null
         , id_value          varchar     not null
         , is_preferred      bool        null
+        , extras            jsonb       null
     );
     create index src_external_ids_idx on ror.external_ids(id);
 
@@ -79,6 +88,7 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
           id                varchar	    not null
         , link_type         varchar     not null
         , value             varchar     not null
+        , extras            jsonb       null
     );
     create index src_links_idx on ror.links(id);
 
@@ -97,6 +107,7 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
         , rel_type          varchar     not null
         , related_id        varchar     not null
         , related_label     varchar     not null
+        , extras            jsonb       null
     ); 
     create index src_relationships_idx on ror.relationships(id);
 
@@ -107,6 +118,39 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
         , value             varchar     not null
     );
     create index src_domains_idx on ror.domains(id);
+
+    drop table if exists ror.extra_fields;
+    create table ror.extra_fields
+    (
+          field_path        varchar     not null
+        , occurrences       int         not null
+    );
+
+    drop table if exists ror.load_stats;
+    create table ror.load_stats
+    (
+          table_name        varchar     not null
+        , row_count         bigint      not null
+        , load_secs         float8      not null
+    );
+
+    drop table if exists ror.rejected_records;
+    create table ror.rejected_records
+    (
+          record_num        int         not null
+        , id                varchar     null
+        , reasons           varchar     not null
+        , raw_json          jsonb       not null
+    );
+
+    drop table if exists ror.invalid_ids;
+    create table ror.invalid_ids
+    (
+          id                varchar     null
+        , field             varchar     not null
+        , value             varchar     not null
+        , reason            varchar     not null
+    );
     
     SET client_min_messages TO NOTICE;"#;
 
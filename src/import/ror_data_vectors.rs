@@ -1,18 +1,22 @@
 use chrono::NaiveDate;
-use sqlx::{Pool, Postgres};
-use crate::import::ror_json_models::RorRecord;
+use crate::import::ror_json_models::{RorRecord, extras_as_json};
+use crate::import::ror_copy_loader::CopySpools;
+use crate::error_defs::AppError;
+use crate::ror_id::{RorId, RorIdError};
 
-// vectors to hold column values, 100 at a time
+// vectors to hold column values, one batch at a time
 
 pub struct CoreDataVecs {
     pub db_ids: Vec<String>,
     pub ror_ids: Vec<String>,
     pub statuses: Vec<String>,
     pub estabs: Vec<Option<i16>>,
-    pub created_dates: Vec<NaiveDate>,
-    pub created_vs: Vec<String>,
-    pub lastmod_dates: Vec<NaiveDate>,
-    pub lastmod_vs: Vec<String>,
+    pub created_dates: Vec<Option<NaiveDate>>,
+    pub created_vs: Vec<Option<String>>,
+    pub lastmod_dates: Vec<Option<NaiveDate>>,
+    pub lastmod_vs: Vec<Option<String>>,
+    pub extras: Vec<Option<String>>,
+    pub admin_extras: Vec<Option<String>>,
 }
 
 impl CoreDataVecs{
@@ -26,6 +30,8 @@ impl CoreDataVecs{
             created_vs: Vec::with_capacity(vsize),
             lastmod_dates: Vec::with_capacity(vsize),
             lastmod_vs: Vec::with_capacity(vsize),
+            extras: Vec::with_capacity(vsize),
+            admin_extras: Vec::with_capacity(vsize),
         }
     }
 
@@ -37,39 +43,37 @@ impl CoreDataVecs{
         self.statuses.push(r.status.clone());
         self.estabs.push(r.established.clone());
 
-        let cr_date = NaiveDate::parse_from_str(&r.admin.created.date, "%Y-%m-%d").unwrap();
-        let lm_date = NaiveDate::parse_from_str(&r.admin.last_modified.date, "%Y-%m-%d").unwrap();
+        // Dates have been checked by the record validator, so will parse successfully.
+        // Records converted from v1 have no admin details, so these are left null.
+
+        let admin = r.admin.as_ref();
+        let get_date = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
             
-        self.created_dates.push(cr_date);
-        self.created_vs.push(r.admin.created.schema_version.clone());
-        self.lastmod_dates.push(lm_date);
-        self.lastmod_vs.push(r.admin.last_modified.schema_version.clone());
+        self.created_dates.push(admin.map(|a| get_date(&a.created.date)));
+        self.created_vs.push(admin.map(|a| a.created.schema_version.clone()));
+        self.lastmod_dates.push(admin.map(|a| get_date(&a.last_modified.date)));
+        self.lastmod_vs.push(admin.map(|a| a.last_modified.schema_version.clone()));
+
+        self.extras.push(extras_as_json(&r.extras));
+        self.admin_extras.push(admin.and_then(|a| extras_as_json(&a.all_extras())));
     }
 
 
-    pub async fn store_data(&self, pool : &Pool<Postgres>) {
+    pub fn write_copy_rows(&self, spools: &mut CopySpools) -> Result<(), AppError> {
     
         // do the core data
-        let _ = sqlx::query(r#"INSERT INTO ror.core_data (id, ror_full_id, status, established) 
-            SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::int[])"#)
-        .bind(&self.db_ids)
-        .bind(&self.ror_ids)
-        .bind(&self.statuses)
-        .bind(&self.estabs)
-        .execute(pool)
-        .await;
+        for i in 0..self.db_ids.len() {
+            spools.core_data.add_row(&[&self.db_ids[i], &self.ror_ids[i], &self.statuses[i], 
+                                       &self.estabs[i], &self.extras[i]])?;
+        }
         
         // do the admin data
-        let _ = sqlx::query(r#"INSERT INTO ror.admin_data (id, created, cr_schema, last_modified, lm_schema) 
-            SELECT * FROM UNNEST($1::text[], $2::timestamp[], $3::text[], $4::timestamp[], $5::text[])"#)
-        .bind(&self.db_ids)
-        .bind(&self.created_dates)
-        .bind(&self.created_vs)
-        .bind(&self.lastmod_dates)
-        .bind(&self.lastmod_vs)
-        .execute(pool)
-        .await;
+        for i in 0..self.db_ids.len() {
+            spools.admin_data.add_row(&[&self.db_ids[i], &self.created_dates[i], &self.created_vs[i], 
+                                        &self.lastmod_dates[i], &self.lastmod_vs[i], &self.admin_extras[i]])?;
+        }
 
+        Ok(())
     }
 
 }
@@ -81,21 +85,23 @@ pub struct RequiredDataVecs {
     pub name_types: Vec<String>,
     pub is_rors:Vec<Option<bool>>,
     pub langs: Vec<Option<String>>,
+    pub name_extras: Vec<Option<String>>,
 
     pub type_db_ids: Vec<String>,
     pub org_types: Vec<String>,
 
     pub loc_db_ids: Vec<String>,
-    pub gn_ids: Vec<i64>,
+    pub gn_ids: Vec<Option<i64>>,
     pub gn_names: Vec<String>,
-    pub lats: Vec<f64>,
-    pub lngs: Vec<f64>,
+    pub lats: Vec<Option<f64>>,
+    pub lngs: Vec<Option<f64>>,
     pub cont_codes: Vec<Option<String>>,
     pub cont_names: Vec<Option<String>>,
     pub cy_codes: Vec<String>,
     pub cy_names: Vec<String>,
     pub cy_subdiv_codes: Vec<Option<String>>,
     pub cy_subdiv_names: Vec<Option<String>>,
+    pub loc_extras: Vec<Option<String>>,
 }
 
 
@@ -107,6 +113,7 @@ impl RequiredDataVecs{
             name_types: Vec::with_capacity(vsize),
             is_rors: Vec::with_capacity(vsize),
             langs: Vec::with_capacity(vsize),
+            name_extras: Vec::with_capacity(vsize),
 
             type_db_ids:Vec::with_capacity(vsize),
             org_types: Vec::with_capacity(vsize),
@@ -122,6 +129,7 @@ impl RequiredDataVecs{
             cy_names: Vec::with_capacity(vsize),
             cy_subdiv_codes: Vec::with_capacity(vsize),
             cy_subdiv_names: Vec::with_capacity(vsize),
+            loc_extras: Vec::with_capacity(vsize),
         }
     }
 
@@ -141,6 +149,7 @@ impl RequiredDataVecs{
                         self.name_types.push("label".to_string());
                         self.is_rors.push(Some(true));
                         self.langs.push(name.lang.clone()); 
+                        self.name_extras.push(extras_as_json(&name.extras));
                     }
                     else {
                         let mut is_a_ror_name: Option<bool> = None;
@@ -156,6 +165,7 @@ impl RequiredDataVecs{
                                 self.name_types.push(name_type.clone());
                                 self.is_rors.push(is_a_ror_name);
                                 self.langs.push(name.lang.clone()); 
+                                self.name_extras.push(extras_as_json(&name.extras));
                             }
                         }
                     }
@@ -176,60 +186,44 @@ impl RequiredDataVecs{
             for loc in r.locations.iter()
             {
                 self.loc_db_ids.push(db_id.clone());
-                self.gn_ids.push(loc.geonames_id.clone());
+                self.gn_ids.push(loc.geonames_id);
                 self.gn_names.push(loc.geonames_details.name.clone());
-                self.lats.push(loc.geonames_details.lat.clone());
-                self.lngs.push(loc.geonames_details.lng.clone());
+                self.lats.push(loc.geonames_details.lat);
+                self.lngs.push(loc.geonames_details.lng);
                 self.cont_codes.push(loc.geonames_details.continent_code.clone());
                 self.cont_names.push(loc.geonames_details.continent_name.clone());
                 self.cy_codes.push(loc.geonames_details.country_code.clone());
                 self.cy_names.push(loc.geonames_details.country_name.clone());
                 self.cy_subdiv_codes.push(loc.geonames_details.country_subdivision_code.clone());
                 self.cy_subdiv_names.push(loc.geonames_details.country_subdivision_name.clone());
+                self.loc_extras.push(extras_as_json(&loc.all_extras()));
             }
         }
 
     }
 
-    pub async fn store_data(&self, pool : &Pool<Postgres>) {
+    pub fn write_copy_rows(&self, spools: &mut CopySpools) -> Result<(), AppError> {
         
         // do the name data
-        let _ = sqlx::query(r#"INSERT INTO ror.names (id, value, name_type, is_ror_name, lang) 
-        SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::bool[], $5::text[])"#)
-        .bind(&self.name_db_ids)
-        .bind(&self.names)
-        .bind(&self.name_types)
-        .bind(&self.is_rors)
-        .bind(&self.langs)
-        .execute(pool)
-        .await;
+        for i in 0..self.name_db_ids.len() {
+            spools.names.add_row(&[&self.name_db_ids[i], &self.names[i], &self.name_types[i], 
+                                   &self.is_rors[i], &self.langs[i], &self.name_extras[i]])?;
+        }
 
         // do the type data
-        let _ = sqlx::query(r#"INSERT INTO ror.type (id, org_type) 
-        SELECT * FROM UNNEST($1::text[], $2::text[])"#)
-        .bind(&self.type_db_ids)
-        .bind(&self.org_types)
-        .execute(pool)
-        .await;
+        for i in 0..self.type_db_ids.len() {
+            spools.types.add_row(&[&self.type_db_ids[i], &self.org_types[i]])?;
+        }
 
         // do the location data
-        let _ = sqlx::query(r#"INSERT INTO ror.locations (id, geonames_id, name, lat, lng, 
-        continent_code, continent_name, country_code, country_name, country_subdivision_code, country_subdivision_name ) 
-        SELECT * FROM UNNEST($1::text[], $2::int[], $3::text[], $4::real[], $5::real[], $6::text[], $7::text[], $8::text[], $9::text[], $10::text[], $11::text[])"#)
-        .bind(&self.loc_db_ids)
-        .bind(&self.gn_ids)
-        .bind(&self.gn_names)
-        .bind(&self.lats)
-        .bind(&self.lngs)
-        .bind(&self.cont_codes)
-        .bind(&self.cont_names)
-        .bind(&self.cy_codes)
-        .bind(&self.cy_names)
-        .bind(&self.cy_subdiv_codes)
-        .bind(&self.cy_subdiv_names)
-        .execute(pool)
-        .await;
+        for i in 0..self.loc_db_ids.len() {
+            spools.locations.add_row(&[&self.loc_db_ids[i], &self.gn_ids[i], &self.gn_names[i], 
+                                       &self.lats[i], &self.lngs[i], &self.cont_codes[i], &self.cont_names[i],
+                                       &self.cy_codes[i], &self.cy_names[i], 
+                                       &self.cy_subdiv_codes[i], &self.cy_subdiv_names[i], &self.loc_extras[i]])?;
+        }
 
+        Ok(())
     }
 }
 
@@ -238,20 +232,24 @@ pub struct NonRequiredDataVecs {
     pub link_db_ids: Vec<String>,
     pub link_types: Vec<String>,
     pub links: Vec<String>,
+    pub link_extras: Vec<Option<String>>,
 
     pub id_db_ids: Vec<String>,
     pub id_types: Vec<String>,
     pub id_values: Vec<String>,
     pub is_prefs: Vec<Option<bool>>,
+    pub id_extras: Vec<Option<String>>,
 
     pub rel_db_ids: Vec<String>,
     pub rel_types: Vec<String>,
     pub rel_ids: Vec<String>,
     pub rel_labels: Vec<String>,
+    pub rel_extras: Vec<Option<String>>,
 
     pub dom_db_ids: Vec<String>,
     pub doms:Vec<String>,
 
+    pub invalid_ids: Vec<InvalidId>,
 }
 
 impl NonRequiredDataVecs{
@@ -260,35 +258,49 @@ impl NonRequiredDataVecs{
             link_db_ids: Vec::with_capacity(vsize),
             link_types: Vec::with_capacity(vsize),
             links: Vec::with_capacity(vsize),
+            link_extras: Vec::with_capacity(vsize),
 
             id_db_ids: Vec::with_capacity(vsize),
             id_types: Vec::with_capacity(vsize),
             id_values: Vec::with_capacity(vsize),
             is_prefs: Vec::with_capacity(vsize),
+            id_extras: Vec::with_capacity(vsize),
 
             rel_db_ids: Vec::with_capacity(vsize),
             rel_types: Vec::with_capacity(vsize),
             rel_ids: Vec::with_capacity(vsize),
             rel_labels: Vec::with_capacity(vsize),
+            rel_extras: Vec::with_capacity(vsize),
 
             dom_db_ids: Vec::with_capacity(vsize),
             doms: Vec::with_capacity(vsize),
 
+            invalid_ids: Vec::new(),
         }
     }
 
     pub fn add_non_required_data(&mut self, r: &RorRecord, db_id: &String) 
     {
         // relationships - may be none
+        // A relationship with an invalid related id is not imported, but is
+        // listed (in ror.invalid_ids) instead.
         if r.relationships.is_some() {
             let rels = r.relationships.as_ref().unwrap();
             if rels.len() > 0 {
                 for rel in rels.iter()
                 {
+                    let related_id = match RorId::parse(&rel.id) {
+                        Ok(id) => id,
+                        Err(e) => {
+                            self.invalid_ids.push(InvalidId::new(Some(db_id), "relationships.id", &rel.id, &e));
+                            continue;
+                        },
+                    };
                     self.rel_db_ids.push(db_id.clone());
                     self.rel_types.push(rel.rel_type.clone());
-                    self.rel_ids.push(extract_id_from(&rel.id).to_string());
+                    self.rel_ids.push(related_id.to_string());
                     self.rel_labels.push(rel.label.clone());
+                    self.rel_extras.push(extras_as_json(&rel.extras));
                 };
             }
         }
@@ -302,6 +314,7 @@ impl NonRequiredDataVecs{
                     self.link_db_ids.push(db_id.clone());
                     self.link_types.push(lnk.link_type.clone());
                     self.links.push(lnk.value.clone());
+                    self.link_extras.push(extras_as_json(&lnk.extras));
                 }; 
             }
         }
@@ -330,6 +343,7 @@ impl NonRequiredDataVecs{
                             self.id_types.push(id_type.clone());
                             self.id_values.push(eid.all[0].clone());
                             self.is_prefs.push(Some(true));  
+                            self.id_extras.push(extras_as_json(&eid.extras));
                           }
                           else {
     
@@ -349,6 +363,7 @@ impl NonRequiredDataVecs{
                                 {
                                     self.is_prefs.push(None);
                                 }
+                                self.id_extras.push(extras_as_json(&eid.extras));
                             }
                         }
                     }
@@ -371,55 +386,56 @@ impl NonRequiredDataVecs{
     
     }
 
-    pub async fn store_data(&self, pool : &Pool<Postgres>) {
+    pub fn write_copy_rows(&self, spools: &mut CopySpools) -> Result<(), AppError> {
 
         // do the relationships data
-        let _ = sqlx::query(r#"INSERT INTO ror.relationships (id, rel_type, related_id, related_label) 
-        SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[])"#)
-        .bind(&self.rel_db_ids)
-        .bind(&self.rel_types)
-        .bind(&self.rel_ids)
-        .bind(&self.rel_labels)
-        .execute(pool)
-        .await;
-
+        for i in 0..self.rel_db_ids.len() {
+            spools.relationships.add_row(&[&self.rel_db_ids[i], &self.rel_types[i], 
+                                           &self.rel_ids[i], &self.rel_labels[i], &self.rel_extras[i]])?;
+        }
     
         // do the links data
-        let _ = sqlx::query(r#"INSERT INTO ror.links (id, link_type, value) 
-        SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[])"#)
-        .bind(&self.link_db_ids)
-        .bind(&self.link_types)
-        .bind(&self.links)
-        .execute(pool)
-        .await;
-
+        for i in 0..self.link_db_ids.len() {
+            spools.links.add_row(&[&self.link_db_ids[i], &self.link_types[i], &self.links[i], 
+                                   &self.link_extras[i]])?;
+        }
     
         // do the external ids data
-        let _ = sqlx::query(r#"INSERT INTO ror.external_ids (id, id_type, id_value, is_preferred) 
-        SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::bool[])"#)
-        .bind(&self.id_db_ids)
-        .bind(&self.id_types)
-        .bind(&self.id_values)
-        .bind(&self.is_prefs)
-        .execute(pool)
-        .await;
+        for i in 0..self.id_db_ids.len() {
+            spools.external_ids.add_row(&[&self.id_db_ids[i], &self.id_types[i], 
+                                          &self.id_values[i], &self.is_prefs[i], &self.id_extras[i]])?;
+        }
     
         // do the domain data
-        let _ = sqlx::query(r#"INSERT INTO ror.domains (id, value) 
-        SELECT * FROM UNNEST($1::text[], $2::text[])"#)
-        .bind(&self.dom_db_ids)
-        .bind(&self.doms)
-        .execute(pool)
-        .await;
+        for i in 0..self.dom_db_ids.len() {
+            spools.domains.add_row(&[&self.dom_db_ids[i], &self.doms[i]])?;
+        }
 
+        Ok(())
     }
 
 }
 
 
-pub fn extract_id_from(full_id: &String) -> &str {
-    let b = full_id.as_bytes();
-    std::str::from_utf8(&b[b.len()-9..]).unwrap()
+// An id that failed the ror id checks, with the record it was found in
+// (if that record's own id is valid), the field and the reason.
+
+pub struct InvalidId {
+    pub id: Option<String>,
+    pub field: String,
+    pub value: String,
+    pub reason: String,
+}
+
+impl InvalidId {
+    pub fn new(id: Option<&String>, field: &str, value: &str, e: &RorIdError) -> Self {
+        InvalidId {
+            id: id.cloned(),
+            field: field.to_string(),
+            value: value.to_string(),
+            reason: e.to_string(),
+        }
+    }
 }
 
 // Tests
@@ -427,13 +443,45 @@ pub fn extract_id_from(full_id: &String) -> &str {
 #[cfg(test)]
 mod tests {
     use super::*;
-    
-    // Ensure the extract_id_from utility function works as expected.
 
     #[test]
-    fn test_extracting_id() {
-        let test_id = "https://ror.org/123456789".to_string();
-        assert_eq!(extract_id_from(&(test_id)), "123456789")
+    fn check_name_extras_aligned_with_name_types() {
+        let json = r#"{"id": "https://ror.org/04ttjf776", "status": "active", "established": null,
+            "names": [{"value": "Test Org", "lang": "en", "types": ["label", "alias"], "note": "x"},
+                      {"value": "TO", "lang": null, "types": ["acronym"]}],
+            "types": [], "locations": [], "external_ids": [], "links": [], "domains": [], "relationships": [],
+            "admin": {"created": {"date": "2020-01-01", "schema_version": "1.0"},
+                      "last_modified": {"date": "2024-01-01", "schema_version": "2.0"}}}"#;
+        let r: RorRecord = serde_json::from_str(json).unwrap();
+        let mut rdv = RequiredDataVecs::new(1);
+        rdv.add_required_data(&r, &"04ttjf776".to_string());
+
+        assert_eq!(rdv.name_db_ids.len(), 3);
+        for len in [rdv.names.len(), rdv.name_types.len(), rdv.is_rors.len(), rdv.langs.len(), rdv.name_extras.len()] {
+            assert_eq!(len, 3);
+        }
+        assert_eq!(rdv.name_types, vec!["label", "alias", "acronym"]);
+        let extras = Some(r#"{"note":"x"}"#.to_string());
+        assert_eq!(rdv.name_extras, vec![extras.clone(), extras, None]);
     }
-}
 
+    #[test]
+    fn check_invalid_related_ids_listed_not_imported() {
+        let json = r#"{"id": "https://ror.org/04ttjf776", "status": "active", "established": null,
+            "names": [], "types": [], "locations": [], "external_ids": [], "links": [], "domains": [],
+            "relationships": [{"type": "parent", "label": "A", "id": "https://ror.org/02bfwt286"},
+                              {"type": "related", "label": "B", "id": "https://ror.org/1234"},
+                              {"type": "child", "label": "C", "id": "https://ror.org/05zzzzz99"}],
+            "admin": {"created": {"date": "2020-01-01", "schema_version": "1.0"},
+                      "last_modified": {"date": "2024-01-01", "schema_version": "2.0"}}}"#;
+        let r: RorRecord = serde_json::from_str(json).unwrap();
+        let mut ndv = NonRequiredDataVecs::new(1);
+        ndv.add_non_required_data(&r, &"04ttjf776".to_string());
+
+        assert_eq!(ndv.rel_ids, vec!["02bfwt286".to_string()]);
+        assert_eq!(ndv.invalid_ids.len(), 2);
+        assert_eq!(ndv.invalid_ids[0].id.as_deref(), Some("04ttjf776"));
+        assert_eq!(ndv.invalid_ids[0].value, "https://ror.org/1234");
+        assert_eq!(ndv.invalid_ids[1].reason, "the checksum does not match (expected '40')");
+    }
+}
@@ -0,0 +1,124 @@
+/***************************************************************************
+ * Keeps a permanent record, in smm.imported_files, of the source file used
+ * for each version - its name, SHA-256 checksum, size and number of records.
+ * Before an import the checksum of the source file is compared with any
+ * record for the same version. An identical file has already been imported,
+ * so, if its data is still in the ror tables, the import is skipped, unless
+ * it is 'forced'. (Whether the data is then processed depends on whether the
+ * version has already been summarised - see lib.rs). A
+ * different file claiming the same version is an error, as it would
+ * otherwise silently replace the data and summaries of that version.
+ * The record is written within the import transaction, so it is only kept
+ * if the import succeeds.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres, Transaction};
+use super::ror_source_file::SourceChecksum;
+use crate::error_defs::AppError;
+
+#[derive(Debug, PartialEq)]
+pub enum PriorImport {
+    NotFound,
+    SameFile,
+    DifferentFile { source_file: String, sha256: String },
+}
+
+pub async fn ensure_history_table(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let sql = r#"SET client_min_messages TO WARNING;
+    create schema if not exists smm;
+
+    create table if not exists smm.imported_files
+    (
+          vcode             varchar     not null primary key
+        , source_file       varchar     not null
+        , file_sha256       char(64)    not null
+        , file_size         bigint      not null
+        , record_count      int         not null
+        , imported_at       timestamp   not null default now()
+    );
+
+    SET client_min_messages TO NOTICE;"#;
+    sqlx::raw_sql(sql).execute(pool).await?;
+    Ok(())
+}
+
+
+pub async fn check_prior_import(vcode: &str, checksum: &SourceChecksum, pool: &Pool<Postgres>) -> Result<PriorImport, AppError> {
+
+    let sql = r#"select source_file, file_sha256, file_size from smm.imported_files where vcode = $1"#;
+    let prior: Option<(String, String, i64)> = sqlx::query_as(sql).bind(vcode).fetch_optional(pool).await?;
+    Ok(match prior {
+        None => PriorImport::NotFound,
+        Some((source_file, sha256, size)) => compare_files(checksum, source_file, sha256, size),
+    })
+}
+
+
+fn compare_files(checksum: &SourceChecksum, source_file: String, sha256: String, size: i64) -> PriorImport {
+    if sha256 == checksum.sha256 && size as u64 == checksum.size {
+        PriorImport::SameFile
+    }
+    else {
+        PriorImport::DifferentFile { source_file, sha256 }
+    }
+}
+
+
+pub async fn ror_tables_hold_version(vcode: &str, pool: &Pool<Postgres>) -> Result<bool, AppError> {
+
+    let sql = r#"select to_regclass('ror.version_details') is not null"#;
+    let exists: bool = sqlx::query_scalar(sql).fetch_one(pool).await?;
+    if !exists {
+        return Ok(false)
+    }
+    let sql = r#"select exists (select 1 from ror.version_details where version = $1)"#;
+    let held: bool = sqlx::query_scalar(sql).bind(vcode).fetch_one(pool).await?;
+    Ok(held)
+}
+
+
+pub async fn record_import(vcode: &str, source_file: &str, checksum: &SourceChecksum, record_count: u64,
+                           tx: &mut Transaction<'_, Postgres>) -> Result<(), AppError> {
+
+    let sql = r#"insert into smm.imported_files (vcode, source_file, file_sha256, file_size, record_count)
+                 values ($1, $2, $3, $4, $5)
+                 on conflict (vcode) do update set source_file = excluded.source_file,
+                 file_sha256 = excluded.file_sha256, file_size = excluded.file_size,
+                 record_count = excluded.record_count, imported_at = now()"#;
+    sqlx::query(sql).bind(vcode).bind(source_file).bind(&checksum.sha256)
+    .bind(checksum.size as i64).bind(record_count as i32)
+    .execute(&mut **tx).await?;
+    Ok(())
+}
+
+
+pub async fn remove_import_record(vcode: &str, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // The table only exists once an import has been run (e.g. not after a -p -z test run on a new database).
+
+    let sql = r#"select to_regclass('smm.imported_files') is not null"#;
+    let exists: bool = sqlx::query_scalar(sql).fetch_one(pool).await?;
+    if !exists {
+        return Ok(())
+    }
+    sqlx::query("delete from smm.imported_files where vcode = $1")
+    .bind(vcode).execute(pool).await?;
+    Ok(())
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_prior_files_compared() {
+        let checksum = SourceChecksum { sha256: "ab12".to_string(), size: 100 };
+        assert_eq!(compare_files(&checksum, "a.json".to_string(), "ab12".to_string(), 100), PriorImport::SameFile);
+        assert_eq!(compare_files(&checksum, "b.json".to_string(), "cd34".to_string(), 100),
+                   PriorImport::DifferentFile { source_file: "b.json".to_string(), sha256: "cd34".to_string() });
+    }
+}
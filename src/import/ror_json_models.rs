@@ -1,4 +1,10 @@
 use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+// Each struct includes an 'extras' map, which collects any fields not (yet) known 
+// to the system, e.g. those added in a later version of the ror schema. These are 
+// stored in the 'extras' column of the corresponding ror table, rather than being lost.
 
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -13,7 +19,10 @@ use serde::{Serialize, Deserialize};
         pub links: Option<Vec<Link>>,
         pub relationships: Option<Vec<Relationship>>,
         pub domains: Option<Vec<String>>,
-        pub admin: Admin,
+        #[serde(deserialize_with = "deserialize_admin")]
+        pub admin: Option<Admin>,       // required in v2 data, absent from v1 records
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
     }
 
     #[derive(Serialize, Deserialize, Debug)]
@@ -21,12 +30,16 @@ use serde::{Serialize, Deserialize};
         pub value: String,
         pub lang: Option<String>,
         pub types: Vec<String>,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
     }
 
     #[derive(Serialize, Deserialize, Debug)]
     pub struct Location {
-        pub geonames_id: i64,
+        pub geonames_id: Option<i64>,
         pub geonames_details: GeoDetails,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
     }
 
     #[derive(Serialize, Deserialize, Debug)]
@@ -37,9 +50,11 @@ use serde::{Serialize, Deserialize};
         pub country_name: String,
         pub country_subdivision_code : Option<String>,
         pub country_subdivision_name : Option<String>,
-        pub lat: f64,
-        pub lng: f64,
+        pub lat: Option<f64>,
+        pub lng: Option<f64>,
         pub name: String,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
     } 
 
     #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -48,6 +63,8 @@ use serde::{Serialize, Deserialize};
         pub id_type: String,
         pub all: Vec<String>,
         pub preferred: Option<String>,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
     } 
 
 
@@ -56,6 +73,8 @@ use serde::{Serialize, Deserialize};
         #[serde(rename(deserialize = "type"))]
         pub link_type: String,
         pub value: String,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
     }
 
 
@@ -65,6 +84,8 @@ use serde::{Serialize, Deserialize};
         pub rel_type: String,
         pub label: String,
         pub id: String,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
     }
 
     
@@ -72,6 +93,8 @@ use serde::{Serialize, Deserialize};
     pub struct Admin {
         pub created: DateSchema,
         pub last_modified: DateSchema,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
     }
 
 
@@ -79,5 +102,142 @@ use serde::{Serialize, Deserialize};
     pub struct DateSchema {
         pub date: String,
         pub schema_version: String,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
+    }
+
+
+
+impl RorRecord {
+
+    // Adds the path of each unknown field in the record (e.g. 'names.script'), 
+    // to the running count of each path's occurrences.
+
+    pub fn count_extra_fields(&self, counts: &mut BTreeMap<String, usize>) {
+        add_paths("record", &self.extras, counts);
+        for name in self.names.iter() {
+            add_paths("names", &name.extras, counts);
+        }
+        for loc in self.locations.iter() {
+            add_paths("locations", &loc.extras, counts);
+            add_paths("locations.geonames_details", &loc.geonames_details.extras, counts);
+        }
+        for eid in self.external_ids.iter().flatten() {
+            add_paths("external_ids", &eid.extras, counts);
+        }
+        for lnk in self.links.iter().flatten() {
+            add_paths("links", &lnk.extras, counts);
+        }
+        for rel in self.relationships.iter().flatten() {
+            add_paths("relationships", &rel.extras, counts);
+        }
+        if let Some(admin) = &self.admin {
+            add_paths("admin", &admin.extras, counts);
+            add_paths("admin.created", &admin.created.extras, counts);
+            add_paths("admin.last_modified", &admin.last_modified.extras, counts);
+        }
+    }
+}
+
+impl Location {
+
+    // Unknown fields of the geonames details are stored with those of the location.
+
+    pub fn all_extras(&self) -> Map<String, Value> {
+        let mut extras = self.extras.clone();
+        if !self.geonames_details.extras.is_empty() {
+            extras.insert("geonames_details".to_string(), Value::Object(self.geonames_details.extras.clone()));
+        }
+        extras
+    }
+}
+
+impl Admin {
+
+    // Unknown fields of the created and last modified details are stored with those of admin.
+
+    pub fn all_extras(&self) -> Map<String, Value> {
+        let mut extras = self.extras.clone();
+        if !self.created.extras.is_empty() {
+            extras.insert("created".to_string(), Value::Object(self.created.extras.clone()));
+        }
+        if !self.last_modified.extras.is_empty() {
+            extras.insert("last_modified".to_string(), Value::Object(self.last_modified.extras.clone()));
+        }
+        extras
+    }
+}
+
+
+// The admin section is only optional for records converted from v1, which have
+// none. In the v2 data it must be present, as before.
+
+fn deserialize_admin<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Admin>, D::Error> {
+    Admin::deserialize(deserializer).map(Some)
+}
+
+
+pub fn extras_as_json(extras: &Map<String, Value>) -> Option<String> {
+    if extras.is_empty() {
+        None
+    }
+    else {
+        Some(Value::Object(extras.clone()).to_string())
     }
+}
+
+
+fn add_paths(prefix: &str, extras: &Map<String, Value>, counts: &mut BTreeMap<String, usize>) {
+    for key in extras.keys() {
+        *counts.entry(format!("{}.{}", prefix, key)).or_insert(0) += 1;
+    }
+}
+
 
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ensure fields not in the current schema are retained, at each level, rather than dropped.
+
+    #[test]
+    fn check_unknown_fields_are_kept() {
+        let json = r#"{"id": "https://ror.org/04ttjf776", "status": "active", "established": null,
+            "names": [{"value": "Test Org", "lang": "en", "types": ["label"], "script": "Latn"}],
+            "types": ["education"], 
+            "locations": [{"geonames_id": 2643743, "geonames_details": {"continent_code": "EU",
+                "continent_name": "Europe", "country_code": "GB", "country_name": "United Kingdom",
+                "country_subdivision_code": null, "country_subdivision_name": null,
+                "lat": 51.5, "lng": -0.12, "name": "London", "timezone": "Europe/London"}}],
+            "external_ids": [], "links": [{"type": "website", "value": "https://test.org", "verified": true}], 
+            "relationships": [], "domains": [],
+            "admin": {"created": {"date": "2020-01-01", "schema_version": "1.0"},
+                      "last_modified": {"date": "2024-01-01", "schema_version": "2.2", "by": "curator"}},
+            "acronym_history": ["TO"]}"#;
+        let r: RorRecord = serde_json::from_str(json).unwrap();
+
+        assert_eq!(extras_as_json(&r.extras), Some(r#"{"acronym_history":["TO"]}"#.to_string()));
+        assert_eq!(extras_as_json(&r.names[0].extras), Some(r#"{"script":"Latn"}"#.to_string()));
+        assert_eq!(extras_as_json(&r.locations[0].all_extras()), 
+                   Some(r#"{"geonames_details":{"timezone":"Europe/London"}}"#.to_string()));
+        assert_eq!(extras_as_json(&r.admin.as_ref().unwrap().all_extras()), Some(r#"{"last_modified":{"by":"curator"}}"#.to_string()));
+        assert_eq!(extras_as_json(&r.links.as_ref().unwrap()[0].extras), Some(r#"{"verified":true}"#.to_string()));
+
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        r.count_extra_fields(&mut counts);
+        let paths: Vec<&String> = counts.keys().collect();
+        assert_eq!(paths, vec!["admin.last_modified.by", "links.verified", 
+                               "locations.geonames_details.timezone", "names.script", "record.acronym_history"]);
+    }
+
+    #[test]
+    fn check_v2_record_requires_admin() {
+        let json = r#"{"id": "https://ror.org/04ttjf776", "status": "active", "established": null,
+            "names": [], "types": [], "locations": [], "external_ids": [], "links": [],
+            "relationships": [], "domains": []}"#;
+        let err = serde_json::from_str::<RorRecord>(json).unwrap_err();
+        assert!(err.to_string().starts_with("missing field `admin`"));
+    }
+}
@@ -0,0 +1,202 @@
+/***************************************************************************
+ * A streaming reader for the ROR json dump. The dump is a single top level
+ * json array of ror records. Rather than reading the whole file into a string
+ * and deserialising it into one large vector, the reader walks through the
+ * array and deserialises one record at a time, so that memory use stays
+ * (more or less) constant, however large the source file becomes.
+ * Each record is returned as a generic json value, so that a record that
+ * cannot be converted into a RorRecord can still be stored, as it was, in
+ * the rejected records. Only a break in the json itself is an error here.
+ * The number of bytes read from the underlying source is tracked, to allow
+ * progress to be reported against the total size of the source.
+ ***************************************************************************/
+
+use std::io::{BufRead, BufReader, Read};
+use serde::Deserialize;
+use serde_json::Value;
+use crate::error_defs::{AppError, CustomError};
+
+// Implemented by each of the readers of source data (json or csv), which return 
+// records as json values, and allow progress to be reported by bytes read.
+
+pub trait RecordSource: Iterator<Item = Result<Value, AppError>> {
+    fn bytes_read(&self) -> u64;
+}
+
+// Wraps the underlying reader and keeps a running total of the bytes read from it.
+
+pub struct CountingReader<R: Read> {
+    inner: R,
+    bytes_read: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes_read += n as u64;
+        Ok(n)
+    }
+}
+
+pub struct RorJsonReader<R: Read> {
+    reader: BufReader<CountingReader<R>>,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> RorJsonReader<R> {
+    pub fn new(source: R) -> Self {
+        RorJsonReader {
+            reader: BufReader::new(CountingReader { inner: source, bytes_read: 0 }),
+            started: false,
+            finished: false,
+        }
+    }
+
+    fn peek_non_ws_byte(&mut self) -> Result<Option<u8>, AppError> {
+
+        // Skips any whitespace and returns (without consuming) the next byte,
+        // or None if the end of the source has been reached.
+
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+                Some(i) => {
+                    let b = buf[i];
+                    self.reader.consume(i);
+                    return Ok(Some(b));
+                },
+                None => {
+                    let n = buf.len();
+                    self.reader.consume(n);
+                },
+            }
+        }
+    }
+
+    fn expect_byte(&mut self, expected: &[u8]) -> Result<u8, AppError> {
+        match self.peek_non_ws_byte()? {
+            Some(b) if expected.contains(&b) => {
+                self.reader.consume(1);
+                Ok(b)
+            },
+            Some(b) => Err(format_error(&format!("unexpected character '{}' found in the source data, after {} bytes",
+                                        b as char, self.bytes_read()))),
+            None => Err(format_error("unexpected end of the source data - the json array is incomplete")),
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Value, AppError> {
+
+        // A fresh deserializer is used for each record. serde_json does not read
+        // beyond the closing brace of an object, so the reader is left positioned
+        // immediately after the record.
+
+        let mut de = serde_json::Deserializer::from_reader(&mut self.reader);
+        let rec = Value::deserialize(&mut de)?;
+        Ok(rec)
+    }
+}
+
+impl<R: Read> RecordSource for RorJsonReader<R> {
+    fn bytes_read(&self) -> u64 {
+        self.reader.get_ref().bytes_read
+    }
+}
+
+impl<R: Read> Iterator for RorJsonReader<R> {
+    type Item = Result<Value, AppError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        // The first call must find the opening bracket of the array (and possibly an
+        // immediate closing bracket). Later calls must find either a comma or the closing bracket.
+
+        let res = if !self.started {
+            self.started = true;
+            match self.expect_byte(b"[") {
+                Ok(_) => match self.peek_non_ws_byte() {
+                    Ok(Some(b']')) => { self.reader.consume(1); Ok(None) },
+                    Ok(_) => self.read_record().map(Some),
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            }
+        }
+        else {
+            match self.expect_byte(b",]") {
+                Ok(b']') => Ok(None),
+                Ok(_) => self.read_record().map(Some),
+                Err(e) => Err(e),
+            }
+        };
+
+        match res {
+            Ok(Some(r)) => Some(Ok(r)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            },
+            Err(e) => {
+                self.finished = true;  // no point in trying to continue after a structural error
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+fn format_error(msg: &str) -> AppError {
+    AppError::CsErr(CustomError::new(msg))
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_record(id: &str) -> String {
+        format!(r#"{{"id": "https://ror.org/{}", "status": "active", "established": null,
+            "names": [{{"value": "Test Org", "lang": "en", "types": ["ror_display", "label"]}}],
+            "types": ["education"], "locations": [], "external_ids": [], "links": [],
+            "relationships": [], "domains": [],
+            "admin": {{"created": {{"date": "2020-01-01", "schema_version": "1.0"}},
+                       "last_modified": {{"date": "2024-01-01", "schema_version": "2.0"}}}}}}"#, id)
+    }
+
+    // Ensure records are returned one at a time, and the byte count reaches the source size.
+
+    #[test]
+    fn check_records_are_streamed() {
+        let data = format!("[\n  {},\n  {} \n]\n", make_record("04ttjf776"), make_record("02bfwt286"));
+        let mut rdr = RorJsonReader::new(data.as_bytes());
+        let r1 = rdr.next().unwrap().unwrap();
+        assert_eq!(r1["id"], "https://ror.org/04ttjf776");
+        let r2 = rdr.next().unwrap().unwrap();
+        assert_eq!(r2["id"], "https://ror.org/02bfwt286");
+        assert!(rdr.next().is_none());
+        assert_eq!(rdr.bytes_read(), data.len() as u64);
+    }
+
+    #[test]
+    fn check_empty_array_returns_no_records() {
+        let mut rdr = RorJsonReader::new(" [ ] ".as_bytes());
+        assert!(rdr.next().is_none());
+    }
+
+    #[test]
+    fn check_truncated_array_gives_error() {
+        let data = format!("[{}, ", make_record("04ttjf776"));
+        let mut rdr = RorJsonReader::new(data.as_bytes());
+        assert!(rdr.next().unwrap().is_ok());
+        assert!(rdr.next().unwrap().is_err());
+        assert!(rdr.next().is_none());
+    }
+}
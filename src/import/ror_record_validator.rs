@@ -0,0 +1,105 @@
+/***************************************************************************
+ * Checks each ror record before it is added to the import vectors. The
+ * checks cover the things the later stages rely upon - a well formed ror id
+ * with a matching checksum (see RorId), parseable admin dates, a recognised
+ * status, and at least one name and one location. A record that fails
+ * returns the list of reasons, which are then stored with the raw json of
+ * the record in ror.rejected_records and written to the rejects file. In
+ * 'strict' mode the first invalid record instead aborts the import, as
+ * before.
+ ***************************************************************************/
+
+use chrono::NaiveDate;
+use crate::ror_id::RorId;
+use super::ror_json_models::RorRecord;
+
+const KNOWN_STATUSES: [&str; 3] = ["active", "inactive", "withdrawn"];
+
+pub fn get_invalid_reasons(r: &RorRecord) -> Vec<String> {
+
+    let mut reasons: Vec<String> = Vec::new();
+
+    if let Err(e) = RorId::parse(&r.id) {
+        reasons.push(format!("id '{}' is not a valid ror id ({})", r.id, e));
+    }
+
+    if !KNOWN_STATUSES.contains(&r.status.as_str()) {
+        reasons.push(format!("status '{}' is not a recognised ror status", r.status));
+    }
+
+    // Only records converted from v1 have no admin details (v2 records without them
+    // cannot be read as ror records).
+
+    if let Some(admin) = &r.admin {
+        if NaiveDate::parse_from_str(&admin.created.date, "%Y-%m-%d").is_err() {
+            reasons.push(format!("admin created date '{}' is not a valid ISO date", admin.created.date));
+        }
+
+        if NaiveDate::parse_from_str(&admin.last_modified.date, "%Y-%m-%d").is_err() {
+            reasons.push(format!("admin last modified date '{}' is not a valid ISO date", admin.last_modified.date));
+        }
+    }
+
+    if r.names.is_empty() {
+        reasons.push("record has no names".to_string());
+    }
+    else if r.names.iter().all(|n| n.value.trim().is_empty()) {
+        reasons.push("record has only empty names".to_string());
+    }
+
+    if r.locations.is_empty() {
+        reasons.push("record has no locations".to_string());
+    }
+
+    reasons
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_record(id: &str, status: &str, created: &str, names: &str, locations: &str) -> RorRecord {
+        let json = format!(r#"{{"id": "{}", "status": "{}", "established": null,
+            "names": {}, "types": ["education"], "locations": {},
+            "external_ids": [], "links": [], "relationships": [], "domains": [],
+            "admin": {{"created": {{"date": "{}", "schema_version": "1.0"}},
+                       "last_modified": {{"date": "2024-01-01", "schema_version": "2.0"}}}}}}"#,
+            id, status, names, locations, created);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    const NAMES: &str = r#"[{"value": "Test Org", "lang": "en", "types": ["ror_display", "label"]}]"#;
+    const LOCATIONS: &str = r#"[{"geonames_id": 2643743, "geonames_details": {"continent_code": "EU",
+        "continent_name": "Europe", "country_code": "GB", "country_name": "United Kingdom",
+        "country_subdivision_code": null, "country_subdivision_name": null,
+        "lat": 51.5, "lng": -0.12, "name": "London"}}]"#;
+
+    #[test]
+    fn check_valid_record_has_no_reasons() {
+        let r = make_record("https://ror.org/04ttjf776", "active", "2020-01-01", NAMES, LOCATIONS);
+        assert!(get_invalid_reasons(&r).is_empty());
+    }
+
+    #[test]
+    fn check_id_with_bad_checksum_rejected() {
+        let r = make_record("https://ror.org/04ttjf777", "active", "2020-01-01", NAMES, LOCATIONS);
+        let reasons = get_invalid_reasons(&r);
+        assert_eq!(reasons.len(), 1);
+        assert!(reasons[0].ends_with("(the checksum does not match (expected '76'))"));
+    }
+
+    #[test]
+    fn check_invalid_record_lists_all_reasons() {
+        let r = make_record("https://ror.org/04tt", "closed", "2020-13-01", "[]", "[]");
+        let reasons = get_invalid_reasons(&r);
+        assert_eq!(reasons.len(), 5);
+        assert_eq!(reasons[0], "id 'https://ror.org/04tt' is not a valid ror id (the id has 4 characters rather than 9)");
+        assert!(reasons[1].starts_with("status 'closed'"));
+        assert!(reasons[2].starts_with("admin created date '2020-13-01'"));
+        assert_eq!(reasons[3], "record has no names");
+        assert_eq!(reasons[4], "record has no locations");
+    }
+}
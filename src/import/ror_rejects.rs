@@ -0,0 +1,69 @@
+/***************************************************************************
+ * Records that fail validation (or cannot be read as a ror record at all)
+ * are not imported, but are stored, with the reasons for their rejection and
+ * their original json, in ror.rejected_records. They are also written, as
+ * json lines, to a rejects file in the log folder. The file is only created
+ * if and when the first rejected record is found.
+ ***************************************************************************/
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use serde_json::{json, Value};
+use sqlx::{Postgres, Transaction};
+use crate::error_defs::AppError;
+
+pub struct RejectedRecords {
+    file_path: PathBuf,
+    file: Option<BufWriter<File>>,
+    pub count: usize,
+}
+
+impl RejectedRecords {
+    pub fn new(file_path: PathBuf) -> Self {
+        RejectedRecords {
+            file_path,
+            file: None,
+            count: 0,
+        }
+    }
+
+    pub fn file_path(&self) -> &PathBuf {
+        &self.file_path
+    }
+
+    pub async fn store(&mut self, record_num: usize, raw: &Value, reasons: &[String],
+                       tx: &mut Transaction<'_, Postgres>) -> Result<(), AppError> {
+
+        // The id is taken from the raw json, as there may not be a valid RorRecord.
+
+        let id = raw.get("id").and_then(|v| v.as_str());
+
+        sqlx::query(r#"INSERT INTO ror.rejected_records (record_num, id, reasons, raw_json)
+            values ($1, $2, $3, $4::jsonb)"#)
+        .bind(record_num as i32)
+        .bind(id)
+        .bind(reasons.join("; "))
+        .bind(raw.to_string())
+        .execute(&mut **tx)
+        .await?;
+
+        if self.file.is_none() {
+            self.file = Some(BufWriter::new(File::create(&self.file_path)?));
+        }
+        if let Some(f) = self.file.as_mut() {
+            let line = json!({ "record_num": record_num, "id": id, "reasons": reasons, "record": raw });
+            writeln!(f, "{}", line)?;
+        }
+
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<(), AppError> {
+        if let Some(f) = self.file.as_mut() {
+            f.flush()?;
+        }
+        Ok(())
+    }
+}
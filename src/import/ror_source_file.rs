@@ -0,0 +1,228 @@
+/***************************************************************************
+ * Identifies the type of source file being imported. As well as a 'raw'
+ * data file, the system can read directly from the zip archive that ROR
+ * posts on Zenodo (which contains the v2 json, the v1 json and csv files),
+ * or from a gzipped copy of the data file. In neither case is the data
+ * written to disk - it is decompressed as it is streamed in.
+ * The data itself may be json (the default) or csv. The format is taken
+ * from the file extension, unless specified explicitly (which is necessary
+ * to read the csv file from within a zip archive).
+ * Older archives contain only the v1 json file, which is used if there
+ * is no v2 file in the archive.
+ * The SHA-256 checksum and size of the source file (as a whole) are used
+ * to recognise a file that has already been imported.
+ ***************************************************************************/
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use sha2::{Digest, Sha256};
+use zip::ZipArchive;
+use crate::error_defs::{AppError, CustomError};
+
+#[derive(Debug, PartialEq)]
+pub enum SourceType {
+    Plain,
+    Gzip,
+    Zip,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SourceFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SourceChecksum {
+    pub sha256: String,
+    pub size: u64,
+}
+
+pub fn get_source_type(file_name: &str) -> SourceType {
+    let lc_name = file_name.to_lowercase();
+    if lc_name.ends_with(".zip") {
+        SourceType::Zip
+    }
+    else if lc_name.ends_with(".gz") {
+        SourceType::Gzip
+    }
+    else {
+        SourceType::Plain
+    }
+}
+
+pub fn get_source_format(file_name: &str, format_flag: &str) -> SourceFormat {
+    match format_flag {
+        "csv" => SourceFormat::Csv,
+        "json" => SourceFormat::Json,
+        _ => {
+            let lc_name = file_name.to_lowercase();
+            if lc_name.ends_with(".csv") || lc_name.ends_with(".csv.gz") {
+                SourceFormat::Csv
+            }
+            else {
+                SourceFormat::Json
+            }
+        },
+    }
+}
+
+pub fn get_gz_uncompressed_size(gz_file: &mut File) -> Result<u64, AppError> {
+
+    // The last 4 bytes of a gzip file hold the size of the uncompressed data (modulo 2^32),
+    // as a little endian integer. The file is returned to its start once this has been read.
+
+    let mut size_bytes = [0u8; 4];
+    gz_file.seek(SeekFrom::End(-4))?;
+    gz_file.read_exact(&mut size_bytes)?;
+    gz_file.seek(SeekFrom::Start(0))?;
+    Ok(u32::from_le_bytes(size_bytes) as u64)
+}
+
+pub fn get_file_checksum(file_path: &Path) -> Result<SourceChecksum, AppError> {
+
+    // The file is read in blocks, so that it is never held in memory as a whole.
+
+    let mut file = File::open(file_path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut size: u64 = 0;
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        size += n as u64;
+    }
+    Ok(SourceChecksum {
+        sha256: format!("{:x}", hasher.finalize()),
+        size,
+    })
+}
+
+pub fn find_data_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, format: SourceFormat) -> Result<usize, AppError> {
+
+    // Returns the index of the (single) v2 schema file of the required format within 
+    // the archive, or, if there is none, the index of the (single) v1 file of that format. 
+    // Any folder path within the archive is ignored.
+
+    let ext = match format {
+        SourceFormat::Json => "json",
+        SourceFormat::Csv => "csv",
+    };
+    let v2_ending = format!("_schema_v2.{}", ext);
+    let ending = format!(".{}", ext);
+
+    let mut v2_found: Vec<usize> = Vec::new();
+    let mut other_found: Vec<usize> = Vec::new();
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)?;
+        let lc_name = entry.name().to_lowercase();
+        if entry.is_file() && lc_name.ends_with(&ending) {
+            if lc_name.ends_with(&v2_ending) {
+                v2_found.push(i);
+            }
+            else {
+                other_found.push(i);
+            }
+        }
+    }
+
+    if v2_found.len() > 1 {
+        let msg = format!("More than one file ending in '{}' was found in the source archive", v2_ending);
+        return Err(AppError::CsErr(CustomError::new(&msg)));
+    }
+    if v2_found.len() == 1 {
+        return Ok(v2_found[0]);
+    }
+    
+    match other_found.len() {
+        1 => Ok(other_found[0]),
+        0 => {
+            let msg = format!("No {} file could be found in the source archive", ext);
+            Err(AppError::CsErr(CustomError::new(&msg)))
+        },
+        _ => {
+            let msg = format!("No v2 {} file, and more than one other {} file, was found in the source archive", ext, ext);
+            Err(AppError::CsErr(CustomError::new(&msg)))
+        },
+    }
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    fn make_archive(entry_names: &[&str]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let opts = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for name in entry_names {
+            zw.start_file(*name, opts).unwrap();
+            zw.write_all(b"[]").unwrap();
+        }
+        let cursor = zw.finish().unwrap();
+        ZipArchive::new(cursor).unwrap()
+    }
+
+    #[test]
+    fn check_source_type_from_file_name() {
+        assert_eq!(get_source_type("v1.59-2025-01-23-ror-data.zip"), SourceType::Zip);
+        assert_eq!(get_source_type("v1.59-2025-01-23-ror-data_schema_v2.json.gz"), SourceType::Gzip);
+        assert_eq!(get_source_type("v1.59-2025-01-23-ror-data_schema_v2.json"), SourceType::Plain);
+        assert_eq!(get_source_type("v1.59 2025-01-23.JSON"), SourceType::Plain);
+    }
+
+    #[test]
+    fn check_source_format_from_file_name_and_flag() {
+        assert_eq!(get_source_format("v1.59-2025-01-23-ror-data_schema_v2.json", ""), SourceFormat::Json);
+        assert_eq!(get_source_format("v1.59-2025-01-23-ror-data_schema_v2.csv", ""), SourceFormat::Csv);
+        assert_eq!(get_source_format("v1.59-2025-01-23-ror-data_schema_v2.CSV.gz", ""), SourceFormat::Csv);
+        assert_eq!(get_source_format("v1.59-2025-01-23-ror-data.zip", ""), SourceFormat::Json);
+        assert_eq!(get_source_format("v1.59-2025-01-23-ror-data.zip", "csv"), SourceFormat::Csv);
+        assert_eq!(get_source_format("v1.59 2025-01-23.txt", "json"), SourceFormat::Json);
+    }
+
+    #[test]
+    fn check_v2_entry_found_in_archive() {
+        let mut archive = make_archive(&["v1.59-2025-01-23-ror-data.json",
+                                         "v1.59-2025-01-23-ror-data.csv",
+                                         "v1.59-2025-01-23-ror-data_schema_v2.json"]);
+        assert_eq!(find_data_entry(&mut archive, SourceFormat::Json).unwrap(), 2);
+    }
+
+    #[test]
+    fn check_csv_entry_found_in_archive() {
+        let mut archive = make_archive(&["v1.59-2025-01-23-ror-data.json",
+                                         "v1.59-2025-01-23-ror-data_schema_v2.csv",
+                                         "v1.59-2025-01-23-ror-data_schema_v2.json"]);
+        assert_eq!(find_data_entry(&mut archive, SourceFormat::Csv).unwrap(), 1);
+    }
+
+    #[test]
+    fn check_v1_entry_used_if_no_v2_entry() {
+        let mut archive = make_archive(&["v1.40-2024-01-12-ror-data.csv", "v1.40-2024-01-12-ror-data.json"]);
+        assert_eq!(find_data_entry(&mut archive, SourceFormat::Json).unwrap(), 1);
+    }
+
+    #[test]
+    fn check_file_checksum_and_size() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"abc").unwrap();
+        let checksum = get_file_checksum(file.path()).unwrap();
+        assert_eq!(checksum.sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(checksum.size, 3);
+    }
+
+    #[test]
+    fn check_missing_json_entry_gives_error() {
+        let mut archive = make_archive(&["v1.59-2025-01-23-ror-data.csv"]);
+        assert!(find_data_entry(&mut archive, SourceFormat::Json).is_err());
+    }
+}
@@ -0,0 +1,341 @@
+/***************************************************************************
+ * Models for records in the (legacy) v1 ror schema, used in the ror data
+ * dumps before April 2024 (and alongside the v2 files until late 2025).
+ * Each v1 record is converted into a v2 RorRecord, so that it can be stored
+ * in the same ror tables, and processed and summarised in the same way.
+ * The main differences are that v1 has a single 'name' plus separate lists
+ * of aliases, acronyms and (language tagged) labels, 'addresses' rather than
+ * locations, external ids keyed by their type, links as simple strings (plus
+ * a separate wikipedia url), and capitalised org and relationship types.
+ * There is no admin section in v1, so the created and last modified dates
+ * (and schema versions) are left null, rather than invented. Likewise a
+ * missing geonames id, latitude or longitude is left null.
+ * v1 fields that have no equivalent in v2 (e.g. email_address, ip_addresses,
+ * or an address's postcode) are retained as 'extras', at whatever level they
+ * occur, like any other field outside the v2 schema.
+ ***************************************************************************/
+
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use super::ror_json_models::{RorRecord, Name, Location, GeoDetails, ExternalId,
+                             Link, Relationship};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchemaVersion {
+    V1,
+    V2,
+}
+
+pub fn detect_schema_version(raw: &Value) -> SchemaVersion {
+
+    // A v2 record has a 'names' array and an 'admin' object, whereas
+    // a v1 record has a single 'name' string and a list of 'addresses'.
+
+    if raw.get("name").is_some_and(|v| v.is_string()) && raw.get("names").is_none() {
+        SchemaVersion::V1
+    }
+    else {
+        SchemaVersion::V2
+    }
+}
+
+
+#[derive(Serialize, Deserialize, Debug)]
+    pub struct V1RorRecord {
+        pub id: String,
+        pub name: String,
+        pub status: String,
+        pub established: Option<i16>,
+        pub types: Vec<String>,
+        #[serde(default)]
+        pub aliases: Vec<String>,
+        #[serde(default)]
+        pub acronyms: Vec<String>,
+        #[serde(default)]
+        pub labels: Vec<V1Label>,
+        pub addresses: Vec<V1Address>,
+        pub country: V1Country,
+        #[serde(default)]
+        pub links: Vec<String>,
+        pub wikipedia_url: Option<String>,
+        #[serde(default)]
+        pub relationships: Vec<V1Relationship>,
+        #[serde(default)]
+        pub external_ids: BTreeMap<String, V1ExternalId>,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct V1Label {
+        pub label: String,
+        pub iso639: Option<String>,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct V1Address {
+        pub lat: Option<f64>,
+        pub lng: Option<f64>,
+        pub city: Option<String>,
+        pub geonames_city: Option<V1GeonamesCity>,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct V1GeonamesCity {
+        pub id: Option<i64>,
+        pub city: Option<String>,
+        pub geonames_admin1: Option<V1GeonamesAdmin>,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct V1GeonamesAdmin {
+        pub name: Option<String>,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct V1Country {
+        pub country_name: String,
+        pub country_code: String,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct V1Relationship {
+        #[serde(rename(deserialize = "type"))]
+        pub rel_type: String,
+        pub label: String,
+        pub id: String,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct V1ExternalId {
+        pub preferred: Option<String>,
+        pub all: V1IdValues,
+        #[serde(flatten)]
+        pub extras: Map<String, Value>,
+    }
+
+    // The 'all' list of ids is usually an array, but (for GRID ids) is a single string.
+
+    #[derive(Serialize, Deserialize, Debug)]
+    #[serde(untagged)]
+    pub enum V1IdValues {
+        One(String),
+        Many(Vec<String>),
+    }
+
+
+impl V1RorRecord {
+
+    pub fn into_v2(self) -> RorRecord {
+
+        let mut names: Vec<Name> = Vec::new();
+        names.push(make_name(self.name, vec!["ror_display", "label"], None, Map::new()));
+        for label in self.labels {
+            names.push(make_name(label.label, vec!["label"], label.iso639, label.extras));
+        }
+        for alias in self.aliases {
+            names.push(make_name(alias, vec!["alias"], None, Map::new()));
+        }
+        for acronym in self.acronyms {
+            names.push(make_name(acronym, vec!["acronym"], None, Map::new()));
+        }
+
+        // Unknown fields of the nested geonames city (and its admin1 details) are 
+        // kept under those names in the location's extras, with the address's own.
+        // Any of the country are kept with the record's extras.
+
+        let mut extras = self.extras;
+        if !self.country.extras.is_empty() {
+            extras.insert("country".to_string(), Value::Object(self.country.extras));
+        }
+
+        let locations: Vec<Location> = self.addresses.into_iter().map(|a| {
+            let mut loc_extras = a.extras;
+            let mut geonames_id = None;
+            let mut city = a.city;
+            let mut subdiv_name = None;
+            if let Some(gc) = a.geonames_city {
+                geonames_id = gc.id;
+                city = gc.city.or(city);
+                let mut gc_extras = gc.extras;
+                if let Some(ad) = gc.geonames_admin1 {
+                    subdiv_name = ad.name;
+                    if !ad.extras.is_empty() {
+                        gc_extras.insert("geonames_admin1".to_string(), Value::Object(ad.extras));
+                    }
+                }
+                if !gc_extras.is_empty() {
+                    loc_extras.insert("geonames_city".to_string(), Value::Object(gc_extras));
+                }
+            }
+            Location {
+                geonames_id,
+                geonames_details: GeoDetails {
+                    continent_code: None,
+                    continent_name: None,
+                    country_code: self.country.country_code.clone(),
+                    country_name: self.country.country_name.clone(),
+                    country_subdivision_code: None,
+                    country_subdivision_name: subdiv_name,
+                    lat: a.lat,
+                    lng: a.lng,
+                    name: city.unwrap_or_default(),
+                    extras: Map::new(),
+                },
+                extras: loc_extras,
+            }
+        }).collect();
+
+        // OrgRef ids are not carried over, as they were dropped from the v2 schema.
+
+        let external_ids: Vec<ExternalId> = self.external_ids.into_iter()
+            .filter(|(id_type, _)| id_type.to_lowercase() != "orgref")
+            .map(|(id_type, eid)| ExternalId {
+                id_type: id_type.to_lowercase(),
+                all: match eid.all {
+                    V1IdValues::One(s) => vec![s],
+                    V1IdValues::Many(v) => v,
+                },
+                preferred: eid.preferred,
+                extras: eid.extras,
+            }).collect();
+
+        let mut links: Vec<Link> = self.links.into_iter()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| Link { link_type: "website".to_string(), value: l, extras: Map::new() })
+            .collect();
+        if let Some(wp) = self.wikipedia_url.filter(|w| !w.trim().is_empty()) {
+            links.push(Link { link_type: "wikipedia".to_string(), value: wp, extras: Map::new() });
+        }
+
+        let relationships: Vec<Relationship> = self.relationships.into_iter()
+            .map(|r| Relationship {
+                rel_type: r.rel_type.to_lowercase(),
+                label: r.label,
+                id: r.id,
+                extras: r.extras
+            }).collect();
+
+        RorRecord {
+            id: self.id,
+            status: self.status.to_lowercase(),
+            established: self.established,
+            names,
+            types: self.types.iter().map(|t| t.to_lowercase()).collect(),
+            locations,
+            external_ids: Some(external_ids),
+            links: Some(links),
+            relationships: Some(relationships),
+            domains: None,
+            admin: None,
+            extras,
+        }
+    }
+}
+
+
+fn make_name(value: String, types: Vec<&str>, lang: Option<String>, extras: Map<String, Value>) -> Name {
+    Name {
+        value,
+        lang,
+        types: types.iter().map(|t| t.to_string()).collect(),
+        extras,
+    }
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1_RECORD: &str = r#"{"id": "https://ror.org/04ttjf776", "name": "Test University",
+        "email_address": null, "ip_addresses": [], "established": 1901, "types": ["Education"],
+        "relationships": [{"label": "Test Hospital", "type": "Related", "id": "https://ror.org/02bfwt286"}],
+        "addresses": [{"lat": 51.5, "lng": -0.12, "state": null, "state_code": null, "city": "London",
+            "geonames_city": {"id": 2643743, "city": "London",
+                "geonames_admin1": {"name": "England", "id": 6269131, "ascii_name": "England", "code": "GB.ENG"}},
+            "postcode": null, "primary": false, "line": null, "country_geonames_id": 2635167}],
+        "links": ["https://test.ac.uk"], "aliases": ["Test Uni"], "acronyms": ["TU"], "status": "active",
+        "wikipedia_url": "https://en.wikipedia.org/wiki/Test_University",
+        "labels": [{"label": "Prifysgol Prawf", "iso639": "cy"}],
+        "country": {"country_name": "United Kingdom", "country_code": "GB"},
+        "external_ids": {"ISNI": {"preferred": null, "all": ["0000 0001 2345 6789"]},
+                         "OrgRef": {"preferred": null, "all": ["12345"]},
+                         "GRID": {"preferred": "grid.1234.5", "all": "grid.1234.5"}}}"#;
+
+    #[test]
+    fn check_schema_version_detected() {
+        let v1: Value = serde_json::from_str(V1_RECORD).unwrap();
+        assert_eq!(detect_schema_version(&v1), SchemaVersion::V1);
+        let v2: Value = serde_json::from_str(r#"{"id": "x", "names": [], "admin": {}}"#).unwrap();
+        assert_eq!(detect_schema_version(&v2), SchemaVersion::V2);
+    }
+
+    #[test]
+    fn check_v1_record_converted_to_v2() {
+        let v1: V1RorRecord = serde_json::from_str(V1_RECORD).unwrap();
+        let r = v1.into_v2();
+
+        assert_eq!(r.id, "https://ror.org/04ttjf776");
+        assert_eq!(r.types, vec!["education"]);
+        assert_eq!(r.names.len(), 4);
+        assert_eq!(r.names[0].value, "Test University");
+        assert_eq!(r.names[0].types, vec!["ror_display", "label"]);
+        assert_eq!(r.names[1].lang, Some("cy".to_string()));
+        assert_eq!(r.names[3].types, vec!["acronym"]);
+
+        assert_eq!(r.locations[0].geonames_id, Some(2643743));
+        assert_eq!(r.locations[0].geonames_details.country_code, "GB");
+        assert_eq!(r.locations[0].geonames_details.country_subdivision_name, Some("England".to_string()));
+
+        let eids = r.external_ids.unwrap();
+        assert_eq!(eids.len(), 2);
+        assert_eq!(eids[0].id_type, "grid");
+        assert_eq!(eids[0].all, vec!["grid.1234.5"]);
+        assert_eq!(eids[1].id_type, "isni");
+
+        let links = r.links.unwrap();
+        assert_eq!(links[1].link_type, "wikipedia");
+        assert_eq!(r.relationships.unwrap()[0].rel_type, "related");
+
+        assert!(r.admin.is_none());
+        assert!(r.extras.contains_key("email_address"));
+        assert!(!r.extras.contains_key("addresses"));
+    }
+
+    #[test]
+    fn check_missing_location_data_left_null_and_nested_extras_kept() {
+        let v1: V1RorRecord = serde_json::from_str(r#"{"id": "https://ror.org/04ttjf776", "name": "Test University",
+            "established": null, "types": ["Education"], "status": "active",
+            "addresses": [{"lat": null, "lng": null, "city": "London", "postcode": "WC1",
+                "geonames_city": {"id": null, "city": null, "license": "CC-BY",
+                    "geonames_admin1": {"name": "England", "code": "GB.ENG"}}}],
+            "relationships": [{"label": "Test Hospital", "type": "Related", "id": "https://ror.org/02bfwt286", "note": "x"}],
+            "country": {"country_name": "United Kingdom", "country_code": "GB", "continent": "Europe"}}"#).unwrap();
+        let r = v1.into_v2();
+
+        let loc = &r.locations[0];
+        assert_eq!((loc.geonames_id, loc.geonames_details.lat, loc.geonames_details.lng), (None, None, None));
+        assert_eq!(loc.geonames_details.name, "London");
+        assert_eq!(loc.extras["postcode"], "WC1");
+        assert_eq!(loc.extras["geonames_city"]["license"], "CC-BY");
+        assert_eq!(loc.extras["geonames_city"]["geonames_admin1"]["code"], "GB.ENG");
+        assert_eq!(r.relationships.unwrap()[0].extras["note"], "x");
+        assert_eq!(r.extras["country"]["continent"], "Europe");
+    }
+}
@@ -13,7 +13,12 @@ mod import;
 mod process;
 mod summarise;
 mod export;
+mod batch;
+pub mod history;
 pub mod error_defs;
+pub mod ror_id;
+
+pub use process::{ActiveResolution, resolve_to_active};
 
 use error_defs::AppError;
 use setup::log_helper;
@@ -34,7 +39,11 @@ pub async fn run(args: Vec<OsString>) -> Result<(), AppError> {
     let flags = params.flags;
     let test_run = flags.test_run;
 
-    if !flags.test_run {
+    if flags.batch_import {
+       log_helper::setup_batch_log(&params.log_folder)?;
+       log_helper::log_startup_params(&params);
+    }
+    else if !flags.test_run {
        log_helper::setup_log(&params.log_folder, &params.source_file_name)?;
        log_helper::log_startup_params(&params);
     }
@@ -66,41 +75,97 @@ pub async fn run(args: Vec<OsString>) -> Result<(), AppError> {
 
     if !(flags.create_lookups && flags.create_summary) {
 
+        // A source file that has already been imported (as shown by its checksum), and whose 
+        // data is still in the ror tables, is not imported again, unless the --force flag is used.
+        // Its data is still processed if requested, unless the version has already been 
+        // summarised (i.e. the import and processing both took place earlier).
+
+        let mut already_imported = false;
+
+        if flags.batch_import  // import, process and summarise each dump in the data folder, in version order
+        {
+            batch::run_batch(&params, &pool).await?;
+        }
+
         if flags.import_ror    // import ror from json file and store in ror schema tables
         {
-            import::create_ror_tables(&pool).await?;
-            import::import_data(&params.data_folder, &params.source_file_name, 
-                                &params.data_version, &params.data_date, &pool).await?;
-            if !test_run {
-                import::summarise_import(&pool).await?;
+            let checksum = import::get_source_checksum(&params)?;
+            if import::import_required(&params, &checksum, &pool).await? {
+                import::create_ror_tables(&pool).await?;
+                import::import_data(&params, &checksum, &pool).await?;
+                if !test_run {
+                    import::summarise_import(&pool).await?;
+                }
+            }
+            else {
+                already_imported = true;
             }
         }
     
-        if flags.process_data  // transfer data to src tables, and summarise in smm tables
+        let mut already_processed = false;
+        if flags.process_data && already_imported {
+            let vcode = params.data_version.as_ref().map(|v| v.code()).unwrap_or_default();
+            already_processed = !summarise::processing_required(&vcode, &pool).await?;
+        }
+
+        if flags.process_data && !already_processed  // transfer data to src tables, and summarise in smm tables
         {
             process::create_src_tables(&pool).await?;
-            process::process_data(&params.data_version, &pool).await?;
-            summarise::summarise_data(&pool).await?;
+            process::process_data(&params.data_version, params.unmapped_categories, 
+                                  &params.dup_name_rules_path, &pool).await?;
+            summarise::summarise_data(&params.delta_threshold, &pool).await?;
+            if !test_run {
+                history::store_snapshot(params.snapshot_retention, &pool).await?;
+            }
+        }
+
+        if flags.dry_run  // report what the duplicate name rules would delete from the names in the ror tables
+        {
+            process::report_dup_name_rules(&params.dup_name_rules_path, &params.output_folder, &pool).await?;
         }
 
         if flags.export_text  // write out summary data from data in smm tables
         { 
             export::export_as_text(&params.output_folder, &params.output_file_name, 
-                    &params.data_version, &pool).await?;
+                    &params.data_version, params.baseline_date, &pool).await?;
         }
 
         if flags.export_csv  // write out summary data from data in smm tables
         { 
-            export::export_as_csv(&params.output_folder, &params.data_version, &pool).await?;
+            export::export_as_csv(&params.output_folder, &params.data_version, params.baseline_date, &pool).await?;
         }
 
         if flags.export_full_csv  // write out summary data for all versions from data in smm tables
         {       
-                export::export_all_as_csv(&params.output_folder, &pool).await?;
+                export::export_all_as_csv(&params.output_folder, params.baseline_date, &pool).await?;
+        }
+
+        if flags.export_trends  // write out a trend report and wide csv file for all versions from data in smm tables
+        {
+            export::export_trends(&params.output_folder, params.baseline_date, &pool).await?;
+        }
+
+        if flags.export_changes  // write out the records changed since an earlier version, from the ror tables
+        {
+            if let Some(since) = &params.changes_since {
+                export::export_changes(&params.output_folder, since, &pool).await?;
+            }
+        }
+
+        if flags.resolve_id  // report the current active organisation for a ror id, from the src tables
+        {
+            process::report_active_id(&params.resolve_id, &pool).await?;
+        }
+
+        if flags.export_rel_issues  // write out the relationship issues found when the src data was processed
+        {
+            export::export_relationship_issues(&params.output_folder, &pool).await?;
         }
 
         if test_run {
             summarise::smm_helper::delete_any_existing_data(&"v99".to_string(), &pool).await?; // Clear any test data from the smm tables.
+            summarise::smm_version_registry::remove_version("v99", &pool).await?;
+            import::remove_import_record("v99", &pool).await?;
         }
 
 
@@ -6,11 +6,25 @@ mod src_data_importer;
 mod src_data_processor;
 mod src_create_tables;
 mod src_rmv_dup_names;
+mod src_dup_name_rules;
+mod src_category_mapper;
+mod src_script_detector;
+mod src_lang_codes;
+mod src_hierarchy;
+mod src_relationship_issues;
+mod src_successor_map;
 
 
 use log::{info, error};
 use sqlx::{Pool, Postgres};
+use std::path::PathBuf;
 use crate::AppError;
+use crate::error_defs::CustomError;
+use crate::ror_id::RorId;
+use crate::setup::data_version::DataVersion;
+use crate::setup::UnmappedCategories;
+
+pub use src_successor_map::{ActiveResolution, resolve_to_active};
 
 
 pub async fn create_src_tables(pool : &Pool<Postgres>) -> Result<(), AppError>
@@ -33,12 +47,13 @@ pub async fn create_src_tables(pool : &Pool<Postgres>) -> Result<(), AppError>
 
 }
 
-pub async fn process_data(data_version: &String, pool : &Pool<Postgres>) -> Result<(), AppError>
+pub async fn process_data(data_version: &Option<DataVersion>, unmapped_categories: UnmappedCategories, 
+                          dup_name_rules_path: &PathBuf, pool : &Pool<Postgres>) -> Result<(), AppError>
 {
 
     // Import the data from ror schema to src schema.
 
-    match src_data_importer::import_data(data_version, pool).await
+    match src_data_importer::import_data(data_version, unmapped_categories, dup_name_rules_path, pool).await
     {
         Ok(()) => {
             info!("Data imported from ror to src tables"); 
@@ -64,16 +79,106 @@ pub async fn process_data(data_version: &String, pool : &Pool<Postgres>) -> Resu
 
     // Add the script codes to the names.
 
-    match src_data_processor::add_script_codes(pool).await
+    match src_script_detector::add_script_codes(pool).await
     {
         Ok(()) => {
             info!("Script codes added to organisation names"); 
         },
         Err(e) => {
             error!("An error occured while adding the script codes: {}", e);
-            return Err(AppError::SqErr(e))
+            return Err(e)
+            },
+    }
+
+    // Build the closure table of the parent-child hierarchies.
+
+    match src_hierarchy::store_hierarchy(pool).await
+    {
+        Ok(()) => {
+            info!("Organisation hierarchies stored"); 
+        },
+        Err(e) => {
+            error!("An error occured while building the organisation hierarchies: {}", e);
+            return Err(e)
+            },
+    }
+
+    // List the relationship records needing repair.
+
+    match src_relationship_issues::store_relationship_issues(pool).await
+    {
+        Ok(()) => {
+            info!("Relationship issues identified"); 
+        },
+        Err(e) => {
+            error!("An error occured while identifying the relationship issues: {}", e);
+            return Err(e)
+            },
+    }
+
+    // Resolve the inactive and withdrawn organisations to their active successors.
+
+    match src_successor_map::store_successor_map(pool).await
+    {
+        Ok(()) => {
+            info!("Successor map stored"); 
+        },
+        Err(e) => {
+            error!("An error occured while resolving the successors of inactive organisations: {}", e);
+            return Err(e)
             },
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+pub async fn report_dup_name_rules(dup_name_rules_path: &PathBuf, output_folder: &PathBuf, 
+                                   pool : &Pool<Postgres>) -> Result<(), AppError>
+{
+    // Apply the duplicate name rules to the names in the ror tables, without changing any data.
+
+    match src_rmv_dup_names::report_dup_rules(dup_name_rules_path, output_folder, pool).await
+    {
+        Ok(()) => {
+            info!("Duplicate name rules dry run completed"); 
+        },
+        Err(e) => {
+            error!("An error occured during the duplicate name rules dry run: {}", e);
+            return Err(e)
+            },
+    }
+    Ok(())
+}
+
+
+pub async fn report_active_id(ror_id: &str, pool : &Pool<Postgres>) -> Result<(), AppError>
+{
+    // Log the current active organisation for the given ROR id, which may be
+    // the full url or the final 9 characters (see RorId).
+
+    let id = match RorId::parse(ror_id) {
+        Ok(id) => id,
+        Err(e) => {
+            let msg = format!("'{}' is not a valid ror id: {}", ror_id, e);
+            error!("{}", msg);
+            return Err(AppError::CsErr(CustomError::new(&msg)))
+        },
+    };
+
+    match src_successor_map::resolve_to_active(&id, pool).await
+    {
+        Ok(r) => {
+            match r.active_id {
+                Some(active_id) => info!("{} ({}) resolves to {}, {} hop(s): {}", r.id, r.status, active_id, 
+                                         r.num_hops.unwrap_or_default(), r.path.unwrap_or_default()),
+                None => info!("{} ({}) cannot be resolved to a single active organisation: {}{}", r.id, r.status, 
+                              r.resolution, r.candidates.map(|c| format!(" ({})", c)).unwrap_or_default()),
+            }
+        },
+        Err(e) => {
+            error!("An error occured while resolving {}: {}", ror_id, e);
+            return Err(e)
+            },
+    }
+    Ok(())
+}
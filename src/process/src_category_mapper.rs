@@ -0,0 +1,127 @@
+/***************************************************************************
+ * The category values in the ror data - statuses, org types, name types,
+ * external id types, link types and relationship types - are held as
+ * integer ids in the src tables. The ids are obtained by joining to the
+ * corresponding lup tables, each of which has the value as it appears in
+ * the ror data (ror_value) as well as the name used in the summaries.
+ * Before the data is transferred, any values in the ror tables that are not
+ * in the lup tables are listed in the log, with the number of records
+ * having each. Depending on the 'unmapped_categories' setting they are then
+ * either added to the lup tables, with new ids, or left unmapped, in which
+ * case the records with them are not transferred to the src tables.
+ * A new category in the ror data therefore requires no code change.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use log::{info, warn};
+use crate::AppError;
+use crate::setup::UnmappedCategories;
+
+struct Category {
+    label: &'static str,
+    lup_table: &'static str,
+    ror_table: &'static str,
+    ror_column: &'static str,
+    min_new_id: i32,
+}
+
+// New ids are kept clear of those used for derived rows in the attribute summaries
+// (e.g. 12 and 22 for the non-acronym names, and 100 plus the id for names without a language).
+
+const CATEGORIES: [Category; 6] = [
+    Category { label: "status", lup_table: "ror_status_types", ror_table: "core_data", ror_column: "status", min_new_id: 10 },
+    Category { label: "org type", lup_table: "ror_org_types", ror_table: "type", ror_column: "org_type", min_new_id: 1000 },
+    Category { label: "name type", lup_table: "ror_name_types", ror_table: "names", ror_column: "name_type", min_new_id: 50 },
+    Category { label: "external id type", lup_table: "ror_id_types", ror_table: "external_ids", ror_column: "id_type", min_new_id: 50 },
+    Category { label: "link type", lup_table: "ror_link_types", ror_table: "links", ror_column: "link_type", min_new_id: 50 },
+    Category { label: "relationship type", lup_table: "ror_org_rels", ror_table: "relationships", ror_column: "rel_type", min_new_id: 50 },
+];
+
+
+pub async fn check_categories(policy: UnmappedCategories, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    ensure_ror_values(pool).await?;
+
+    let mut num_unmapped = 0;
+    for cat in CATEGORIES.iter() {
+        let sql = format!(r#"select t.{col}, count(*) from ror.{tbl} t
+                             where t.{col} is not null
+                             and not exists (select 1 from lup.{lup} l where l.ror_value = t.{col})
+                             group by t.{col} order by t.{col}"#,
+                             col = cat.ror_column, tbl = cat.ror_table, lup = cat.lup_table);
+        let unmapped: Vec<(String, i64)> = sqlx::query_as(&sql).fetch_all(pool).await?;
+
+        for (value, number) in unmapped {
+            num_unmapped += 1;
+            match policy {
+                UnmappedCategories::Add => {
+                    let id = add_category_value(cat, &value, pool).await?;
+                    warn!("Unrecognised {} '{}' ({} records) - added to lup.{} with id {}",
+                          cat.label, value, number, cat.lup_table, id);
+                },
+                UnmappedCategories::Reject => {
+                    warn!("Unrecognised {} '{}' ({} records) - these records are not transferred to the src tables",
+                          cat.label, value, number);
+                },
+            }
+        }
+    }
+    if num_unmapped == 0 {
+        info!("All category values in the ror data are in the lup tables");
+    }
+    Ok(())
+}
+
+
+pub async fn ensure_ror_values(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // Lookup tables created before the ror values were added are brought up to date. Apart
+    // from the relationship types ('has parent' etc.) the names are the same as the values.
+
+    let mut sql = "SET client_min_messages TO WARNING;\n".to_string();
+    for cat in CATEGORIES.iter() {
+        sql += &format!("alter table lup.{} add column if not exists ror_value varchar;\n", cat.lup_table);
+    }
+    sql += r#"update lup.ror_org_rels set ror_value = split_part(name, ' ', 2) where ror_value is null and name like '% %';"#;
+    for cat in CATEGORIES.iter() {
+        sql += &format!("\nupdate lup.{} set ror_value = name where ror_value is null;", cat.lup_table);
+    }
+    sql += "\nSET client_min_messages TO NOTICE;";
+    sqlx::raw_sql(&sql).execute(pool).await?;
+    Ok(())
+}
+
+
+async fn add_category_value(cat: &Category, value: &str, pool: &Pool<Postgres>) -> Result<i32, AppError> {
+
+    let sql = format!("select max(id) from lup.{}", cat.lup_table);
+    let max_id: Option<i32> = sqlx::query_scalar(&sql).fetch_one(pool).await?;
+    let id = next_category_id(max_id, cat.min_new_id);
+
+    let sql = format!("insert into lup.{}(id, name, ror_value) values ($1, $2, $2)", cat.lup_table);
+    sqlx::query(&sql).bind(id).bind(value).execute(pool).await?;
+    Ok(id)
+}
+
+
+fn next_category_id(max_id: Option<i32>, min_new_id: i32) -> i32 {
+    match max_id {
+        Some(m) if m >= min_new_id => m + 1,
+        _ => min_new_id,
+    }
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_next_category_id() {
+        assert_eq!(next_category_id(Some(900), 1000), 1000);
+        assert_eq!(next_category_id(Some(1000), 1000), 1001);
+        assert_eq!(next_category_id(None, 50), 50);
+    }
+}
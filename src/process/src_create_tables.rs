@@ -38,6 +38,8 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
         , is_ror_name       bool        not null default false
         , lang_code         varchar     null
         , script_code       varchar     null
+        , script_codes      varchar[]   null
+        , is_mixed_script   bool        not null default false
     );
     create index names_idx on src.names(id);
         
@@ -50,6 +52,8 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
         , dup_type          varchar     not null
         , is_ror_name       bool        null
         , lang_code         varchar     null
+        , num_names         int         not null default 0
+        , num_kept          int         not null default 0
     );
     create index dup_names_idx on src.dup_names(id);
 
@@ -62,8 +66,10 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
         , dup_type          varchar     not null
         , is_ror_name       bool        null
         , lang_code         varchar     null
+        , dup_group         varchar     null
+        , rule_name         varchar     null
     );
-    create index dup_names_deleted_idx on src.dup_names(id);
+    create index dup_names_deleted_idx on src.dup_names_deleted(id);
 
     drop table if exists src.locations;
     create table src.locations
@@ -133,6 +139,63 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
     );
     create index domains_idx on src.domains(id);
 
+    drop table if exists src.hierarchy;
+    create table src.hierarchy
+    (
+          ancestor          varchar     not null
+        , descendant        varchar     not null
+        , depth             int         not null
+        , primary key (ancestor, descendant)
+    );
+    create index hierarchy_desc_idx on src.hierarchy(descendant);
+
+    drop table if exists src.hierarchy_paths;
+    create table src.hierarchy_paths
+    (
+          descendant        varchar     not null
+        , depth             int         not null
+        , root_id           varchar     not null
+        , path              varchar     not null
+    );
+    create index hierarchy_paths_desc_idx on src.hierarchy_paths(descendant);
+    create index hierarchy_paths_root_idx on src.hierarchy_paths(root_id);
+
+    drop table if exists src.hierarchy_issues;
+    create table src.hierarchy_issues
+    (
+          id                varchar     not null
+        , issue_type        varchar     not null
+        , details           varchar     null
+    );
+    create index hierarchy_issues_idx on src.hierarchy_issues(id);
+
+    drop table if exists src.relationship_issues;
+    create table src.relationship_issues
+    (
+          id                varchar     not null
+        , ror_name          varchar     not null
+        , rel_type          int         not null
+        , related_id        varchar     not null
+        , related_name      varchar     not null
+        , issue_type        varchar     not null
+        , target_name       varchar     null
+        , suggestion        varchar     not null
+    );
+    create index relationship_issues_idx on src.relationship_issues(id);
+
+    drop table if exists src.successor_map;
+    create table src.successor_map
+    (
+          id                varchar     not null primary key
+        , status            int         not null
+        , resolution        varchar     not null
+        , active_id         varchar     null
+        , num_hops          int         null
+        , path              varchar     null
+        , candidates        varchar     null
+        , has_cycle         bool        not null default false
+    );
+
     SET client_min_messages TO NOTICE;"#;
 
     sqlx::raw_sql(sql).execute(pool).await?;
@@ -173,10 +236,10 @@ pub async fn create_admin_data_table(pool: &Pool<Postgres>) -> Result<(), AppErr
         , n_sucrels         int         not null default 0
         , n_predrels        int         not null default 0
         , n_doms            int         not null default 0
-        , created           date        not null
-        , cr_schema         varchar     not null
-        , last_modified     date        not null
-        , lm_schema         varchar     not null  
+        , created           date        null
+        , cr_schema         varchar     null
+        , last_modified     date        null
+        , lm_schema         varchar     null  
     );"#;
 
     sqlx::raw_sql(sql).execute(pool).await?;
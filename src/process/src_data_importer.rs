@@ -1,17 +1,28 @@
 use sqlx::{Pool, Postgres};
+use std::path::PathBuf;
 use log::{info, error};
 use crate::error_defs::{AppError, CustomError};
-use super::src_rmv_dup_names;
+use crate::setup::data_version::DataVersion;
+use crate::setup::UnmappedCategories;
+use super::{src_rmv_dup_names, src_category_mapper, src_lang_codes};
 
-pub async fn import_data (data_version: &String, pool: &Pool<Postgres>) -> Result<(), AppError> {
+pub async fn import_data (data_version: &Option<DataVersion>, unmapped_categories: UnmappedCategories, 
+                          dup_name_rules_path: &PathBuf, pool: &Pool<Postgres>) -> Result<(), AppError> {
 
     check_data_version_matches_ror_schema_data(data_version, pool).await?;
 
+    // Category values are mapped to ids by joining to the lup tables, so any values 
+    // not in those tables are dealt with first - see src_category_mapper.
+
+    src_category_mapper::check_categories(unmapped_categories, pool).await?;
+
     execute_sql(get_version_details_sql(), pool).await?;
     execute_sql(get_import_names_sql(), pool).await?;
     info!("Name data transferred to src table");
     
-    src_rmv_dup_names::remove_dups(pool).await?;  // done here to prevent PK errors in core_data
+    src_lang_codes::replace_lang_codes(pool).await?;  // before the duplicates are identified
+    src_rmv_dup_names::remove_dups(dup_name_rules_path, pool).await?;  // done here to prevent PK errors in core_data
+    src_lang_codes::check_lang_codes(pool).await?;
     
     execute_sql(get_core_data_sql(), pool).await?;
     execute_sql(update_core_data_sql(), pool).await?;
@@ -31,11 +42,12 @@ pub async fn import_data (data_version: &String, pool: &Pool<Postgres>) -> Resul
     Ok(())
 }
 
-async fn check_data_version_matches_ror_schema_data(data_version: &String, pool: &Pool<Postgres>)-> Result<(), AppError> {
+async fn check_data_version_matches_ror_schema_data(data_version: &Option<DataVersion>, pool: &Pool<Postgres>)-> Result<(), AppError> {
     
     let sql = "select version from ror.version_details";
     let stored_version: String  = sqlx::query_scalar(sql).fetch_one(pool).await?;
-    if stored_version != data_version.to_string()
+    let data_version = data_version.as_ref().map(|v| v.code()).unwrap_or_default();
+    if stored_version != data_version
     {
         let mut msg = format!("\n\nThe version specified ({}), does not match \nthe data stored in the ror schema ({}).\n\n",
                                data_version, stored_version);
@@ -68,19 +80,15 @@ fn get_version_details_sql <'a>() -> &'a str {
 fn get_import_names_sql <'a>() -> &'a str {
         r#"insert into src.names(id, value, name_type, 
         is_ror_name, lang_code)
-        select id, value, 
-        case 
-            when name_type = 'alias' then 7
-            when name_type = 'acronym' then 10
-            when name_type = 'label' then 5
-            else 0
-        end,
+        select a.id, a.value, t.id,
         case
-            when is_ror_name = true then true
+            when a.is_ror_name = true then true
             else false
         end, 
-        lang
-        from ror.names"#
+        a.lang
+        from ror.names a
+        inner join lup.ror_name_types t
+        on a.name_type = t.ror_value"#
 }
 
 fn get_core_data_sql <'a>() -> &'a str {
@@ -90,14 +98,10 @@ fn get_core_data_sql <'a>() -> &'a str {
     
         r#"insert into src.core_data (id, ror_full_id, 
         ror_name, status, established)
-        select c.id, c.ror_full_id, m.value, 
-        case 
-            when c.status = 'active' then 1
-            when c.status = 'inactive' then 2
-            when c.status = 'withdrawn' then 3
-        end, 
-        c.established 
+        select c.id, c.ror_full_id, m.value, s.id, c.established 
         from ror.core_data c
+        inner join lup.ror_status_types s
+        on c.status = s.ror_value
         inner join
             (select id, value from src.names where is_ror_name = true) m
         on c.id = m.id;"#
@@ -123,56 +127,36 @@ fn get_admin_data_sql <'a>() -> &'a str {
 
 fn get_links_sql <'a>() -> &'a str  {
         r#"insert into src.links(id, ror_name, link_type, link)
-        select a.id, c.ror_name, 
-        case 
-            when a.link_type = 'wikipedia' then 21
-            when a.link_type = 'website' then 22
-            else 0
-        end, 
-        value
+        select a.id, c.ror_name, t.id, a.value
         from ror.links a
         inner join src.core_data c
-        on a.id = c.id;"#
+        on a.id = c.id
+        inner join lup.ror_link_types t
+        on a.link_type = t.ror_value;"#
 }
 
 fn get_external_ids_sql <'a>() -> &'a str {
         r#"insert into src.external_ids(id, ror_name, id_type, id_value, is_preferred)
-        select a.id, c.ror_name,
-        case 
-            when id_type = 'isni' then 11
-            when id_type = 'wikidata' then 12
-            when id_type = 'grid' then 13
-            when id_type = 'fundref' then 14
-            else 0
-        end,
-        a.id_value, 
+        select a.id, c.ror_name, t.id, a.id_value, 
         case
             when a.is_preferred = true then true
             else false
         end
         from ror.external_ids a
         inner join src.core_data c
-        on a.id = c.id;"#
+        on a.id = c.id
+        inner join lup.ror_id_types t
+        on a.id_type = t.ror_value;"#
 }
 
 fn get_types_sql <'a>() -> &'a str {
         r#"insert into src.type(id, ror_name, org_type)
-        select a.id, c.ror_name, 
-        case 
-            when org_type = 'government' then 100
-            when org_type = 'education' then 200
-            when org_type = 'healthcare' then 300
-            when org_type = 'company' then 400
-            when org_type = 'nonprofit' then 500
-            when org_type = 'funder' then 600
-            when org_type = 'facility' then 700
-            when org_type = 'archive' then 800
-            when org_type = 'other' then 900
-            else 0
-        end
+        select a.id, c.ror_name, t.id
         from ror.type a
         inner join src.core_data c
-        on a.id = c.id;"#
+        on a.id = c.id
+        inner join lup.ror_org_types t
+        on a.org_type = t.ror_value;"#
 }
 
 fn get_locations_sql <'a>() -> &'a str  {
@@ -190,19 +174,12 @@ fn get_locations_sql <'a>() -> &'a str  {
 
 fn get_relationships_sql <'a>() -> &'a str {
         r#"insert into src.relationships(id, ror_name, rel_type, related_id, related_name)
-        select a.id, c.ror_name, 
-        case 
-            when a.rel_type = 'parent' then 1
-            when a.rel_type = 'child' then 2
-            when a.rel_type = 'related' then 3
-            when a.rel_type = 'predecessor' then 4
-            when a.rel_type = 'successor' then 5
-            else 0
-        end, 
-        a.related_id, a.related_label
+        select a.id, c.ror_name, t.id, a.related_id, a.related_label
         from ror.relationships a
         inner join src.core_data c
-        on a.id = c.id;"#
+        on a.id = c.id
+        inner join lup.ror_org_rels t
+        on a.rel_type = t.ror_value;"#
 }
 
 fn get_domains_sql <'a>() -> &'a str {
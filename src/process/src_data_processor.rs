@@ -1,357 +1,123 @@
-use sqlx::{Pool, Postgres};
+/***************************************************************************
+ * Adds to src.admin_data the number of each type of entity linked to each
+ * organisation (names by type, names without language codes, external ids
+ * and links by type, relationships by type, locations and domains) and
+ * whether it is a company. Each src table is read only once, with all the
+ * counts taken from it aggregated into a temporary table, and admin_data is
+ * then updated from those tables in a single statement. All this happens in
+ * one transaction (so on one connection, as the temporary tables require).
+ * The time taken by each step is logged.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres, Transaction};
+use std::time::Instant;
 use crate::AppError;
 use log::{info, error};
 
+// The temporary table for each src table, and the query that aggregates its counts.
+// The totals of external ids and links include any types added to the lup tables.
+
+const AGGREGATES: [(&str, &str); 7] = [
+    ("agg_names", r#"select id, count(*) as n_names,
+        count(*) filter (where name_type = 5) as n_labels,
+        count(*) filter (where name_type = 7) as n_aliases,
+        count(*) filter (where name_type = 10) as n_acronyms,
+        count(*) filter (where name_type <> 10) as n_nacro,
+        count(*) filter (where lang_code is null) as n_names_wolc,
+        count(*) filter (where lang_code is null and name_type <> 10) as n_nacro_wolc
+        from src.names group by id"#),
+    ("agg_types", r#"select id, count(*) as n_types,
+        bool_or(org_type = 400) as is_company
+        from src.type group by id"#),
+    ("agg_ext_ids", r#"select id, count(*) as n_ext_ids,
+        count(*) filter (where id_type = 11) as n_isni,
+        count(*) filter (where id_type = 12) as n_wikidata,
+        count(*) filter (where id_type = 13) as n_grid,
+        count(*) filter (where id_type = 14) as n_fundref
+        from src.external_ids group by id"#),
+    ("agg_links", r#"select id, count(*) as n_links,
+        count(*) filter (where link_type = 21) as n_wikipedia,
+        count(*) filter (where link_type = 22) as n_website
+        from src.links group by id"#),
+    ("agg_locs", r#"select id, count(*) as n_locs
+        from src.locations group by id"#),
+    ("agg_rels", r#"select id,
+        count(*) filter (where rel_type = 1) as n_parrels,
+        count(*) filter (where rel_type = 2) as n_chrels,
+        count(*) filter (where rel_type = 3) as n_relrels,
+        count(*) filter (where rel_type = 4) as n_predrels,
+        count(*) filter (where rel_type = 5) as n_sucrels
+        from src.relationships group by id"#),
+    ("agg_doms", r#"select id, count(*) as n_doms
+        from src.domains group by id"#),
+];
+
 pub async fn store_org_attribute_numbers (pool: &Pool<Postgres>) -> Result<(), AppError> {
         
-    execute_sql(get_name_data_sql(), pool).await?;
-    execute_sql(get_label_data_sql(), pool).await?;
-    execute_sql(get_alias_data_sql(), pool).await?;
-    execute_sql(get_acronym_data_sql(), pool).await?;
-
-    info!("Basic name data summarised in admin data table");
-
-    execute_sql(get_nacro_data_sql(), pool).await?;
-    execute_sql(get_names_wolc_sql(), pool).await?;
-    execute_sql(get_nacro_wolc_sql(), pool).await?;
-
-    info!("Name language code data summarised in admin data table");
-
-    execute_sql(get_companies_sql(), pool).await?;
-    execute_sql(get_types_data_sql(), pool).await?;
-
-    info!("Types data summarised in admin data table");
-    
-    execute_sql(get_isni_data_sql(), pool).await?;
-    execute_sql(get_grid_data_sql(), pool).await?;
-    execute_sql(get_fundref_data_sql(), pool).await?;
-    execute_sql(get_wikidata_data_sql(), pool).await?;
-    execute_sql(get_ext_ids_data_sql(), pool).await?;
-
-    info!("External ID summarised in admin data table");
-
-    execute_sql(get_wikipedia_data_sql(), pool).await?;
-    execute_sql(get_website_data_sql(), pool).await?;
-    execute_sql(get_links_data_sql(), pool).await?;
-
-    info!("Links data summarised in admin data table");
-    
-    execute_sql(get_locations_data_sql(), pool).await?;
-    execute_sql(get_parrels_data_sql(), pool).await?;
-    execute_sql(get_chrels_data_sql(), pool).await?;
-    execute_sql(get_relrels_data_sql(), pool).await?;
-    execute_sql(get_predrels_data_sql(), pool).await?;
-    execute_sql(get_succrels_data_sql(), pool).await?;
-    execute_sql(get_domains_data_sql(), pool).await?;
+    let start = Instant::now();
+    let mut tx = pool.begin().await?;
+
+    for (agg_table, select_sql) in AGGREGATES {
+        let step_start = Instant::now();
+        let sql = format!("create temp table {} on commit drop as {};", agg_table, select_sql);
+        execute_sql(&sql, &mut tx).await?;
+        info!("Counts aggregated into {} in {:.2} secs", agg_table, step_start.elapsed().as_secs_f64());
+    }
 
-    info!("Relationship, location and domain data summarised in admin data table");
+    let step_start = Instant::now();
+    execute_sql(get_admin_data_update_sql(), &mut tx).await?;
+    tx.commit().await?;
+    info!("Attribute numbers written to src.admin_data in {:.2} secs", step_start.elapsed().as_secs_f64());
 
+    info!("All org attributes counted in {:.2} secs", start.elapsed().as_secs_f64());
     Ok(())
 }
 
-async fn execute_sql(sql: &str, pool: &Pool<Postgres>) -> Result<(), AppError> {
-    match sqlx::query(&sql).execute(pool).await
+async fn execute_sql(sql: &str, tx: &mut Transaction<'_, Postgres>) -> Result<(), AppError> {
+    match sqlx::query(sql).execute(&mut **tx).await
     {
         Ok(_) => Ok(()),
         Err(e) => {
             error!("An error occured, {}, while adding attribute numbers to src.admin_data with sql code {}", 
                     e, &sql);
-            return Err(AppError::SqErr(e))
+            Err(AppError::SqErr(e))
         },
     }
 }
 
-fn get_name_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_names = n
-    from (
-        select id, count(id) as n
-        from src.names 
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_label_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_labels = n
-    from (
-        select id, count(id) as n
-        from src.names where name_type = 5
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_alias_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_aliases = n
-    from (
-        select id, count(id) as n
-        from src.names where name_type = 7
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_acronym_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_acronyms = n
-    from (
-        select id, count(id) as n
-        from src.names where name_type = 10
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_nacro_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_nacro = n_names - n_acronyms;"#
-}
-
-fn get_names_wolc_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_names_wolc = n
-    from (
-        select id, count(id) as n
-        from src.names 
-        where lang_code is null
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_nacro_wolc_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_nacro_wolc = n
-    from (
-        select id, count(id) as n
-        from src.names 
-        where lang_code is null and name_type <> 10
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_companies_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set is_company = true
-    from src.type t
-    where ad.id = t.id
-    and t.org_type = 400;"#
-}
-
-fn get_types_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_types = n
-    from (
-        select id, count(id) as n
-        from src.type 
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_isni_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_isni = n
-    from (
-        select id, count(id) as n
-        from src.external_ids 
-        where id_type = 11
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_grid_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_grid = n
-    from (
-        select id, count(id) as n
-        from src.external_ids 
-        where id_type = 13
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_fundref_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_fundref = n
-    from (
-        select id, count(id) as n
-        from src.external_ids 
-        where id_type = 14
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_wikidata_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_wikidata = n
-    from (
-        select id, count(id) as n
-        from src.external_ids 
-        where id_type = 12
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_ext_ids_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data
-    set n_ext_ids = n_isni + n_grid + n_fundref + n_wikidata;"#
-}
-
-fn get_wikipedia_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_wikipedia = n
-    from (
-        select id, count(id) as n
-        from src.links 
-        where link_type = 21
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_website_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_website = n
-    from (
-        select id, count(id) as n
-        from src.links 
-        where link_type = 22
-        group by id) c
-        where ad.id = c.id;"#
-}
-
-fn get_links_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data
-    set n_links = n_wikipedia + n_website"#
-}
-
-
-fn get_locations_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_locs = n
-    from (
-        select id, count(id) as n
-        from src.locations 
-        group by id) c
-    where ad.id = c.id;"#
-}
-    
-fn get_parrels_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_parrels = n
-    from (
-        select id, count(id) as n
-        from src.relationships
-        where rel_type = 1
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_chrels_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_chrels = n
-    from (
-        select id, count(id) as n
-        from src.relationships
-        where rel_type = 2
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_relrels_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_relrels = n
-    from (
-        select id, count(id) as n
-        from src.relationships
-        where rel_type = 3
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_predrels_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_predrels = n
-    from (
-        select id, count(id) as n
-        from src.relationships
-        where rel_type = 4
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_succrels_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_sucrels = n
-    from (
-        select id, count(id) as n
-        from src.relationships
-        where rel_type = 5
-        group by id) c
-    where ad.id = c.id;"#
-}
-
-fn get_domains_data_sql <'a>() -> &'a str {
-    r#"update src.admin_data ad
-    set n_doms = n
-    from (
-        select id, count(id) as n
-        from src.domains 
-    group by id) c
-    where ad.id = c.id;"#
-}
-
-
-pub async fn add_script_codes (pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
-
-    // Examines the names and looks at the Unicode value of its first character. Uses that to 
-    // determine the script (but checks for leading bracket - if present use the second character)
-    
-    #[derive(sqlx::FromRow)]
-    struct Script {
-        code: String,
-        ascii_start: i32, 
-        ascii_end: i32,
-    }
-
-    // Get the Unicode scripts with their ascii code boundaries.
-
-    let sql  = r#"select code, ascii_start, ascii_end
-    from lup.lang_scripts
-    where ascii_end <> 0
-    order by ascii_start;"#;
-    let rows: Vec<Script> = sqlx::query_as(sql).fetch_all(pool).await?;
-    info!("Unicode script characteristics obtained");
-
-    // Update names records by testing against each unicode entry.
-    let mut n = 0;
-    for r in rows {
-        
-        sqlx::query(r#"update src.names
-        set script_code = $1 
-        where ascii(substr(value, 1, 1)) >= $2
-        and   ascii(substr(value, 1, 1)) <= $3"#)
-        .bind(r.code.clone())
-        .bind(r.ascii_start)
-        .bind(r.ascii_end)
-        .execute(pool)
-        .await?;
-        
-        // Correct for any bracketed names
-
-        sqlx::query(r#"update src.names
-        set script_code = $1 
-        where ascii(substr(value, 2, 1)) >= $2
-        and   ascii(substr(value, 2, 1)) <= $3
-        and substr(value, 1, 1) = '('"#)
-        .bind(r.code)
-        .bind(r.ascii_start)
-        .bind(r.ascii_end)
-        .execute(pool)
-        .await?;
-
-        n +=1;
-        if n % 20 == 0 {
-            info!("{} scripts processed...", n.to_string());
-        }
-    }
-
-    Ok(())
+fn get_admin_data_update_sql <'a>() -> &'a str {
+    r#"update src.admin_data ad
+    set n_names = coalesce(nm.n_names, 0),
+    n_labels = coalesce(nm.n_labels, 0),
+    n_aliases = coalesce(nm.n_aliases, 0),
+    n_acronyms = coalesce(nm.n_acronyms, 0),
+    n_nacro = coalesce(nm.n_nacro, 0),
+    n_names_wolc = coalesce(nm.n_names_wolc, 0),
+    n_nacro_wolc = coalesce(nm.n_nacro_wolc, 0),
+    n_types = coalesce(tp.n_types, 0),
+    is_company = coalesce(tp.is_company, false),
+    n_isni = coalesce(ei.n_isni, 0),
+    n_grid = coalesce(ei.n_grid, 0),
+    n_fundref = coalesce(ei.n_fundref, 0),
+    n_wikidata = coalesce(ei.n_wikidata, 0),
+    n_ext_ids = coalesce(ei.n_ext_ids, 0),
+    n_wikipedia = coalesce(lk.n_wikipedia, 0),
+    n_website = coalesce(lk.n_website, 0),
+    n_links = coalesce(lk.n_links, 0),
+    n_locs = coalesce(lc.n_locs, 0),
+    n_parrels = coalesce(rl.n_parrels, 0),
+    n_chrels = coalesce(rl.n_chrels, 0),
+    n_relrels = coalesce(rl.n_relrels, 0),
+    n_predrels = coalesce(rl.n_predrels, 0),
+    n_sucrels = coalesce(rl.n_sucrels, 0),
+    n_doms = coalesce(dm.n_doms, 0)
+    from src.admin_data a
+    left join agg_names nm on a.id = nm.id
+    left join agg_types tp on a.id = tp.id
+    left join agg_ext_ids ei on a.id = ei.id
+    left join agg_links lk on a.id = lk.id
+    left join agg_locs lc on a.id = lc.id
+    left join agg_rels rl on a.id = rl.id
+    left join agg_doms dm on a.id = dm.id
+    where ad.id = a.id;"#
 }
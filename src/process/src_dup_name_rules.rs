@@ -0,0 +1,370 @@
+/***************************************************************************
+ * The rules used to decide which of a set of duplicated names (names of the
+ * same organisation with the same value) is kept. The rules are read from a
+ * json file - by default the dup_name_rules.json file in this folder, which
+ * is built into the program, or another file given by 'dup_name_rules_path'
+ * in the configuration file. Each rule applies to one or more of the
+ * groups of duplicates (listed in DupGroup below) and prefers names by
+ * - whether they are the ror name ("prefer": "ror_name"),
+ * - their name type, in a given order ("prefer": "name_type"), or
+ * - their language code ("prefer": "lang_code"), using an ordered list of
+ *   languages for each country, and a list of the least preferred codes.
+ * The rules are applied in the order listed. Each removes, from each set of
+ * duplicates in its groups, the names it ranks below the best. A set still
+ * having more than one name, with equal ranks, is left for the later rules,
+ * and if none of them resolve it the set is reported as unresolved.
+ ***************************************************************************/
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use serde::Deserialize;
+use crate::error_defs::{AppError, CustomError};
+
+const DEFAULT_RULES: &str = include_str!("dup_name_rules.json");
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DupGroup {
+    NameTypes,
+    LangCodes,
+    NameTypesDiffRorStatus,
+    LangCodesDiffRorStatus,
+}
+
+impl DupGroup {
+
+    pub const ALL: [DupGroup; 4] = [DupGroup::NameTypes, DupGroup::LangCodes,
+                                    DupGroup::NameTypesDiffRorStatus, DupGroup::LangCodesDiffRorStatus];
+
+    // The labels are those used in src.dup_names and in the rules file.
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DupGroup::NameTypes => "2 name types",
+            DupGroup::LangCodes => "2 lang codes",
+            DupGroup::NameTypesDiffRorStatus => "2 name types + diff is_ror_name",
+            DupGroup::LangCodesDiffRorStatus => "2 lang codes + diff is_ror_name",
+        }
+    }
+
+    pub fn deletion_type(&self) -> &'static str {
+        match self {
+            DupGroup::NameTypes | DupGroup::NameTypesDiffRorStatus => "superfluous name_type",
+            DupGroup::LangCodes | DupGroup::LangCodesDiffRorStatus => "superfluous lang_code",
+        }
+    }
+
+    fn from_label(label: &str) -> Option<DupGroup> {
+        DupGroup::ALL.into_iter().find(|g| g.label() == label)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "prefer", rename_all = "snake_case")]
+pub enum Preference {
+    RorName,
+    NameType {
+        name_types: Vec<String>,
+    },
+    LangCode {
+        #[serde(default)]
+        countries: BTreeMap<String, Vec<String>>,
+        #[serde(default)]
+        least_preferred: Vec<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct RawRule {
+    name: String,
+    groups: Vec<String>,
+    #[serde(flatten)]
+    preference: Preference,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawRuleSet {
+    rules: Vec<RawRule>,
+}
+
+#[derive(Debug)]
+pub struct DupNameRule {
+    pub name: String,
+    pub groups: Vec<DupGroup>,
+    pub preference: Preference,
+}
+
+#[derive(Debug)]
+pub struct DupNameRules {
+    pub source: String,
+    pub rules: Vec<DupNameRule>,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct DupName {
+    pub id: String,
+    pub value: String,
+    pub name_type: i32,
+    pub type_name: String,
+    pub lang_code: Option<String>,
+    pub is_ror_name: bool,
+    pub country_code: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct DupSet {
+    pub group: DupGroup,
+    pub names: Vec<usize>,    // indices into the list of names
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Deletion {
+    pub name: usize,
+    pub set: usize,
+    pub rule: usize,
+}
+
+#[derive(Debug)]
+pub struct RulesOutcome {
+    pub deletions: Vec<Deletion>,
+    pub num_kept: Vec<usize>,    // for each set, the number of names remaining
+}
+
+
+impl DupNameRule {
+
+    fn rank(&self, name: &DupName) -> usize {
+
+        // Lower ranks are preferred. Within a set all names belong to the same
+        // organisation, and so have the same country.
+
+        match &self.preference {
+            Preference::RorName => if name.is_ror_name { 0 } else { 1 },
+            Preference::NameType { name_types } => {
+                name_types.iter().position(|t| *t == name.type_name).unwrap_or(name_types.len())
+            },
+            Preference::LangCode { countries, least_preferred } => {
+                let country_langs = name.country_code.as_ref().and_then(|c| countries.get(c));
+                let num_listed = country_langs.map(|v| v.len()).unwrap_or(0);
+                let lang = name.lang_code.as_deref().unwrap_or("");
+                match country_langs.and_then(|v| v.iter().position(|l| l == lang)) {
+                    Some(p) => p,
+                    None => match least_preferred.iter().position(|l| l == lang) {
+                        Some(p) => num_listed + 1 + p,
+                        None => num_listed,
+                    },
+                }
+            },
+        }
+    }
+}
+
+
+impl DupNameRules {
+
+    pub fn load(rules_path: &PathBuf) -> Result<Self, AppError> {
+
+        // An empty path means the built in rules are used.
+
+        if rules_path.as_os_str().is_empty() {
+            return DupNameRules::parse(DEFAULT_RULES, "the default rules");
+        }
+        let json = fs::read_to_string(rules_path)?;
+        DupNameRules::parse(&json, &rules_path.display().to_string())
+    }
+
+    pub fn parse(json: &str, source: &str) -> Result<Self, AppError> {
+
+        let raw: RawRuleSet = serde_json::from_str(json)?;
+        let mut rules = Vec::new();
+        for r in raw.rules {
+            let mut groups = Vec::new();
+            for g in r.groups.iter() {
+                match DupGroup::from_label(g) {
+                    Some(group) => groups.push(group),
+                    None => return Err(rule_error(source, &r.name, &format!("'{}' is not a duplicate group", g))),
+                }
+            }
+            if groups.is_empty() {
+                return Err(rule_error(source, &r.name, "no duplicate groups are listed"));
+            }
+            if let Preference::NameType { name_types } = &r.preference {
+                if name_types.is_empty() {
+                    return Err(rule_error(source, &r.name, "no name types are listed"));
+                }
+            }
+            rules.push(DupNameRule { name: r.name, groups, preference: r.preference });
+        }
+        Ok(DupNameRules { source: source.to_string(), rules })
+    }
+}
+
+fn rule_error(source: &str, rule_name: &str, problem: &str) -> AppError {
+    let msg = format!("The duplicate name rule '{}' in {} is not valid: {}", rule_name, source, problem);
+    AppError::CsErr(CustomError::new(&msg))
+}
+
+
+pub fn find_dup_sets(names: &[DupName]) -> Vec<DupSet> {
+
+    // Names with the same value and language code (but different name types), and names with
+    // the same value and name type (but different language codes), form the sets of duplicates.
+
+    let mut by_value: BTreeMap<(&str, &str), Vec<usize>> = BTreeMap::new();
+    for (i, n) in names.iter().enumerate() {
+        by_value.entry((&n.id, &n.value)).or_default().push(i);
+    }
+
+    let mut sets = Vec::new();
+    for indices in by_value.values() {
+        let mut by_lang: BTreeMap<Option<&str>, Vec<usize>> = BTreeMap::new();
+        let mut by_type: BTreeMap<i32, Vec<usize>> = BTreeMap::new();
+        for i in indices {
+            by_lang.entry(names[*i].lang_code.as_deref()).or_default().push(*i);
+            by_type.entry(names[*i].name_type).or_default().push(*i);
+        }
+        for set_names in by_lang.into_values().filter(|v| v.len() > 1) {
+            let group = match same_ror_status(names, &set_names) {
+                true => DupGroup::NameTypes,
+                false => DupGroup::NameTypesDiffRorStatus,
+            };
+            sets.push(DupSet { group, names: set_names });
+        }
+        for set_names in by_type.into_values().filter(|v| v.len() > 1) {
+            let group = match same_ror_status(names, &set_names) {
+                true => DupGroup::LangCodes,
+                false => DupGroup::LangCodesDiffRorStatus,
+            };
+            sets.push(DupSet { group, names: set_names });
+        }
+    }
+    sets
+}
+
+fn same_ror_status(names: &[DupName], set_names: &[usize]) -> bool {
+    set_names.iter().all(|i| names[*i].is_ror_name == names[set_names[0]].is_ror_name)
+}
+
+
+pub fn apply_rules(rules: &DupNameRules, names: &[DupName], sets: &[DupSet]) -> RulesOutcome {
+
+    let mut deleted = vec![false; names.len()];
+    let mut deletions = Vec::new();
+
+    for (ri, rule) in rules.rules.iter().enumerate() {
+        for (si, set) in sets.iter().enumerate() {
+            if !rule.groups.contains(&set.group) {
+                continue;
+            }
+            let remaining: Vec<usize> = set.names.iter().copied().filter(|i| !deleted[*i]).collect();
+            if remaining.len() < 2 {
+                continue;
+            }
+            let ranks: Vec<usize> = remaining.iter().map(|i| rule.rank(&names[*i])).collect();
+            let best = *ranks.iter().min().unwrap_or(&0);
+            for (i, rank) in remaining.iter().zip(ranks) {
+                if rank > best {
+                    deleted[*i] = true;
+                    deletions.push(Deletion { name: *i, set: si, rule: ri });
+                }
+            }
+        }
+    }
+
+    let num_kept = sets.iter().map(|s| s.names.iter().filter(|i| !deleted[**i]).count()).collect();
+    RulesOutcome { deletions, num_kept }
+}
+
+
+pub fn get_dry_run_report(vcode: &str, rules: &DupNameRules, names: &[DupName],
+                          sets: &[DupSet], outcome: &RulesOutcome) -> Vec<String> {
+
+    let name_line = |n: &DupName| format!("    {:<12}{:<50.50} {:<10}{:<6}{:<6}{}", n.id, n.value, n.type_name,
+                                          n.lang_code.as_deref().unwrap_or("-"),
+                                          n.country_code.as_deref().unwrap_or("-"),
+                                          if n.is_ror_name { "ror name" } else { "" }).trim_end().to_string();
+    let mut lines = vec![format!("Duplicate name rules dry run, for {}, using {}", vcode, rules.source), "".to_string()];
+
+    lines.push(format!("{:<36}{:>8}{:>8}{:>10}{:>12}", "Group", "Sets", "Names", "Deleted", "Unresolved"));
+    for group in DupGroup::ALL {
+        let in_group: Vec<usize> = (0..sets.len()).filter(|s| sets[*s].group == group).collect();
+        let num_names: usize = in_group.iter().map(|s| sets[*s].names.len()).sum();
+        let num_deleted = outcome.deletions.iter().filter(|d| sets[d.set].group == group).count();
+        let num_unresolved = in_group.iter().filter(|s| outcome.num_kept[**s] > 1).count();
+        lines.push(format!("{:<36}{:>8}{:>8}{:>10}{:>12}", group.label(), in_group.len(), num_names, num_deleted, num_unresolved));
+    }
+
+    for (ri, rule) in rules.rules.iter().enumerate() {
+        let rule_deletions: Vec<&Deletion> = outcome.deletions.iter().filter(|d| d.rule == ri).collect();
+        lines.push("".to_string());
+        lines.push(format!("Rule {}: {} - {} name(s) would be deleted", ri + 1, rule.name, rule_deletions.len()));
+        for d in rule_deletions {
+            lines.push(name_line(&names[d.name]));
+        }
+    }
+
+    let unresolved: Vec<usize> = (0..sets.len()).filter(|s| outcome.num_kept[*s] > 1).collect();
+    lines.push("".to_string());
+    lines.push(format!("Unresolved sets - {}", unresolved.len()));
+    for s in unresolved {
+        lines.push(format!("  {}", sets[s].group.label()));
+        for i in sets[s].names.iter() {
+            lines.push(name_line(&names[*i]));
+        }
+    }
+    lines
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(id: &str, value: &str, name_type: i32, lang: Option<&str>, is_ror_name: bool, country: &str) -> DupName {
+        let type_name = match name_type { 5 => "label", 7 => "alias", _ => "acronym" };
+        DupName { id: id.to_string(), value: value.to_string(), name_type, type_name: type_name.to_string(),
+                  lang_code: lang.map(|l| l.to_string()), is_ror_name, country_code: Some(country.to_string()) }
+    }
+
+    #[test]
+    fn check_default_rules_and_invalid_group() {
+        let rules = DupNameRules::load(&PathBuf::new()).unwrap();
+        assert_eq!(rules.rules.len(), 3);
+        assert_eq!(rules.rules[0].groups, vec![DupGroup::NameTypesDiffRorStatus, DupGroup::LangCodesDiffRorStatus]);
+
+        let json = r#"{"rules": [{"name": "r1", "groups": ["3 name types"], "prefer": "ror_name"}]}"#;
+        assert!(DupNameRules::parse(json, "test").is_err());
+        let json = r#"{"rules": [{"name": "r1", "groups": ["2 name types"], "prefer": "script"}]}"#;
+        assert!(DupNameRules::parse(json, "test").is_err());
+    }
+
+    #[test]
+    fn check_rules_resolve_dup_sets() {
+        let rules = DupNameRules::load(&PathBuf::new()).unwrap();
+        let names = vec![
+            name("01", "CSIC", 5, Some("es"), true, "ES"),      // label + alias, same lang
+            name("01", "CSIC", 7, Some("es"), true, "ES"),
+            name("02", "Uni A", 5, Some("gl"), false, "ES"),    // gl and es, in Spain
+            name("02", "Uni A", 5, Some("es"), false, "ES"),
+            name("03", "ETH", 10, None, true, "CH"),            // acronym is the ror name
+            name("03", "ETH", 5, None, false, "CH"),
+            name("04", "Inst B", 7, Some("fr"), false, "BE"),   // fr and nl - no preference
+            name("04", "Inst B", 7, Some("nl"), false, "BE"),
+            name("05", "Single", 5, Some("en"), true, "GB"),
+        ];
+        let sets = find_dup_sets(&names);
+        assert_eq!(sets.len(), 4);
+
+        let outcome = apply_rules(&rules, &names, &sets);
+        let deleted: Vec<usize> = outcome.deletions.iter().map(|d| d.name).collect();
+        assert_eq!(deleted, vec![5, 1, 2]);
+        assert_eq!(outcome.deletions[0].rule, 0);
+        assert_eq!(outcome.num_kept.iter().filter(|k| **k > 1).count(), 1);
+
+        let report = get_dry_run_report("v1.59", &rules, &names, &sets, &outcome);
+        assert!(report.iter().any(|l| l.starts_with("Rule 3: language preference by country - 1 name")));
+        assert!(report.iter().any(|l| l == "Unresolved sets - 1"));
+    }
+}
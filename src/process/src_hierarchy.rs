@@ -0,0 +1,302 @@
+/***************************************************************************
+ * Builds src.hierarchy, a closure table of the parent-child relationships,
+ * so that, for instance, all the units under a university can be listed
+ * with a simple query (where ancestor = '<id>' and depth > 0), without a
+ * recursive CTE. A link is taken from either side of the relationship, i.e.
+ * A 'has parent' B or B 'has child' A, as long as both organisations are in
+ * src.core_data. Each organisation at the top of a hierarchy (with children
+ * but no parent) is a root, and the hierarchy is walked down from each root.
+ * src.hierarchy holds each (ancestor, descendant) pair once - its key - with
+ * the smallest depth of the descendant below that ancestor, and a row for
+ * every organisation in src.core_data as its own ancestor (depth 0), whether
+ * or not it is in a hierarchy. The routes taken are in src.hierarchy_paths,
+ * with a row for every organisation reached by each route, giving the root's
+ * id, the depth below the root and the materialised path (the ids from the
+ * root down, separated by '/'). An organisation with more than one parent is
+ * reached by more than one route, and so has a path for each, as do the
+ * organisations below it. Links that lead back to an organisation already
+ * on the route are cycles - they are not followed, but recorded.
+ * Organisations in a cycle that cannot be reached from any root are walked
+ * from the first of them (by id), which is treated as a root.
+ * Cycles and organisations with several parents are listed in the log and
+ * stored in src.hierarchy_issues.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use log::{info, warn};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use crate::AppError;
+
+
+#[derive(sqlx::FromRow)]
+struct ParentLink {
+    child_id: String,
+    parent_id: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HierarchyRow {
+    pub ancestor: String,
+    pub descendant: String,
+    pub depth: i32,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HierarchyPath {
+    pub descendant: String,
+    pub depth: i32,
+    pub root_id: String,
+    pub path: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Hierarchy {
+    pub rows: Vec<HierarchyRow>,                           // ordered by ancestor, descendant
+    pub paths: Vec<HierarchyPath>,
+    pub num_roots: usize,
+    pub cycles: BTreeSet<Vec<String>>,                     // each starting with its lowest id
+    pub multi_parents: BTreeMap<String, Vec<String>>,      // org id -> parent ids
+}
+
+
+pub fn build_hierarchy(links: &[(String, String)]) -> Hierarchy {
+
+    // Links are (child, parent). Maps are ordered, so that the output is stable.
+
+    let mut children: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    let mut parents: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (child, parent) in links.iter() {
+        children.entry(parent.as_str()).or_default().insert(child.as_str());
+        parents.entry(child.as_str()).or_default().insert(parent.as_str());
+    }
+
+    let mut h = Hierarchy::default();
+    let mut visited: HashSet<&str> = HashSet::new();
+    let mut path: Vec<&str> = Vec::new();
+    let mut closure: BTreeMap<(&str, &str), i32> = BTreeMap::new();
+
+    let roots: Vec<&str> = children.keys().filter(|id| !parents.contains_key(*id)).copied().collect();
+    h.num_roots = roots.len();
+    for root in roots {
+        walk(root, &children, &mut path, &mut visited, &mut closure, &mut h);
+    }
+
+    let unreached: Vec<&str> = parents.keys().copied().collect();
+    for id in unreached {
+        if !visited.contains(id) {
+            walk(id, &children, &mut path, &mut visited, &mut closure, &mut h);
+            h.num_roots += 1;
+        }
+    }
+
+    h.rows = closure.into_iter().map(|((ancestor, descendant), depth)| HierarchyRow {
+        ancestor: ancestor.to_string(),
+        descendant: descendant.to_string(),
+        depth,
+    }).collect();
+
+    for (id, ps) in parents.iter().filter(|(_, ps)| ps.len() > 1) {
+        h.multi_parents.insert(id.to_string(), ps.iter().map(|p| p.to_string()).collect());
+    }
+    h
+}
+
+
+fn walk<'a>(id: &'a str, children: &BTreeMap<&'a str, BTreeSet<&'a str>>, path: &mut Vec<&'a str>,
+            visited: &mut HashSet<&'a str>, closure: &mut BTreeMap<(&'a str, &'a str), i32>, h: &mut Hierarchy) {
+
+    // The path is recorded for every route, but each ancestor of the organisation
+    // is only added to the closure once, with the smallest depth found.
+
+    visited.insert(id);
+    path.push(id);
+    let n = path.len();
+    h.paths.push(HierarchyPath {
+        descendant: id.to_string(),
+        depth: (n - 1) as i32,
+        root_id: path[0].to_string(),
+        path: path.join("/"),
+    });
+    for (i, ancestor) in path.iter().enumerate() {
+        let depth = (n - 1 - i) as i32;
+        closure.entry((ancestor, id)).and_modify(|d| *d = (*d).min(depth)).or_insert(depth);
+    }
+
+    if let Some(cs) = children.get(id) {
+        for child in cs.iter() {
+            match path.iter().position(|p| p == child) {
+                Some(pos) => {
+                    let mut cycle: Vec<String> = path[pos..].iter().map(|p| p.to_string()).collect();
+                    let min_pos = cycle.iter().enumerate().min_by_key(|(_, c)| *c).map(|(i, _)| i).unwrap_or(0);
+                    cycle.rotate_left(min_pos);
+                    h.cycles.insert(cycle);
+                },
+                None => walk(child, children, path, visited, closure, h),
+            }
+        }
+    }
+    path.pop();
+}
+
+
+pub async fn store_hierarchy(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let sql = r#"select r.id as child_id, r.related_id as parent_id
+    from src.relationships r
+    inner join src.core_data c1 on r.id = c1.id
+    inner join src.core_data c2 on r.related_id = c2.id
+    where r.rel_type = 1
+    union
+    select r.related_id as child_id, r.id as parent_id
+    from src.relationships r
+    inner join src.core_data c1 on r.id = c1.id
+    inner join src.core_data c2 on r.related_id = c2.id
+    where r.rel_type = 2"#;
+
+    let links: Vec<ParentLink> = sqlx::query_as(sql).fetch_all(pool).await?;
+    let links: Vec<(String, String)> = links.into_iter().map(|l| (l.child_id, l.parent_id)).collect();
+    let h = build_hierarchy(&links);
+
+    let mut tx = pool.begin().await?;
+
+    let mut ancestors = Vec::with_capacity(h.rows.len());
+    let mut descendants = Vec::with_capacity(h.rows.len());
+    let mut depths = Vec::with_capacity(h.rows.len());
+    for r in h.rows.iter() {
+        ancestors.push(r.ancestor.as_str());
+        descendants.push(r.descendant.as_str());
+        depths.push(r.depth);
+    }
+
+    let sql = r#"insert into src.hierarchy (ancestor, descendant, depth)
+                 select * from unnest($1::varchar[], $2::varchar[], $3::int[])"#;
+    sqlx::query(sql).bind(&ancestors).bind(&descendants).bind(&depths)
+    .execute(&mut *tx).await?;
+
+    // Organisations not in any hierarchy are their own (and only) ancestor.
+
+    let sql = r#"insert into src.hierarchy (ancestor, descendant, depth)
+                 select c.id, c.id, 0 from src.core_data c
+                 where not exists (select 1 from src.hierarchy h where h.ancestor = c.id and h.descendant = c.id)"#;
+    let res = sqlx::query(sql).execute(&mut *tx).await?;
+    let num_unlinked = res.rows_affected();
+
+    let mut descendants = Vec::with_capacity(h.paths.len());
+    let mut depths = Vec::with_capacity(h.paths.len());
+    let mut root_ids = Vec::with_capacity(h.paths.len());
+    let mut paths = Vec::with_capacity(h.paths.len());
+    for p in h.paths.iter() {
+        descendants.push(p.descendant.as_str());
+        depths.push(p.depth);
+        root_ids.push(p.root_id.as_str());
+        paths.push(p.path.as_str());
+    }
+
+    let sql = r#"insert into src.hierarchy_paths (descendant, depth, root_id, path)
+                 select * from unnest($1::varchar[], $2::int[], $3::varchar[], $4::varchar[])"#;
+    sqlx::query(sql).bind(&descendants).bind(&depths).bind(&root_ids).bind(&paths)
+    .execute(&mut *tx).await?;
+
+    // Each organisation in a cycle has the cycle recorded against it.
+
+    let mut ids = Vec::new();
+    let mut issue_types = Vec::new();
+    let mut details = Vec::new();
+    for cycle in h.cycles.iter() {
+        let cycle_string = format!("{}/{}", cycle.join("/"), cycle[0]);
+        warn!("Parent-child cycle found: {}", cycle_string);
+        for id in cycle.iter() {
+            ids.push(id.clone());
+            issue_types.push("cycle");
+            details.push(cycle_string.clone());
+        }
+    }
+    for (id, ps) in h.multi_parents.iter() {
+        ids.push(id.clone());
+        issue_types.push("multiple parents");
+        details.push(ps.join(", "));
+    }
+
+    let sql = r#"insert into src.hierarchy_issues (id, issue_type, details)
+                 select * from unnest($1::varchar[], $2::varchar[], $3::varchar[])"#;
+    sqlx::query(sql).bind(&ids).bind(&issue_types).bind(&details)
+    .execute(&mut *tx).await?;
+
+    tx.commit().await?;
+
+    info!("{} hierarchy rows stored, for {} hierarchies, and {} for organisations not in a hierarchy",
+           h.rows.len(), h.num_roots, num_unlinked);
+    info!("{} hierarchy paths stored", h.paths.len());
+    info!("{} parent-child cycles found, {} organisations with more than one parent",
+           h.cycles.len(), h.multi_parents.len());
+    Ok(())
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(c, p)| (c.to_string(), p.to_string())).collect()
+    }
+
+    #[test]
+    fn check_closure_rows_and_multiple_parents() {
+
+        // u has units a and b, a has unit c, which is also a unit of v
+
+        let h = build_hierarchy(&links(&[("a", "u"), ("b", "u"), ("c", "a"), ("c", "v")]));
+        assert_eq!(h.num_roots, 2);
+        assert!(h.cycles.is_empty());
+        assert_eq!(h.multi_parents.get("c"), Some(&vec!["a".to_string(), "v".to_string()]));
+
+        let under_u: BTreeSet<&str> = h.rows.iter().filter(|r| r.ancestor == "u" && r.depth > 0)
+            .map(|r| r.descendant.as_str()).collect();
+        assert_eq!(under_u, BTreeSet::from(["a", "b", "c"]));
+
+        let c_rows: Vec<&HierarchyRow> = h.rows.iter().filter(|r| r.descendant == "c").collect();
+        assert_eq!(c_rows.len(), 4);     // c itself, a, u and v
+        assert!(c_rows.iter().any(|r| r.ancestor == "u" && r.depth == 2));
+        assert!(c_rows.iter().any(|r| r.ancestor == "v" && r.depth == 1));
+
+        let c_paths: Vec<&str> = h.paths.iter().filter(|p| p.descendant == "c").map(|p| p.path.as_str()).collect();
+        assert_eq!(c_paths, vec!["u/a/c", "v/c"]);
+        assert!(h.paths.iter().any(|p| p.descendant == "c" && p.root_id == "v" && p.depth == 1));
+    }
+
+    #[test]
+    fn check_pairs_reached_by_several_routes_stored_once() {
+
+        // d is under both b and c, which are both under a, and also directly under a
+
+        let h = build_hierarchy(&links(&[("b", "a"), ("c", "a"), ("d", "b"), ("d", "c"), ("d", "a"), ("e", "d")]));
+        let mut keys: Vec<(&str, &str)> = h.rows.iter().map(|r| (r.ancestor.as_str(), r.descendant.as_str())).collect();
+        let num_rows = keys.len();
+        keys.dedup();
+        assert_eq!(keys.len(), num_rows);
+
+        let a_to_e: Vec<&HierarchyRow> = h.rows.iter().filter(|r| r.ancestor == "a" && r.descendant == "e").collect();
+        assert_eq!(a_to_e.len(), 1);
+        assert_eq!(a_to_e[0].depth, 2);     // the shortest route, a/d/e
+        assert_eq!(h.paths.iter().filter(|p| p.descendant == "e").count(), 3);
+    }
+
+    #[test]
+    fn check_cycles_are_found_and_not_followed() {
+
+        // r has unit x, and x, y and z form a cycle, as do p and q (unreachable from any root)
+
+        let h = build_hierarchy(&links(&[("x", "r"), ("y", "x"), ("z", "y"), ("x", "z"),
+                                         ("q", "p"), ("p", "q")]));
+        let cycles: Vec<Vec<String>> = h.cycles.iter().cloned().collect();
+        assert_eq!(cycles, vec![vec!["p".to_string(), "q".to_string()],
+                                vec!["x".to_string(), "y".to_string(), "z".to_string()]]);
+        assert_eq!(h.num_roots, 2);
+        assert!(h.paths.iter().any(|p| p.descendant == "z" && p.path == "r/x/y/z"));
+        assert!(h.paths.iter().any(|p| p.descendant == "q" && p.root_id == "p" && p.depth == 1));
+        assert!(!h.rows.iter().any(|r| r.ancestor == "z" && r.descendant == "x"));
+        assert_eq!(h.multi_parents.len(), 1);     // x has parents r and z
+    }
+}
@@ -0,0 +1,105 @@
+/***************************************************************************
+ * Any language code of the names listed in lup.lang_code_replacements (e.g.
+ * 'sh' for Serbo-Croatian, replaced by 'sr') is replaced by its current
+ * equivalent as soon as the names are in src.names, before the duplicate
+ * names are identified, so that names differing only in an old and a new
+ * form of the same code are treated as duplicates. A replacement that makes
+ * a name identical to another of the organisation's names leaves only one of
+ * the two (the ror name, if either is). Once the duplicate names have been
+ * removed the codes are checked against lup.lang_codes, which is loaded from
+ * the ISO 639 data that comes with the program. Any code that remains
+ * unknown is then listed in the log, with the number of names having it,
+ * so that new codes in a ROR release are seen immediately. Those names are
+ * also counted in the singletons when the version is summarised.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use log::{info, warn};
+use crate::AppError;
+use crate::setup;
+
+
+#[derive(sqlx::FromRow)]
+struct CodeCount {
+    old_code: String,
+    new_code: String,
+    num: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct UnknownCode {
+    lang_code: String,
+    num: i64,
+}
+
+
+pub async fn ensure_replacements_table(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // Databases set up before the replacements table was added have it created here.
+
+    let sql = "select to_regclass('lup.lang_code_replacements') is not null";
+    let table_exists: bool = sqlx::query_scalar(sql).fetch_one(pool).await?;
+    if !table_exists {
+        setup::create_lang_code_replacements(pool).await?;
+    }
+    Ok(())
+}
+
+
+pub async fn replace_lang_codes(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    ensure_replacements_table(pool).await?;
+
+    let sql = r#"with r as
+        (update src.names n
+        set lang_code = c.new_code
+        from lup.lang_code_replacements c
+        where n.lang_code = c.old_code
+        returning c.old_code, c.new_code)
+    select old_code, new_code, count(*) as num
+    from r
+    group by old_code, new_code
+    order by old_code"#;
+
+    let replaced: Vec<CodeCount> = sqlx::query_as(sql).fetch_all(pool).await?;
+    for r in replaced.iter() {
+        info!("Language code '{}' replaced by '{}' in {} names", r.old_code, r.new_code, r.num);
+    }
+    if replaced.is_empty() {
+        info!("No names found with language codes requiring replacement");
+        return Ok(())
+    }
+
+    // Names made identical by the replacements are reduced to one.
+
+    let sql = r#"delete from src.names a
+    using src.names b
+    where a.id = b.id and a.value = b.value and a.name_type = b.name_type
+    and a.lang_code is not distinct from b.lang_code
+    and (b.is_ror_name, a.ctid) > (a.is_ror_name, b.ctid)"#;
+    let res = sqlx::query(sql).execute(pool).await?;
+    if res.rows_affected() > 0 {
+        info!("{} names identical to another name after their language code was replaced have been removed", res.rows_affected());
+    }
+    Ok(())
+}
+
+
+pub async fn check_lang_codes(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let sql = r#"select n.lang_code, count(*) as num
+    from src.names n
+    where n.lang_code is not null
+    and not exists (select 1 from lup.lang_codes c where c.code = n.lang_code)
+    group by n.lang_code
+    order by count(*) desc, n.lang_code"#;
+
+    let unknown: Vec<UnknownCode> = sqlx::query_as(sql).fetch_all(pool).await?;
+    for u in unknown.iter() {
+        warn!("Language code '{}', used in {} names, is not in lup.lang_codes", u.lang_code, u.num);
+    }
+    if unknown.is_empty() {
+        info!("All language codes of names found in lup.lang_codes");
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+/***************************************************************************
+ * Lists, in src.relationship_issues, each relationship record that needs
+ * attention, with a suggested repair, so that curation requests can be made
+ * to ROR. Three types of issue are identified:
+ * - 'missing reciprocal', where the related organisation does not have the
+ *   matching link back, e.g. A 'has parent' B but B does not 'have child' A
+ *   (parent and child, predecessor and successor, and related links are
+ *   matched with each other),
+ * - 'missing target', where the related id is not in src.core_data, and
+ * - 'label mismatch', where the label held with the link differs from the
+ *   related organisation's current ror name.
+ * The numbers of each type are logged. The issues can be written out as a
+ * csv file using the --rel-issues flag (see export_rel_issues).
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use log::{info, warn};
+use crate::AppError;
+
+
+#[derive(sqlx::FromRow)]
+struct IssueCount {
+    issue_type: String,
+    num: i64,
+}
+
+
+pub async fn store_relationship_issues(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    sqlx::query(get_missing_reciprocals_sql()).execute(pool).await?;
+    sqlx::query(get_missing_targets_sql()).execute(pool).await?;
+    sqlx::query(get_label_mismatches_sql()).execute(pool).await?;
+
+    let sql = r#"select issue_type, count(*) as num
+    from src.relationship_issues
+    group by issue_type
+    order by issue_type"#;
+    let counts: Vec<IssueCount> = sqlx::query_as(sql).fetch_all(pool).await?;
+    for c in counts.iter() {
+        warn!("{} relationship records with issue '{}'", c.num, c.issue_type);
+    }
+    if counts.is_empty() {
+        info!("No relationship issues found");
+    }
+    Ok(())
+}
+
+
+fn get_missing_reciprocals_sql <'a>() -> &'a str {
+
+    // Each relationship type paired with its reciprocal.
+
+    r#"insert into src.relationship_issues (id, ror_name, rel_type, related_id, related_name,
+    issue_type, target_name, suggestion)
+    select r.id, r.ror_name, r.rel_type, r.related_id, r.related_name,
+    'missing reciprocal', c.ror_name,
+    'Add ''' || t.name || ''' link from ' || r.related_id || ' to ' || r.id
+    from src.relationships r
+    inner join (values (1, 2), (2, 1), (3, 3), (4, 5), (5, 4)) as p(rel_type, recip_type)
+    on r.rel_type = p.rel_type
+    inner join lup.ror_org_rels t
+    on p.recip_type = t.id
+    inner join src.core_data c
+    on r.related_id = c.id
+    where not exists
+        (select 1 from src.relationships r2
+        where r2.id = r.related_id
+        and r2.related_id = r.id
+        and r2.rel_type = p.recip_type)
+    order by r.id, r.rel_type, r.related_id;"#
+}
+
+fn get_missing_targets_sql <'a>() -> &'a str {
+    r#"insert into src.relationship_issues (id, ror_name, rel_type, related_id, related_name,
+    issue_type, target_name, suggestion)
+    select r.id, r.ror_name, r.rel_type, r.related_id, r.related_name,
+    'missing target', null,
+    'Remove link, or correct related id ' || r.related_id || ', which is not in the ROR data'
+    from src.relationships r
+    where not exists (select 1 from src.core_data c where c.id = r.related_id)
+    order by r.id, r.rel_type, r.related_id;"#
+}
+
+fn get_label_mismatches_sql <'a>() -> &'a str {
+    r#"insert into src.relationship_issues (id, ror_name, rel_type, related_id, related_name,
+    issue_type, target_name, suggestion)
+    select r.id, r.ror_name, r.rel_type, r.related_id, r.related_name,
+    'label mismatch', c.ror_name,
+    'Update label of link to ' || r.related_id || ' to ''' || c.ror_name || ''''
+    from src.relationships r
+    inner join src.core_data c
+    on r.related_id = c.id
+    where r.related_name <> c.ror_name
+    order by r.id, r.rel_type, r.related_id;"#
+}
@@ -1,214 +1,228 @@
-use sqlx::{Pool, Postgres};
-use log::{info, error};
+/***************************************************************************
+ * Before further processing the duplicate names need to be removed from
+ * src.names. If this is not done the import to the core data, that follows,
+ * will fail, as some organisations have more than one name marked as the
+ * 'ror name' (the import therefore fails because of a duplicated PK).
+ * No organisation has names that have the same value, name type and language
+ * code, but duplicates exist where an organisation has names with the same
+ * value and lang code, but differ on the name type, or have the same value and
+ * name type, but differ on language code. Both groups include sets with the
+ * same 'is_ror_name' value, and sets with different 'is_ror_name' values.
+ * The sets of duplicates are stored in src.dup_names, which also indicates
+ * the nature of the duplication, and the records deleted in
+ * src.dup_names_deleted, with the rule that caused each deletion. The rules
+ * themselves are described in src_dup_name_rules.
+ * A 'dry run' applies the rules to the names in the ror tables, without
+ * changing any data, and lists what each rule would delete.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres, Transaction};
+use log::{info, warn, error};
+use chrono::Local;
+use std::fs;
+use std::path::PathBuf;
 use crate::AppError;
+use super::src_dup_name_rules::{self, DupGroup, DupName, DupNameRules, DupSet, RulesOutcome};
+use super::{src_category_mapper, src_lang_codes};
 
-pub async fn remove_dups (pool: &Pool<Postgres>) -> Result<(), AppError> {
-    
-    // Before further processing the duplicate names need to be removed from 
-    // src.names. 
-    // If this is not done the import to the core data, that follows, will fail, 
-    // as some organisations have more than one name marked as the 'ror name' (the 
-    // import therefore fails because of a duplicated PK). 
-    // The duplicates are stored separately in src.dup_names, which 
-    // also indicates the nature of the duplication, and the records deleted in 
-    // src.dup_names_deleted.
-
-    // No organisation has names that have the same value, name type and language code,
-    // but duplicates exist where an organisation has names with the same value and lang 
-    // code, but differ on the name type, or have the same value and name type, but differ 
-    // on language code. Both groups include pairs with the same 'is_ror_name' value, and 
-    // pairs with the opposite 'is_ror_name' values.
-
-    // First set up the table of names that are duplicated (same ror id and name value).
-    // Four different sql statements are needed, given the different types of duplication.
-    
-    execute_sql(get_dup_names_with_2_name_types_sql(), pool).await?;                               // group 1
-    execute_sql(get_dup_names_with_2_lang_codes_sql(), pool).await?;                               // group 2
-    execute_sql(get_dup_names_with_2_name_types_and_diff_ror_status_sql(), pool).await?;           // group 3
-    execute_sql(get_dup_names_with_2_lang_codes_and_diff_ror_status_sql(), pool).await?;           // group 4
-
-    info!("Duplicates names identified");
-
-    // Then, for each group, identify the records to be deleted and add then to the 
-    // src.dup_names_deleted table
-
-    execute_sql(get_update_2_name_types_dup_names_with_name_type_info_sql(), pool).await?;         // initial clarification of types involved
-    execute_sql(get_dups_with_2_name_types_5_and_7_to_be_deleted_sql(), pool).await?;              // group 1 (label + alias)
-    execute_sql(get_dups_with_2_name_types_5_and_10_to_be_deleted_sql(), pool).await?;             // group 1 (label + acronym)
-    execute_sql(get_dups_with_2_name_types_7_and_10_to_be_deleted_sql(), pool).await?;             // group 1 (alias + acronym)
-    execute_sql(get_dups_with_2_name_types_and_diff_ror_status_to_be_deleted_sql(), pool).await?;  // group 3
-    execute_sql(get_dups_with_2_lang_codes_and_same_ror_status_to_be_deleted_sql(), pool).await?;  // group 2
-    execute_sql(get_dups_with_2_lang_codes_and_diff_ror_status_to_be_deleted_sql(), pool).await?;  // group 4
-
-    // Finally, use the records in src.dup_names_deleted to delete the specified
-    // records from src.names;
-
-    execute_sql(get_delete_names_with_superfluous_name_type_sql(), pool).await?;
-    execute_sql(get_delete_names_with_superfluous_lang_code_sql(), pool).await?;
-    execute_sql(replace_deprecated_lang_code_sql(), pool).await?;
+
+pub async fn remove_dups (rules_path: &PathBuf, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let rules = DupNameRules::load(rules_path)?;
+    info!("Duplicate name rules read from {}", rules.source);
+
+    let sql = get_dup_names_sql(get_src_names_sql());
+    let names: Vec<DupName> = sqlx::query_as(&sql).fetch_all(pool).await?;
+    let sets = src_dup_name_rules::find_dup_sets(&names);
+    info!("{} sets of duplicated names identified", sets.len());
+
+    let outcome = src_dup_name_rules::apply_rules(&rules, &names, &sets);
+    for (ri, rule) in rules.rules.iter().enumerate() {
+        info!("Rule '{}' removed {} names", rule.name, outcome.deletions.iter().filter(|d| d.rule == ri).count());
+    }
+    for (set, _) in sets.iter().zip(outcome.num_kept.iter()).filter(|(_, kept)| **kept > 1) {
+        let n = &names[set.names[0]];
+        warn!("Duplicated name '{}' of {} ({}) not resolved by any rule", n.value, n.id, set.group.label());
+    }
+
+    // The sets and deletions are stored, and the names deleted, as a single transaction.
+
+    let mut tx = pool.begin().await?;
+    store_dup_sets(&names, &sets, &outcome, &mut tx).await?;
+    store_deletions(&rules, &names, &sets, &outcome, &mut tx).await?;
+    execute_sql(get_delete_names_sql(), &mut tx).await?;
+    tx.commit().await?;
 
     info!("Duplicates transferred from src.names to src.dup_names_deleted table");
     Ok(())
 }
 
-async fn execute_sql(sql: &str, pool: &Pool<Postgres>) -> Result<(), AppError> {
-    match sqlx::query(&sql).execute(pool).await
-    {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            error!("An error occured, {}, in the name duplicates removal code, with the sql {}", 
-                    e, &sql);
-            return Err(AppError::SqErr(e))
-        },
-    }
-}
 
-fn get_dup_names_with_2_name_types_sql <'a>() -> &'a str {
-    r#"insert into src.dup_names (id, value, lang_code, dup_type, is_ror_name)
-    select id, value, lang_code, '2 name types', is_ror_name  from src.names
-    group by id, value, lang_code, is_ror_name having count(id) > 1;"#
-}
+pub async fn report_dup_rules(rules_path: &PathBuf, output_folder: &PathBuf, pool: &Pool<Postgres>) -> Result<(), AppError> {
 
-fn get_dup_names_with_2_lang_codes_sql <'a>() -> &'a str {
-    r#"insert into src.dup_names (id, value, name_type, dup_type, is_ror_name)
-    select id, value, name_type, '2 lang codes', is_ror_name from src.names
-    group by id, value, name_type, is_ror_name  having count(id) > 1;"#
-}
+    let rules = DupNameRules::load(rules_path)?;
+    src_category_mapper::ensure_ror_values(pool).await?;
+    src_lang_codes::ensure_replacements_table(pool).await?;
+
+    let vcode: String = sqlx::query_scalar("select version from ror.version_details").fetch_one(pool).await?;
+    let sql = get_dup_names_sql(get_ror_names_sql());
+    let names: Vec<DupName> = sqlx::query_as(&sql).fetch_all(pool).await?;
+    let sets = src_dup_name_rules::find_dup_sets(&names);
+    let outcome = src_dup_name_rules::apply_rules(&rules, &names, &sets);
 
-fn get_dup_names_with_2_name_types_and_diff_ror_status_sql <'a>() -> &'a str {
-    r#"insert into src.dup_names (id, value, lang_code, dup_type)
-    select g.* from
-    (select id, value, lang_code, '2 name types + diff is_ror_name' from src.names
-    group by id, value, lang_code having count(id) > 1) g
-    left join src.dup_names dn
-    on g.id = dn.id
-    where dn.id is null;"#
+    let lines = src_dup_name_rules::get_dry_run_report(&vcode, &rules, &names, &sets, &outcome);
+    let datetime_string = Local::now().format("%m-%d %H%M%S").to_string();
+    let report_name = format!("{} dup name rules dry run at {}.txt", vcode, datetime_string);
+    let report_path: PathBuf = [output_folder, &PathBuf::from(report_name)].iter().collect();
+    fs::write(&report_path, lines.join("\n") + "\n")?;
+
+    info!("{} sets of duplicated names in {}, {} names would be deleted, {} sets unresolved", sets.len(), vcode,
+          outcome.deletions.len(), outcome.num_kept.iter().filter(|k| **k > 1).count());
+    info!("Dry run report written to {}", report_path.display());
+    Ok(())
 }
 
-fn get_dup_names_with_2_lang_codes_and_diff_ror_status_sql <'a>() -> &'a str {
-    r#"insert into src.dup_names (id, value, name_type, dup_type)
-    select g.* from
-    (select id, value, name_type, '2 lang codes + diff is_ror_name' from src.names
-    group by id, value, name_type  having count(id) > 1) g
-    left join src.dup_names dn
-    on g.id = dn.id
-    where dn.id is null;"#
+
+async fn store_dup_sets(names: &[DupName], sets: &[DupSet], outcome: &RulesOutcome,
+                        tx: &mut Transaction<'_, Postgres>) -> Result<(), AppError> {
+
+    // For the name type groups the shared language code is recorded, for the
+    // language code groups the shared name type, and for both the ror name
+    // status if all the names share it.
+
+    let mut ids = Vec::new();
+    let mut values = Vec::new();
+    let mut name_types: Vec<Option<i32>> = Vec::new();
+    let mut lang_codes: Vec<Option<String>> = Vec::new();
+    let mut dup_types = Vec::new();
+    let mut ror_statuses: Vec<Option<bool>> = Vec::new();
+    let mut num_names = Vec::new();
+    let mut num_kept = Vec::new();
+
+    for (si, set) in sets.iter().enumerate() {
+        let n = &names[set.names[0]];
+        ids.push(n.id.clone());
+        values.push(n.value.clone());
+        match set.group {
+            DupGroup::NameTypes | DupGroup::NameTypesDiffRorStatus => {
+                name_types.push(None);
+                lang_codes.push(n.lang_code.clone());
+            },
+            DupGroup::LangCodes | DupGroup::LangCodesDiffRorStatus => {
+                name_types.push(Some(n.name_type));
+                lang_codes.push(None);
+            },
+        }
+        dup_types.push(set.group.label().to_string());
+        ror_statuses.push(match set.group {
+            DupGroup::NameTypes | DupGroup::LangCodes => Some(n.is_ror_name),
+            _ => None,
+        });
+        num_names.push(set.names.len() as i32);
+        num_kept.push(outcome.num_kept[si] as i32);
+    }
+
+    let sql = r#"insert into src.dup_names (id, value, name_type, lang_code, dup_type, is_ror_name, num_names, num_kept)
+                 select * from unnest($1::varchar[], $2::varchar[], $3::int[], $4::varchar[], $5::varchar[],
+                                      $6::bool[], $7::int[], $8::int[])"#;
+    sqlx::query(sql).bind(&ids).bind(&values).bind(&name_types).bind(&lang_codes)
+    .bind(&dup_types).bind(&ror_statuses).bind(&num_names).bind(&num_kept)
+    .execute(&mut **tx).await?;
+    Ok(())
 }
 
-fn get_update_2_name_types_dup_names_with_name_type_info_sql<'a>() -> &'a str {
 
-    // Allows both name types to be visible in the record of each of the name type pair.
+async fn store_deletions(rules: &DupNameRules, names: &[DupName], sets: &[DupSet], outcome: &RulesOutcome,
+                         tx: &mut Transaction<'_, Postgres>) -> Result<(), AppError> {
+
+    let mut ids = Vec::new();
+    let mut values = Vec::new();
+    let mut name_types = Vec::new();
+    let mut dup_types = Vec::new();
+    let mut ror_statuses = Vec::new();
+    let mut lang_codes: Vec<Option<String>> = Vec::new();
+    let mut dup_groups = Vec::new();
+    let mut rule_names = Vec::new();
+
+    for d in outcome.deletions.iter() {
+        let n = &names[d.name];
+        let group = sets[d.set].group;
+        ids.push(n.id.clone());
+        values.push(n.value.clone());
+        name_types.push(n.name_type);
+        dup_types.push(group.deletion_type().to_string());
+        ror_statuses.push(n.is_ror_name);
+        lang_codes.push(n.lang_code.clone());
+        dup_groups.push(group.label().to_string());
+        rule_names.push(rules.rules[d.rule].name.clone());
+    }
 
-    r#"update src.dup_names dn
-    set dup_type = adn.dup_type
-    from 
-        (select dn.id, dn.value, dn.dup_type||' '||string_agg(n.name_type::text, ', ' order by n.name_type) as dup_type
-        from src.names n inner join src.dup_names dn
-        on  dn.id = n.id and dn.value = n.value
-        where dup_type like '2 name types'
-        group by dn.id, dn.value, dn.dup_type) adn
-    where dn.id = adn.id and dn.value = adn.value;"#
+    let sql = r#"insert into src.dup_names_deleted (id, value, name_type, dup_type, is_ror_name, lang_code, dup_group, rule_name)
+                 select * from unnest($1::varchar[], $2::varchar[], $3::int[], $4::varchar[], $5::bool[],
+                                      $6::varchar[], $7::varchar[], $8::varchar[])"#;
+    sqlx::query(sql).bind(&ids).bind(&values).bind(&name_types).bind(&dup_types)
+    .bind(&ror_statuses).bind(&lang_codes).bind(&dup_groups).bind(&rule_names)
+    .execute(&mut **tx).await?;
+    Ok(())
 }
 
-fn get_dups_with_2_name_types_5_and_7_to_be_deleted_sql<'a>() -> &'a str {
-
-    // The alias is always to be deleted, leaving the label.
-
-    r#"insert into src.dup_names_deleted (id, value, name_type, dup_type, is_ror_name, lang_code)
-    select n.id, n.value, n.name_type, 'superfluous name_type', n.is_ror_name, n.lang_code
-    from src.dup_names dn
-    inner join src.names n
-    on dn.id = n.id and dn.value = n.value
-    where dup_type like '2 name types 5, 7'
-    and n.name_type = 7;"#
-}  
-
-fn get_dups_with_2_name_types_5_and_10_to_be_deleted_sql<'a>() -> &'a str {
-
-    // The acronym is always to be deleted, leaving the label.
-
-    r#"insert into src.dup_names_deleted (id, value, name_type, dup_type, is_ror_name, lang_code)
-    select n.id, n.value, n.name_type, 'superfluous name_type', n.is_ror_name, n.lang_code
-    from src.dup_names dn
-    inner join src.names n
-    on dn.id = n.id and dn.value = n.value
-    where dup_type like '2 name types 5, 10'
-    and n.name_type = 10;"#
-}  
-
-fn get_dups_with_2_name_types_7_and_10_to_be_deleted_sql<'a>() -> &'a str {
-
-    // The alias is always to be deleted, leaving the acronym (these values all appear to be acronyms).
-    
-    r#"insert into src.dup_names_deleted (id, value, name_type, dup_type, is_ror_name, lang_code)
-    select n.id, n.value, n.name_type, 'superfluous name_type', n.is_ror_name, n.lang_code
-    from src.dup_names dn
-    inner join src.names n
-    on dn.id = n.id and dn.value = n.value
-    where dup_type like '2 name types 7, 10'
-    and n.name_type = 7;"#
-}  
-
-fn get_dups_with_2_name_types_and_diff_ror_status_to_be_deleted_sql <'a>() -> &'a str {
-
-    // The non 'ror name' is selected for deletion whatever the name type.
-    
-    r#"insert into src.dup_names_deleted (id, value, name_type, dup_type, is_ror_name, lang_code)
-    select n.id, n.value, n.name_type, 'superfluous name_type', n.is_ror_name, n.lang_code
-    from src.dup_names dn
-    inner join src.names n
-    on dn.id = n.id and dn.value = n.value
-    where dup_type like '2 name types + diff is_ror_name'
-    and n.is_ror_name is false;"#
+
+async fn execute_sql(sql: &str, tx: &mut Transaction<'_, Postgres>) -> Result<(), AppError> {
+    match sqlx::query(sql).execute(&mut **tx).await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("An error occured, {}, in the name duplicates removal code, with the sql {}",
+                    e, &sql);
+            Err(AppError::SqErr(e))
+        },
+    }
 }
 
-fn get_dups_with_2_lang_codes_and_same_ror_status_to_be_deleted_sql <'a>() -> &'a str {
-
-    // The selection of codes to delete was manually determined as being the most 
-    // reasonable, given organisation locations, but are arbitrary.
-    
-    r#"insert into src.dup_names_deleted (id, value, name_type, dup_type, is_ror_name, lang_code)
-    select n.id, n.value, n.name_type, 'superfluous lang_code', n.is_ror_name, n.lang_code
-    from src.dup_names dn
-    inner join src.names n
-    on dn.id = n.id and dn.value = n.value
-    where dup_type like '2 lang codes'
-    and n.lang_code in ('gl', 'nn', 'rm', 'en', 'hr');"#
+
+fn get_src_names_sql <'a>() -> &'a str {
+    r#"select n.id, n.value, n.name_type, t.name as type_name, n.lang_code, n.is_ror_name
+    from src.names n
+    inner join lup.ror_name_types t
+    on n.name_type = t.id"#
 }
 
-fn get_dups_with_2_lang_codes_and_diff_ror_status_to_be_deleted_sql <'a>() -> &'a str {
-
-    // The non 'ror name' is selected for deletion whatever the lang code.
-    
-    r#"insert into src.dup_names_deleted (id, value, name_type, dup_type, is_ror_name, lang_code)
-    select n.id, n.value, n.name_type, 'superfluous lang_code', n.is_ror_name, n.lang_code
-    from src.dup_names dn
-    inner join src.names n
-    on dn.id = n.id and dn.value = n.value
-    where dup_type like '2 lang codes + diff is_ror_name'
-    and n.is_ror_name is false;"#
+fn get_ror_names_sql <'a>() -> &'a str {
+
+    // The names as they would be transferred to src.names, with their language codes replaced.
+
+    r#"select a.id, a.value, t.id as name_type, t.name as type_name, coalesce(c.new_code, a.lang) as lang_code,
+    coalesce(a.is_ror_name, false) as is_ror_name
+    from ror.names a
+    inner join lup.ror_name_types t
+    on a.name_type = t.ror_value
+    left join lup.lang_code_replacements c
+    on a.lang = c.old_code"#
 }
 
-fn get_delete_names_with_superfluous_name_type_sql <'a>() -> &'a str {
-    r#"delete from src.names n
-    using src.dup_names_deleted dnd
-    where dup_type = 'superfluous name_type'
-    and n.id = dnd.id
-    and n.value = dnd.value
-    and n.name_type = dnd.name_type;"#
+fn get_dup_names_sql(names_sql: &str) -> String {
+
+    // All the names of each organisation that share their value with another of its names,
+    // together with the organisation's country (used by the language preferences).
+
+    format!(r#"select n.id, n.value, n.name_type, n.type_name, n.lang_code, n.is_ror_name, l.country_code
+    from ({names}) n
+    inner join
+        (select id, value from ({names}) v
+        group by id, value having count(*) > 1) d
+    on n.id = d.id and n.value = d.value
+    left join
+        (select id, min(country_code) as country_code from ror.locations
+        group by id) l
+    on n.id = l.id
+    order by n.id, n.value, n.name_type, n.lang_code;"#, names = names_sql)
 }
 
-fn get_delete_names_with_superfluous_lang_code_sql <'a>() -> &'a str {
+fn get_delete_names_sql <'a>() -> &'a str {
     r#"delete from src.names n
     using src.dup_names_deleted dnd
-    where dup_type = 'superfluous lang_code'
-    and n.id = dnd.id
+    where n.id = dnd.id
     and n.value = dnd.value
-    and n.lang_code = dnd.lang_code;"#
+    and n.name_type = dnd.name_type
+    and n.lang_code is not distinct from dnd.lang_code;"#
 }
-
-fn replace_deprecated_lang_code_sql <'a>() -> &'a str {
-    r#"update src.names 
-    set lang_code = 'sr'
-    where lang_code = 'sh';"#
-}
\ No newline at end of file
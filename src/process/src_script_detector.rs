@@ -0,0 +1,199 @@
+/***************************************************************************
+ * Determines the script(s) used in each name, by examining every character
+ * of the name against the Unicode ranges in lup.lang_scripts (plus a few
+ * supplementary blocks, listed below, that those ranges do not cover).
+ * Only letters are considered - digits, spaces, punctuation, brackets and
+ * other symbols are common to all scripts. For each name are stored
+ * - the dominant script, i.e. the one with most letters (the first to occur
+ *   if two are equal), or 'Zyyy' (Common) if the name has no letters,
+ * - the list of all the scripts present, in descending order of use, and
+ * - whether the name mixes scripts. Japanese text normally combines Han,
+ *   Hiragana and Katakana, so names using only those are not 'mixed'.
+ * Letters in ranges not recognised at all are ignored. The script depends
+ * only on the name's value, so each distinct value is examined once, and
+ * the results are written back to src.names in a single update.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use log::info;
+use crate::AppError;
+
+// Blocks not included in lup.lang_scripts, with the script to which they belong.
+
+const SUPPLEMENTARY_RANGES: [(&str, u32, u32); 16] = [
+    ("Cyrl", 0x0500, 0x052F),     // Cyrillic Supplement
+    ("Arab", 0x0750, 0x077F),     // Arabic Supplement
+    ("Hang", 0x1100, 0x11FF),     // Hangul Jamo
+    ("Latn", 0x1E00, 0x1EFF),     // Latin Extended Additional (e.g. Vietnamese)
+    ("Grek", 0x1F00, 0x1FFF),     // Greek Extended
+    ("Hani", 0x3005, 0x3007),     // Ideographic iteration mark etc.
+    ("Hang", 0x3130, 0x318F),     // Hangul Compatibility Jamo
+    ("Kana", 0x31F0, 0x31FF),     // Katakana Phonetic Extensions
+    ("Hani", 0x3400, 0x4DBF),     // CJK Unified Ideographs Extension A
+    ("Hani", 0xF900, 0xFAFF),     // CJK Compatibility Ideographs
+    ("Arab", 0xFB50, 0xFDFF),     // Arabic Presentation Forms-A
+    ("Arab", 0xFE70, 0xFEFF),     // Arabic Presentation Forms-B
+    ("Latn", 0xFF21, 0xFF3A),     // Fullwidth Latin capitals
+    ("Latn", 0xFF41, 0xFF5A),     // Fullwidth Latin small letters
+    ("Kana", 0xFF66, 0xFF9F),     // Halfwidth Katakana
+    ("Hani", 0x20000, 0x2A6DF),   // CJK Unified Ideographs Extension B
+];
+
+const JAPANESE_SCRIPTS: [&str; 3] = ["Hani", "Hira", "Kana"];
+
+#[derive(sqlx::FromRow)]
+struct ScriptRow {
+    code: String,
+    ascii_start: i32,
+    ascii_end: i32,
+}
+
+pub struct ScriptTable {
+    ranges: Vec<(u32, u32, String)>,   // start, end, script code - ordered by start
+}
+
+#[derive(Debug, PartialEq)]
+pub struct NameScripts {
+    pub dominant: String,
+    pub scripts: Vec<String>,
+    pub is_mixed: bool,
+}
+
+
+impl ScriptTable {
+
+    pub fn new(lup_ranges: Vec<(String, u32, u32)>) -> Self {
+        let mut ranges: Vec<(u32, u32, String)> = lup_ranges.into_iter()
+            .map(|(code, start, end)| (start, end, code)).collect();
+        for (code, start, end) in SUPPLEMENTARY_RANGES {
+            if !ranges.iter().any(|r| start <= r.1 && end >= r.0) {
+                ranges.push((start, end, code.to_string()));
+            }
+        }
+        ranges.sort_by_key(|r| r.0);
+        ScriptTable { ranges }
+    }
+
+    fn script_of(&self, c: char) -> Option<&str> {
+        let cp = c as u32;
+        let i = self.ranges.partition_point(|r| r.0 <= cp);
+        match i {
+            0 => None,
+            _ => {
+                let r = &self.ranges[i - 1];
+                if cp <= r.1 { Some(r.2.as_str()) } else { None }
+            },
+        }
+    }
+
+    pub fn detect(&self, value: &str) -> NameScripts {
+
+        // Counts are kept in order of first occurrence, so that a stable
+        // sort by count leaves the earlier script first among equals.
+
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        for c in value.chars().filter(|c| c.is_alphabetic()) {
+            if let Some(code) = self.script_of(c) {
+                match counts.iter_mut().find(|(s, _)| *s == code) {
+                    Some(entry) => entry.1 += 1,
+                    None => counts.push((code, 1)),
+                }
+            }
+        }
+        counts.sort_by_key(|c| std::cmp::Reverse(c.1));
+
+        let scripts: Vec<String> = counts.iter().map(|(s, _)| s.to_string()).collect();
+        let is_mixed = scripts.len() > 1
+                       && !scripts.iter().all(|s| JAPANESE_SCRIPTS.contains(&s.as_str()));
+        NameScripts {
+            dominant: scripts.first().cloned().unwrap_or("Zyyy".to_string()),
+            scripts,
+            is_mixed,
+        }
+    }
+}
+
+
+async fn get_script_table(pool: &Pool<Postgres>) -> Result<ScriptTable, AppError> {
+
+    let sql  = r#"select code, ascii_start, ascii_end
+    from lup.lang_scripts
+    where ascii_end <> 0
+    order by ascii_start;"#;
+    let rows: Vec<ScriptRow> = sqlx::query_as(sql).fetch_all(pool).await?;
+    let lup_ranges = rows.into_iter()
+        .map(|r| (r.code, r.ascii_start as u32, r.ascii_end as u32)).collect();
+    Ok(ScriptTable::new(lup_ranges))
+}
+
+
+pub async fn add_script_codes(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let table = get_script_table(pool).await?;
+    info!("Unicode script characteristics obtained");
+
+    let values: Vec<String> = sqlx::query_scalar("select distinct value from src.names").fetch_all(pool).await?;
+    let mut dominants = Vec::with_capacity(values.len());
+    let mut script_lists = Vec::with_capacity(values.len());
+    let mut mixed = Vec::with_capacity(values.len());
+    for v in values.iter() {
+        let ns = table.detect(v);
+        dominants.push(ns.dominant);
+        script_lists.push(ns.scripts.join(" "));
+        mixed.push(ns.is_mixed);
+    }
+    info!("Scripts determined for {} distinct name values", values.len());
+
+    let sql = r#"update src.names n
+                 set script_code = u.code,
+                 script_codes = string_to_array(u.codes, ' '),
+                 is_mixed_script = u.mixed
+                 from unnest($1::varchar[], $2::varchar[], $3::varchar[], $4::bool[]) as u(value, code, codes, mixed)
+                 where n.value = u.value"#;
+    sqlx::query(sql).bind(&values).bind(&dominants).bind(&script_lists).bind(&mixed)
+    .execute(pool).await?;
+    Ok(())
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_table() -> ScriptTable {
+        ScriptTable::new(vec![("Latn".to_string(), 0, 767), ("Grek".to_string(), 880, 1023),
+                              ("Cyrl".to_string(), 1024, 1279), ("Hira".to_string(), 12352, 12447),
+                              ("Kana".to_string(), 12448, 12543), ("Hani".to_string(), 19968, 40959)])
+    }
+
+    #[test]
+    fn check_scripts_use_every_letter() {
+        let t = test_table();
+
+        // leading digits, quotes and brackets are ignored
+        let ns = t.detect("(«Московский» университет)");
+        assert_eq!((ns.dominant.as_str(), ns.is_mixed), ("Cyrl", false));
+        assert_eq!(t.detect("3M Company").dominant, "Latn");
+        assert_eq!(t.detect("1234 - 5678").dominant, "Zyyy");
+
+        let ns = t.detect("Université Παν. Αθηνών Ltd");
+        assert_eq!(ns.scripts, vec!["Latn", "Grek"]);
+        assert!(ns.is_mixed);
+
+        // a Cyrillic 'а' within a Latin name, and Vietnamese from the supplementary ranges
+        assert!(t.detect("Mаrine Institute").is_mixed);
+        assert_eq!(t.detect("Trường Đại học").scripts, vec!["Latn"]);
+    }
+
+    #[test]
+    fn check_japanese_names_not_mixed() {
+        let t = test_table();
+        let ns = t.detect("東京大学のセンター");
+        assert_eq!(ns.dominant, "Hani");
+        assert_eq!(ns.scripts.len(), 3);
+        assert!(!ns.is_mixed);
+        assert!(t.detect("東京 University").is_mixed);
+    }
+}
@@ -0,0 +1,281 @@
+/***************************************************************************
+ * Resolves each inactive or withdrawn organisation to the active
+ * organisation(s) that succeeded it, storing the results in
+ * src.successor_map. Successor links are taken from either side of the
+ * relationship, i.e. A 'has successor' B or B 'has predecessor' A. The
+ * chain of successors is followed, through any number of inactive or
+ * withdrawn organisations, until an active organisation is reached - so
+ * each branch ends at the first active organisation found on it. Each
+ * organisation is then given one of the following resolutions:
+ * - 'resolved', if exactly one active organisation is reached (the number
+ *   of hops and the path, of ids separated by '/', are those of the
+ *   shortest route),
+ * - 'ambiguous', if the chain branches and more than one is reached (all
+ *   are listed as candidates),
+ * - 'no successor', if the organisation has no successor links,
+ * - 'no active successor', if the links lead only to organisations that are
+ *   not active, or not in the data, and
+ * - 'cycle', if no active organisation is reached and the links loop back.
+ * Any cycle met on the way is also flagged with has_cycle, whatever the
+ * resolution. An active organisation resolves to itself, and is not
+ * included in the table. resolve_to_active returns the resolution of any
+ * ROR id in the current src data.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use log::{info, warn};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::AppError;
+use crate::error_defs::CustomError;
+use crate::ror_id::RorId;
+
+
+const ACTIVE: i32 = 1;
+
+#[derive(sqlx::FromRow)]
+struct OrgStatus {
+    id: String,
+    status: i32,
+}
+
+#[derive(sqlx::FromRow)]
+struct SuccessorLink {
+    id: String,
+    successor_id: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SuccessorEntry {
+    pub id: String,
+    pub status: i32,
+    pub resolution: String,
+    pub active_id: Option<String>,
+    pub num_hops: Option<i32>,
+    pub path: Option<String>,
+    pub candidates: Option<String>,
+    pub has_cycle: bool,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct ActiveResolution {
+    pub id: String,
+    pub status: String,
+    pub resolution: String,
+    pub active_id: Option<String>,
+    pub num_hops: Option<i32>,
+    pub path: Option<String>,
+    pub candidates: Option<String>,
+}
+
+
+pub fn map_successors(statuses: &HashMap<String, i32>, links: &[(String, String)]) -> Vec<SuccessorEntry> {
+
+    let mut successors: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (id, successor_id) in links.iter() {
+        successors.entry(id.as_str()).or_default().insert(successor_id.as_str());
+    }
+
+    let mut ids: Vec<(&String, &i32)> = statuses.iter().filter(|(_, s)| **s != ACTIVE).collect();
+    ids.sort();
+
+    let mut entries = Vec::with_capacity(ids.len());
+    for (id, status) in ids {
+        let mut reached: BTreeMap<&str, Vec<&str>> = BTreeMap::new();   // active id -> shortest path
+        let mut has_cycle = false;
+        let mut path = vec![id.as_str()];
+        follow(&mut path, statuses, &successors, &mut reached, &mut has_cycle);
+
+        let (resolution, active_id, num_hops, route, candidates) = match reached.len() {
+            1 => {
+                let (active_id, p) = reached.iter().next().unwrap();
+                ("resolved", Some(active_id.to_string()), Some((p.len() - 1) as i32), Some(p.join("/")), None)
+            },
+            0 => {
+                let resolution = if !successors.contains_key(id.as_str()) { "no successor" }
+                                 else if has_cycle { "cycle" }
+                                 else { "no active successor" };
+                (resolution, None, None, None, None)
+            },
+            _ => {
+                let list: Vec<&str> = reached.keys().copied().collect();
+                ("ambiguous", None, None, None, Some(list.join(", ")))
+            },
+        };
+        entries.push(SuccessorEntry {
+            id: id.clone(),
+            status: *status,
+            resolution: resolution.to_string(),
+            active_id,
+            num_hops,
+            path: route,
+            candidates,
+            has_cycle,
+        });
+    }
+    entries
+}
+
+
+fn follow<'a>(path: &mut Vec<&'a str>, statuses: &HashMap<String, i32>, successors: &BTreeMap<&'a str, BTreeSet<&'a str>>,
+              reached: &mut BTreeMap<&'a str, Vec<&'a str>>, has_cycle: &mut bool) {
+
+    let current = path[path.len() - 1];
+    if let Some(ss) = successors.get(current) {
+        for s in ss.iter() {
+            if path.contains(s) {
+                *has_cycle = true;
+                continue;
+            }
+            path.push(s);
+            match statuses.get(*s) {
+                Some(&ACTIVE) => {
+                    let shorter = reached.get(s).is_none_or(|p| path.len() < p.len());
+                    if shorter {
+                        reached.insert(s, path.clone());
+                    }
+                },
+                Some(_) => follow(path, statuses, successors, reached, has_cycle),
+                None => {},     // not in the data - the branch ends here
+            }
+            path.pop();
+        }
+    }
+}
+
+
+pub async fn store_successor_map(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let orgs: Vec<OrgStatus> = sqlx::query_as("select id, status from src.core_data").fetch_all(pool).await?;
+    let statuses: HashMap<String, i32> = orgs.into_iter().map(|o| (o.id, o.status)).collect();
+
+    let sql = r#"select id, related_id as successor_id
+    from src.relationships
+    where rel_type = 5
+    union
+    select related_id as id, id as successor_id
+    from src.relationships
+    where rel_type = 4"#;
+    let links: Vec<SuccessorLink> = sqlx::query_as(sql).fetch_all(pool).await?;
+    let links: Vec<(String, String)> = links.into_iter().map(|l| (l.id, l.successor_id)).collect();
+
+    let entries = map_successors(&statuses, &links);
+
+    let mut ids = Vec::with_capacity(entries.len());
+    let mut status_ids = Vec::with_capacity(entries.len());
+    let mut resolutions = Vec::with_capacity(entries.len());
+    let mut active_ids = Vec::with_capacity(entries.len());
+    let mut hops = Vec::with_capacity(entries.len());
+    let mut paths = Vec::with_capacity(entries.len());
+    let mut candidates = Vec::with_capacity(entries.len());
+    let mut cycles = Vec::with_capacity(entries.len());
+    for e in entries.iter() {
+        ids.push(e.id.as_str());
+        status_ids.push(e.status);
+        resolutions.push(e.resolution.as_str());
+        active_ids.push(e.active_id.as_deref());
+        hops.push(e.num_hops);
+        paths.push(e.path.as_deref());
+        candidates.push(e.candidates.as_deref());
+        cycles.push(e.has_cycle);
+    }
+
+    let sql = r#"insert into src.successor_map (id, status, resolution, active_id, num_hops, path, candidates, has_cycle)
+                 select * from unnest($1::varchar[], $2::int[], $3::varchar[], $4::varchar[], $5::int[],
+                                      $6::varchar[], $7::varchar[], $8::bool[])"#;
+    sqlx::query(sql).bind(&ids).bind(&status_ids).bind(&resolutions).bind(&active_ids)
+    .bind(&hops).bind(&paths).bind(&candidates).bind(&cycles)
+    .execute(pool).await?;
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for e in entries.iter() {
+        *counts.entry(e.resolution.as_str()).or_default() += 1;
+    }
+    for (resolution, n) in counts.iter() {
+        match *resolution {
+            "ambiguous" | "cycle" => warn!("{} inactive or withdrawn organisations with successor resolution '{}'", n, resolution),
+            _ => info!("{} inactive or withdrawn organisations with successor resolution '{}'", n, resolution),
+        }
+    }
+    Ok(())
+}
+
+
+pub async fn resolve_to_active(ror_id: &RorId, pool: &Pool<Postgres>) -> Result<ActiveResolution, AppError> {
+
+    let sql = r#"select c.id, s.name as status,
+                 coalesce(m.resolution, 'active') as resolution,
+                 case when c.status = 1 then c.id else m.active_id end as active_id,
+                 case when c.status = 1 then 0 else m.num_hops end as num_hops,
+                 case when c.status = 1 then c.id else m.path end as path,
+                 m.candidates
+                 from src.core_data c
+                 inner join lup.ror_status_types s on c.status = s.id
+                 left join src.successor_map m on c.id = m.id
+                 where c.id = $1"#;
+    let res: Option<ActiveResolution> = sqlx::query_as(sql).bind(ror_id.as_str()).fetch_optional(pool).await?;
+    match res {
+        Some(r) => Ok(r),
+        None => {
+            let msg = format!("The ROR id '{}' is not in the data currently in the src tables", ror_id);
+            Err(AppError::CsErr(CustomError::new(&msg)))
+        },
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statuses(pairs: &[(&str, i32)]) -> HashMap<String, i32> {
+        pairs.iter().map(|(id, s)| (id.to_string(), *s)).collect()
+    }
+
+    fn links(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect()
+    }
+
+    fn entry<'a>(entries: &'a [SuccessorEntry], id: &str) -> &'a SuccessorEntry {
+        entries.iter().find(|e| e.id == id).unwrap()
+    }
+
+    #[test]
+    fn check_chains_resolved_over_several_hops() {
+
+        // a (withdrawn) -> b (inactive) -> c (active), and e -> x, where x is not in the data
+
+        let st = statuses(&[("a", 3), ("b", 2), ("c", 1), ("e", 2), ("f", 2)]);
+        let entries = map_successors(&st, &links(&[("a", "b"), ("b", "c"), ("e", "x"), ("f", "e")]));
+        assert_eq!(entries.len(), 4);
+
+        let a = entry(&entries, "a");
+        assert_eq!((a.resolution.as_str(), a.active_id.as_deref()), ("resolved", Some("c")));
+        assert_eq!((a.num_hops, a.path.as_deref()), (Some(2), Some("a/b/c")));
+        assert_eq!(entry(&entries, "b").num_hops, Some(1));
+
+        assert_eq!(entry(&entries, "e").resolution, "no active successor");
+        assert_eq!(entry(&entries, "f").resolution, "no active successor");
+
+        let st = statuses(&[("g", 2)]);
+        assert_eq!(map_successors(&st, &[])[0].resolution, "no successor");
+    }
+
+    #[test]
+    fn check_branches_and_cycles_recorded() {
+        let st = statuses(&[("a", 2), ("b", 1), ("c", 1), ("p", 2), ("q", 3), ("r", 2), ("s", 1)]);
+        let entries = map_successors(&st, &links(&[("a", "b"), ("a", "c"), ("p", "q"), ("q", "p"),
+                                                   ("r", "p"), ("r", "s")]));
+        let a = entry(&entries, "a");
+        assert_eq!(a.resolution, "ambiguous");
+        assert_eq!(a.candidates.as_deref(), Some("b, c"));
+        assert_eq!(a.active_id, None);
+
+        let p = entry(&entries, "p");
+        assert_eq!((p.resolution.as_str(), p.has_cycle), ("cycle", true));
+
+        // r reaches s, though its other branch loops between p and q
+        let r = entry(&entries, "r");
+        assert_eq!((r.resolution.as_str(), r.active_id.as_deref(), r.has_cycle), ("resolved", Some("s"), true));
+    }
+}
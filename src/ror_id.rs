@@ -0,0 +1,154 @@
+/***************************************************************************
+ * A ROR id, held as its final 9 characters (e.g. '04ttjf776'), the form
+ * used as the id in the src and hst tables. An id is parsed from the full
+ * url (https://ror.org/04ttjf776), from 'ror.org/04ttjf776', or from the
+ * bare id. Case, surrounding spaces and a trailing '/' are ignored, as is
+ * 'http' rather than 'https', or a 'www.' before the domain.
+ * A valid id is a '0', followed by 6 characters of Crockford's base32
+ * (the digits and the lower case letters other than i, l, o and u), and
+ * then a 2 digit checksum. The checksum is the ISO 7064 mod 97-10 value of
+ * the number encoded by the first 7 characters - i.e. 98 less the
+ * remainder, after that number is multiplied by 100 and divided by 97.
+ ***************************************************************************/
+
+use std::fmt;
+use std::str::FromStr;
+
+const CROCKFORD: &str = "0123456789abcdefghjkmnpqrstvwxyz";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RorId(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RorIdError {
+    Empty,
+    NotRorUrl,
+    WrongLength(usize),
+    NoLeadingZero,
+    InvalidCharacter(char),
+    InvalidChecksum(String),
+}
+
+impl fmt::Display for RorIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RorIdError::Empty => write!(f, "the id is empty"),
+            RorIdError::NotRorUrl => write!(f, "the url is not a ror.org url"),
+            RorIdError::WrongLength(n) => write!(f, "the id has {} characters rather than 9", n),
+            RorIdError::NoLeadingZero => write!(f, "the id does not start with '0'"),
+            RorIdError::InvalidCharacter(c) => write!(f, "'{}' is not a valid character in a ror id", c),
+            RorIdError::InvalidChecksum(expected) => write!(f, "the checksum does not match (expected '{}')", expected),
+        }
+    }
+}
+
+impl std::error::Error for RorIdError {}
+
+
+impl RorId {
+
+    pub fn parse(s: &str) -> Result<RorId, RorIdError> {
+
+        let s = s.trim().trim_end_matches('/').to_lowercase();
+        if s.is_empty() {
+            return Err(RorIdError::Empty);
+        }
+
+        // Any scheme and 'www.' are removed, and what remains must then be
+        // either on ror.org or have no domain at all.
+
+        let rest = s.strip_prefix("https://").or_else(|| s.strip_prefix("http://")).unwrap_or(&s);
+        let rest = rest.strip_prefix("www.").unwrap_or(rest);
+        let id = match rest.strip_prefix("ror.org/") {
+            Some(id) => id,
+            None if rest.contains('/') || rest.contains('.') => return Err(RorIdError::NotRorUrl),
+            None => rest,
+        };
+
+        let n = id.chars().count();
+        if n != 9 {
+            return Err(RorIdError::WrongLength(n));
+        }
+        if !id.starts_with('0') {
+            return Err(RorIdError::NoLeadingZero);
+        }
+        if let Some(c) = id.chars().find(|c| !c.is_ascii()) {
+            return Err(RorIdError::InvalidCharacter(c));
+        }
+
+        let (body, check) = id.split_at(7);
+        let mut value: u64 = 0;
+        for c in body.chars() {
+            match CROCKFORD.find(c) {
+                Some(d) => value = value * 32 + d as u64,
+                None => return Err(RorIdError::InvalidCharacter(c)),
+            }
+        }
+        if let Some(c) = check.chars().find(|c| !c.is_ascii_digit()) {
+            return Err(RorIdError::InvalidCharacter(c));
+        }
+
+        let expected = format!("{:02}", 98 - ((value * 100) % 97));
+        if check != expected {
+            return Err(RorIdError::InvalidChecksum(expected));
+        }
+
+        Ok(RorId(id.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn url(&self) -> String {
+        format!("https://ror.org/{}", self.0)
+    }
+}
+
+impl fmt::Display for RorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for RorId {
+    type Err = RorIdError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RorId::parse(s)
+    }
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_id_forms_parsed() {
+        let id = RorId::parse("https://ror.org/04ttjf776").unwrap();
+        assert_eq!(id.as_str(), "04ttjf776");
+        assert_eq!(id.url(), "https://ror.org/04ttjf776");
+        for s in ["04ttjf776", " 04TTJF776 ", "ror.org/04ttjf776", "http://www.ror.org/04ttjf776/"] {
+            assert_eq!(RorId::parse(s), Ok(id.clone()));
+        }
+        assert_eq!("02bfwt286".parse::<RorId>().unwrap().to_string(), "02bfwt286");
+    }
+
+    #[test]
+    fn check_invalid_ids_rejected() {
+        assert_eq!(RorId::parse("04ttjf777"), Err(RorIdError::InvalidChecksum("76".to_string())));
+        assert_eq!(RorId::parse("14ttjf776"), Err(RorIdError::NoLeadingZero));
+        assert_eq!(RorId::parse("04ttif776"), Err(RorIdError::InvalidCharacter('i')));
+        assert_eq!(RorId::parse("https://example.org/04ttjf776"), Err(RorIdError::NotRorUrl));
+        assert_eq!(RorId::parse(""), Err(RorIdError::Empty));
+    }
+
+    #[test]
+    fn check_short_ids_do_not_panic() {
+        assert_eq!(RorId::parse("https://ror.org/1234"), Err(RorIdError::WrongLength(4)));
+        assert_eq!(RorId::parse("https://ror.org/"), Err(RorIdError::NotRorUrl));
+        assert_eq!(RorId::parse("https://ror.org/0é1234€x9"), Err(RorIdError::InvalidCharacter('é')));
+    }
+}
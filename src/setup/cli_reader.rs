@@ -26,6 +26,29 @@ pub fn fetch_valid_arguments(args: Vec<OsString>) -> Result<CliPars, AppError>
     let data_version = parse_result.get_one::<String>("data_version").unwrap();
     let data_date = parse_result.get_one::<String>("data_date").unwrap();
 
+    // The format is restricted to 'json' or 'csv', if present.
+
+    let source_format = match parse_result.get_one::<String>("format") {
+        Some(f) => f.clone(),
+        None => "".to_string(),
+    };
+
+    // A version given with --changes-since implies the changed records export.
+
+    let changes_since = match parse_result.get_one::<String>("changes_since") {
+        Some(v) => v.clone(),
+        None => "".to_string(),
+    };
+    let changes_flag = !changes_since.is_empty();
+
+    // Similarly a ror id given with --resolve implies its resolution to an active organisation.
+
+    let resolve_id = match parse_result.get_one::<String>("resolve_id") {
+        Some(id) => id.clone(),
+        None => "".to_string(),
+    };
+    let resolve_flag = !resolve_id.is_empty();
+
     // Flag values are false if not present, true if present.
 
     let a_flag = parse_result.get_flag("a_flag");
@@ -36,9 +59,15 @@ pub fn fetch_valid_arguments(args: Vec<OsString>) -> Result<CliPars, AppError>
     let mut t_flag = parse_result.get_flag("t_flag");
     let x_flag = parse_result.get_flag("x_flag");
     let y_flag = parse_result.get_flag("y_flag");
+    let w_flag = parse_result.get_flag("w_flag");
+    let b_flag = parse_result.get_flag("b_flag");
     let mut c_flag = parse_result.get_flag("c_flag");
     let mut m_flag = parse_result.get_flag("m_flag");
     let z_flag = parse_result.get_flag("z_flag");
+    let strict_flag = parse_result.get_flag("strict_flag");
+    let force_flag = parse_result.get_flag("force_flag");
+    let dry_run_flag = parse_result.get_flag("dry_run_flag");
+    let rel_issues_flag = parse_result.get_flag("rel_issues_flag");
 
     // If c, m, or both flags set (may be by using 'i' (initialise) flag)
     // Only do the c and / or m actions
@@ -51,10 +80,18 @@ pub fn fetch_valid_arguments(args: Vec<OsString>) -> Result<CliPars, AppError>
         
         let flags = Flags {
             import_ror: false,
+            strict_import: false,
+            force_import: false,
             process_data: false,
             export_text: false,
             export_csv: false,
             export_full_csv: false,
+            export_trends: false,
+            export_changes: false,
+            export_rel_issues: false,
+            resolve_id: false,
+            batch_import: false,
+            dry_run: false,
             create_lookups: c_flag,
             create_summary: m_flag,
             test_run: false,
@@ -65,12 +102,20 @@ pub fn fetch_valid_arguments(args: Vec<OsString>) -> Result<CliPars, AppError>
             source_file: "".to_string(),
             data_version: "".to_string(),
             data_date: "".to_string(),
+            source_format: "".to_string(),
+            changes_since: "".to_string(),
+            resolve_id: "".to_string(),
             flags: flags,
         })
     }
     
     else {
-        if a_flag  // 'a' (do all) flag set
+        if b_flag || dry_run_flag  // 'b' (batch) flag set - each file is imported and processed in turn -
+        {                          // or a dry run of the duplicate name rules, which changes no data
+            r_flag = false;
+            p_flag = false;
+        }
+        else if a_flag  // 'a' (do all) flag set
         {
             r_flag = true;  
             p_flag = true;
@@ -78,21 +123,29 @@ pub fn fetch_valid_arguments(args: Vec<OsString>) -> Result<CliPars, AppError>
         }
         else 
         {
-            // if none of r, p, t, x or y flags set
-            // set r to be true, as the default with no flags
+            // if none of r, p, t, x, y or w flags set, and no changes or relationship issues
+            // export, or id resolution, requested, set r to be true, as the default with no flags
 
-            if r_flag == false && p_flag == false && t_flag == false
-                && x_flag == false && y_flag == false {
+            if r_flag == false && p_flag == false && t_flag == false && x_flag == false 
+                && y_flag == false && !w_flag && !changes_flag && !rel_issues_flag && !resolve_flag {
                 r_flag = true;  
             }
         }
 
         let flags = Flags {
             import_ror: r_flag,
+            strict_import: strict_flag,
+            force_import: force_flag,
             process_data: p_flag,
             export_text: t_flag,
             export_csv: x_flag,
             export_full_csv: y_flag,
+            export_trends: w_flag,
+            export_changes: changes_flag,
+            export_rel_issues: rel_issues_flag,
+            resolve_id: resolve_flag,
+            batch_import: b_flag && !dry_run_flag,
+            dry_run: dry_run_flag,
             create_lookups: false,
             create_summary: false,
             test_run: z_flag,
@@ -103,6 +156,9 @@ pub fn fetch_valid_arguments(args: Vec<OsString>) -> Result<CliPars, AppError>
             source_file: source_file.clone(),
             data_version: data_version.clone(),
             data_date: data_date.clone(),
+            source_format,
+            changes_since,
+            resolve_id,
             flags: flags,
         })
     }
@@ -145,6 +201,25 @@ fn parse_args(args: Vec<OsString>) -> Result<ArgMatches, clap::Error> {
            .help("A string with a date in ISO format that gives the date of the data")
            .default_value("")
         )
+        .arg(
+            Arg::new("format")
+           .long("format")
+           .required(false)
+           .help("The format of the source data, 'json' or 'csv' (over-rides the format implied by the file extension)")
+           .value_parser(["json", "csv"])
+        )
+        .arg(
+            Arg::new("changes_since")
+           .long("changes-since")
+           .required(false)
+           .help("A version code (e.g. v1.58) - output the records created, modified or deleted since that version as jsonl")
+        )
+        .arg(
+            Arg::new("resolve_id")
+           .long("resolve")
+           .required(false)
+           .help("A ROR id - report the active organisation that it currently resolves to, following any successor links")
+        )
         .arg(
             Arg::new("a_flag")
            .short('a')
@@ -193,6 +268,22 @@ fn parse_args(args: Vec<OsString>) -> Result<ArgMatches, clap::Error> {
            .help("A flag signifying output a summary of the data for all versions into csv files")
            .action(clap::ArgAction::SetTrue)
        )
+       .arg(
+            Arg::new("w_flag")
+           .short('w')
+           .long("trends")
+           .required(false)
+           .help("A flag signifying output a trend report for all versions, as a text file and a wide format csv file")
+           .action(clap::ArgAction::SetTrue)
+       )
+       .arg(
+            Arg::new("b_flag")
+           .short('b')
+           .long("batch")
+           .required(false)
+           .help("A flag signifying import, process and summarise every dump in the data folder, in version order")
+           .action(clap::ArgAction::SetTrue)
+       )
        .arg(
             Arg::new("i_flag")
            .short('i')
@@ -217,6 +308,34 @@ fn parse_args(args: Vec<OsString>) -> Result<ArgMatches, clap::Error> {
             .help("A flag signifying that summary tables should be recreated")
             .action(clap::ArgAction::SetTrue)
        )
+       .arg(
+            Arg::new("strict_flag")
+            .long("strict")
+            .required(false)
+            .help("A flag signifying that the import should stop at the first invalid record, rather than reject it")
+            .action(clap::ArgAction::SetTrue)
+       )
+       .arg(
+            Arg::new("force_flag")
+            .long("force")
+            .required(false)
+            .help("A flag signifying that a source file should be imported and processed even if it has been imported before")
+            .action(clap::ArgAction::SetTrue)
+       )
+       .arg(
+            Arg::new("dry_run_flag")
+            .long("dry-run")
+            .required(false)
+            .help("A flag signifying report what each duplicate name rule would delete from the names in the ror tables, without changing any data")
+            .action(clap::ArgAction::SetTrue)
+       )
+       .arg(
+            Arg::new("rel_issues_flag")
+            .long("rel-issues")
+            .required(false)
+            .help("A flag signifying write out the relationship issues found when the data now in the src tables was processed, as a csv file")
+            .action(clap::ArgAction::SetTrue)
+       )
        .arg(
             Arg::new("z_flag")
             .short('z')
@@ -371,5 +490,130 @@ mod tests {
         assert_eq!(res.data_version, "1.63");
     }
 
+    #[test]
+    fn check_cli_with_strict_flag() {
+        let target = &"target\\debug\\ror1.exe".replace("\\", "/");
+        let args : Vec<&str> = vec![target, "-r", "--strict"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.flags.import_ror, true);
+        assert_eq!(res.flags.strict_import, true);
+        assert_eq!(res.flags.process_data, false);
+
+        let args : Vec<&str> = vec![target, "-r"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.flags.strict_import, false);
+    }
+
+    #[test]
+    fn check_cli_with_force_flag() {
+        let target = &"target\\debug\\ror1.exe".replace("\\", "/");
+        let args : Vec<&str> = vec![target, "-a", "--force"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.flags.import_ror, true);
+        assert_eq!(res.flags.force_import, true);
+
+        let args : Vec<&str> = vec![target, "-a"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.flags.force_import, false);
+    }
+
+    #[test]
+    fn check_cli_with_changes_since_param() {
+        let target = &"target\\debug\\ror1.exe".replace("\\", "/");
+        let args : Vec<&str> = vec![target, "--changes-since", "v1.58"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.changes_since, "v1.58");
+        assert_eq!(res.flags.export_changes, true);
+        assert_eq!(res.flags.import_ror, false);
+
+        let args : Vec<&str> = vec![target, "-a"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.changes_since, "");
+        assert_eq!(res.flags.export_changes, false);
+    }
+
+    #[test]
+    fn check_cli_with_trends_flag() {
+        let target = &"target\\debug\\ror1.exe".replace("\\", "/");
+        let args : Vec<&str> = vec![target, "-w"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.flags.export_trends, true);
+        assert_eq!(res.flags.import_ror, false);
+        assert_eq!(res.flags.export_full_csv, false);
+    }
+
+    #[test]
+    fn check_cli_with_batch_flag() {
+        let target = &"target\\debug\\ror1.exe".replace("\\", "/");
+        let args : Vec<&str> = vec![target, "-b", "--force", "-a"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.flags.batch_import, true);
+        assert_eq!(res.flags.force_import, true);
+        assert_eq!(res.flags.import_ror, false);
+        assert_eq!(res.flags.process_data, false);
+    }
+
+    #[test]
+    fn check_cli_with_dry_run_flag() {
+        let target = &"target\\debug\\ror1.exe".replace("\\", "/");
+        let args : Vec<&str> = vec![target, "--dry-run", "-p"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.flags.dry_run, true);
+        assert_eq!(res.flags.import_ror, false);
+        assert_eq!(res.flags.process_data, false);
+    }
+
+    #[test]
+    fn check_cli_with_rel_issues_flag() {
+        let target = &"target\\debug\\ror1.exe".replace("\\", "/");
+        let args : Vec<&str> = vec![target, "--rel-issues"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.flags.export_rel_issues, true);
+        assert_eq!(res.flags.import_ror, false);
+        assert_eq!(res.flags.process_data, false);
+    }
+
+    #[test]
+    fn check_cli_with_resolve_param() {
+        let target = &"target\\debug\\ror1.exe".replace("\\", "/");
+        let args : Vec<&str> = vec![target, "--resolve", "https://ror.org/04ttjf776"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.resolve_id, "https://ror.org/04ttjf776");
+        assert_eq!(res.flags.resolve_id, true);
+        assert_eq!(res.flags.import_ror, false);
+    }
+
+    #[test]
+    fn check_cli_with_format_param() {
+        let target = &"target\\debug\\ror1.exe".replace("\\", "/");
+        let args : Vec<&str> = vec![target, "-s", "v1.59-2025-01-23-ror-data.zip", "--format", "csv"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+        let res = fetch_valid_arguments(test_args).unwrap();
+        assert_eq!(res.source_format, "csv");
+
+        let args : Vec<&str> = vec![target, "--format", "xml"];
+        let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+        assert!(fetch_valid_arguments(test_args).is_err());
+    }
+
 }
 
@@ -0,0 +1,99 @@
+/***************************************************************************
+ * The version code of a ror data dump, e.g. 'v1.59' or 'v1.45.1'. Codes
+ * have a 'v' followed by one to three dot separated numbers (a missing 'v'
+ * is added when the code is parsed). Versions are ordered by their numeric
+ * parts, so that v1.100 comes after v1.59, rather than by the code string.
+ * The code itself, as displayed, remains the key for the version in the
+ * smm tables and in the version registry (smm.version_registry).
+ ***************************************************************************/
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::LazyLock;
+use regex::Regex;
+
+static VERSION_RE: LazyLock<Regex> = LazyLock::new(||
+    Regex::new(r"^v?([0-9]+)(?:\.([0-9]+))?(?:\.([0-9]+))?$").unwrap());
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    num_parts: usize,
+}
+
+impl DataVersion {
+
+    pub fn parse(code: &str) -> Option<DataVersion> {
+        let caps = VERSION_RE.captures(code.trim())?;
+        let part = |i: usize| caps.get(i).and_then(|m| m.as_str().parse::<u32>().ok());
+        let major = part(1)?;
+        let num_parts = (1..=3).filter(|i| caps.get(*i).is_some()).count();
+        Some(DataVersion {
+            major,
+            minor: part(2).unwrap_or(0),
+            patch: part(3).unwrap_or(0),
+            num_parts,
+        })
+    }
+
+    pub fn code(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for DataVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts = [self.major, self.minor, self.patch];
+        let nums: Vec<String> = parts[..self.num_parts].iter().map(|n| n.to_string()).collect();
+        write!(f, "v{}", nums.join("."))
+    }
+}
+
+impl Ord for DataVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch, self.num_parts)
+            .cmp(&(other.major, other.minor, other.patch, other.num_parts))
+    }
+}
+
+impl PartialOrd for DataVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_version_codes_parsed() {
+        let v = DataVersion::parse("v1.59").unwrap();
+        assert_eq!((v.major, v.minor, v.patch), (1, 59, 0));
+        assert_eq!(v.code(), "v1.59");
+
+        let v = DataVersion::parse("1.45.1").unwrap();
+        assert_eq!((v.major, v.minor, v.patch), (1, 45, 1));
+        assert_eq!(v.code(), "v1.45.1");
+
+        assert_eq!(DataVersion::parse("v99").unwrap().code(), "v99");
+        assert_eq!(DataVersion::parse(""), None);
+        assert_eq!(DataVersion::parse("v1.x"), None);
+        assert_eq!(DataVersion::parse("v1.2.3.4"), None);
+    }
+
+    #[test]
+    fn check_versions_ordered_numerically() {
+        let mut versions: Vec<DataVersion> = ["v1.100", "v1.59", "v1.9", "v1.59.1", "v2", "v1.60"]
+            .iter().map(|c| DataVersion::parse(c).unwrap()).collect();
+        versions.sort();
+        let codes: Vec<String> = versions.iter().map(|v| v.code()).collect();
+        assert_eq!(codes, vec!["v1.9", "v1.59", "v1.59.1", "v1.60", "v1.100", "v2"]);
+        assert!(DataVersion::parse("v1.100").unwrap() > DataVersion::parse("v1.59").unwrap());
+    }
+}
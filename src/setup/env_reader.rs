@@ -24,8 +24,10 @@
 use std::sync::OnceLock;
 use std::env;
 use std::path::PathBuf;
+use chrono::NaiveDate;
 use dotenv;
 use crate::error_defs::{AppError, CustomError};
+use super::{SnapshotRetention, DeltaThreshold, UnmappedCategories};
 
 #[derive(Debug)]
 pub struct DbPars {
@@ -142,6 +144,93 @@ pub fn fetch_data_date() -> String {
     env::var("data_date").unwrap_or("".to_string())
 }
 
+pub fn fetch_baseline_date() -> NaiveDate {
 
+    // The date from which the 'days' of each version are counted.
+    // Defaults to 2024-04-29, if not provided or not a valid ISO date.
 
+    let default_date = NaiveDate::from_ymd_opt(2024, 4, 29).unwrap();
+    match env::var("baseline_date") {
+        Ok(v) => NaiveDate::parse_from_str(v.trim(), "%Y-%m-%d").unwrap_or(default_date),
+        Err(_) => default_date,
+    }
+}
+
+pub fn fetch_import_batch_size() -> usize {
+
+    // The number of records processed, and rows sent to the database, in each batch.
+
+    let default_size = 1000;
+    match env::var("import_batch_size") {
+        Ok(v) => v.trim().parse::<usize>().ok().filter(|n| *n > 0).unwrap_or(default_size),
+        Err(_) => default_size,
+    }
+}
+
+pub fn fetch_import_concurrency() -> usize {
+
+    // The number of ror tables that may be loaded at the same time.
+
+    match env::var("import_concurrency") {
+        Ok(v) => v.trim().parse::<usize>().ok().filter(|n| *n > 0).unwrap_or(1),
+        Err(_) => 1,
+    }
+}
+
+pub fn fetch_snapshot_retention() -> SnapshotRetention {
+
+    // 'all' keeps snapshots of every version, a number n keeps those of the latest n versions.
+    // If missing, 0 or not recognised, no snapshots are kept.
+
+    match env::var("snapshot_retention") {
+        Ok(v) => {
+            let v = v.trim().to_lowercase();
+            if v == "all" {
+                SnapshotRetention::All
+            }
+            else {
+                match v.parse::<usize>() {
+                    Ok(n) if n > 0 => SnapshotRetention::Latest(n),
+                    _ => SnapshotRetention::Off,
+                }
+            }
+        },
+        Err(_) => SnapshotRetention::Off,
+    }
+}
+
+pub fn fetch_delta_threshold() -> DeltaThreshold {
+
+    // The percentage change in a metric, since the previous version, that causes it to be
+    // flagged (default 10), and the minimum earlier value for the check to apply (default 100).
+
+    let pc = match env::var("delta_threshold_pc") {
+        Ok(v) => v.trim().parse::<f64>().ok().filter(|n| *n > 0.0).unwrap_or(10.0),
+        Err(_) => 10.0,
+    };
+    let min_base = match env::var("delta_min_base") {
+        Ok(v) => v.trim().parse::<i64>().ok().filter(|n| *n >= 0).unwrap_or(100),
+        Err(_) => 100,
+    };
+    DeltaThreshold { pc, min_base }
+}
+
+pub fn fetch_unmapped_categories() -> UnmappedCategories {
 
+    // 'reject' causes records with unrecognised category values to be left out of the
+    // src tables. Otherwise (the default) the values are added to the lup tables.
+
+    match env::var("unmapped_categories") {
+        Ok(v) if v.trim().eq_ignore_ascii_case("reject") => UnmappedCategories::Reject,
+        _ => UnmappedCategories::Add,
+    }
+}
+
+pub fn fetch_dup_name_rules_path() -> PathBuf {
+
+    // A json file with the rules for removing duplicated names. If not given the
+    // rules built into the program are used.
+
+    let path_as_string = env::var("dup_name_rules_path").unwrap_or("".to_string());
+    PathBuf::from(path_as_string.trim().replace("\\", "/"))
+}
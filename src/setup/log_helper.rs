@@ -25,6 +25,16 @@ pub fn setup_log (data_folder: &PathBuf, source_file_name : &String) -> Result<l
     config_log (&log_file_path)
 }
 
+pub fn setup_batch_log (log_folder: &PathBuf) -> Result<log4rs::Handle, AppError> {
+
+    // A batch run has a single log, covering each of the files imported.
+
+    let datetime_string = Local::now().format("%m-%d %H%M%S").to_string();
+    let log_file_name = format!("ror {} batch import.log", datetime_string);
+    let log_file_path: PathBuf = [log_folder, &PathBuf::from(&log_file_name)].iter().collect();
+    config_log (&log_file_path)
+}
+
 fn get_log_file_path(data_folder: &PathBuf, source_file_name : &String) -> PathBuf {
     
     // Derives the log file name, returns the full path
@@ -32,7 +42,7 @@ fn get_log_file_path(data_folder: &PathBuf, source_file_name : &String) -> PathB
     let datetime_string = Local::now().format("%m-%d %H%M%S").to_string();
     let mut log_file_name = format!("ror {} ", datetime_string);
     if source_file_name != "" {
-        let source_file = &source_file_name[..(source_file_name.len() - 5)];
+        let source_file = get_source_file_stem(source_file_name);
         log_file_name = format!("{} from {}.log", log_file_name, source_file);
     }
     else {
@@ -42,6 +52,30 @@ fn get_log_file_path(data_folder: &PathBuf, source_file_name : &String) -> PathB
     
 }
 
+pub fn get_rejects_file_path(log_folder: &PathBuf, source_file_name : &str) -> PathBuf {
+
+    // Rejected records are written (as json lines) to a file alongside the log.
+
+    let datetime_string = Local::now().format("%m-%d %H%M%S").to_string();
+    let source_file = get_source_file_stem(source_file_name);
+    let rejects_file_name = format!("ror {} rejects from {}.jsonl", datetime_string, source_file);
+    [log_folder, &PathBuf::from(&rejects_file_name)].iter().collect()
+}
+
+fn get_source_file_stem(source_file_name: &str) -> &str {
+
+    // Removes the extension(s) of the source file, which may be a 
+    // json or csv file, a gzipped version of either, or a zip archive.
+
+    let lc_name = source_file_name.to_lowercase();
+    for ext in [".json.gz", ".csv.gz", ".json", ".csv", ".gz", ".zip"] {
+        if lc_name.ends_with(ext) {
+            return &source_file_name[..(source_file_name.len() - ext.len())];
+        }
+    }
+    source_file_name
+}
+
 fn config_log (log_file_path: &PathBuf) -> Result<log4rs::Handle, AppError> {
     
     // Initially establish a pattern for each log line.
@@ -97,15 +131,32 @@ pub fn log_startup_params (ip : &InitParams) {
     info!("output_folder: {}", ip.output_folder.display());
     info!("source_file_name: {}", ip.source_file_name);
     info!("output_file_name: {}", ip.output_file_name);
-    info!("data_version: {}", ip.data_version);
+    info!("data_version: {}", ip.data_version.as_ref().map(|v| v.code()).unwrap_or_default());
     info!("data_date: {}", ip.data_date);
+    info!("baseline_date: {}", ip.baseline_date);
+    info!("source_format: {}", ip.source_format);
+    info!("import_batch_size: {}", ip.import_batch_size);
+    info!("import_concurrency: {}", ip.import_concurrency);
+    info!("snapshot_retention: {:?}", ip.snapshot_retention);
+    info!("delta_threshold: {}% (min base {})", ip.delta_threshold.pc, ip.delta_threshold.min_base);
+    info!("unmapped_categories: {:?}", ip.unmapped_categories);
+    info!("dup_name_rules_path: {}", ip.dup_name_rules_path.display());
+    info!("changes_since: {}", ip.changes_since.as_ref().map(|v| v.code()).unwrap_or_default());
     info!("create look up tables: {}", ip.flags.create_lookups);
     info!("create summary tables: {}", ip.flags.create_summary);
     info!("import_ror: {}", ip.flags.import_ror);
+    info!("strict_import: {}", ip.flags.strict_import);
+    info!("force_import: {}", ip.flags.force_import);
     info!("process_data: {}", ip.flags.process_data);
     info!("export_text: {}", ip.flags.export_text);
     info!("export_csv: {}", ip.flags.export_csv);
     info!("export_all_csv: {}", ip.flags.export_full_csv);
+    info!("export_trends: {}", ip.flags.export_trends);
+    info!("export_changes: {}", ip.flags.export_changes);
+    info!("export_rel_issues: {}", ip.flags.export_rel_issues);
+    info!("resolve_id: {}", ip.resolve_id);
+    info!("batch_import: {}", ip.flags.batch_import);
+    info!("dry_run: {}", ip.flags.dry_run);
     info!("");
     info!("************************************");
     info!("");
@@ -10,36 +10,42 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
     create table lup.ror_status_types (
         id                int         not null primary key 
         , name            varchar
+        , ror_value       varchar
     );
 
     drop table if exists lup.ror_org_types;
     create table lup.ror_org_types (
         id                int         not null primary key 
         , name            varchar
+        , ror_value       varchar
     );
     
     drop table if exists lup.ror_name_types;
     create table lup.ror_name_types (
         id                int         not null primary key
         , name            varchar
+        , ror_value       varchar
     );
 
     drop table if exists lup.ror_id_types;
     create table lup.ror_id_types (
         id                int         not null primary key
         , name            varchar
+        , ror_value       varchar
     );
 
     drop table if exists lup.ror_link_types;
     create table lup.ror_link_types (
         id                int         not null primary key
         , name            varchar
+        , ror_value       varchar
     );
 
     drop table if exists lup.ror_org_rels;
     create table lup.ror_org_rels (
         id                int         not null primary key
         , name            varchar
+        , ror_value       varchar
     );
 
     drop table if exists lup.countries;
@@ -52,7 +58,10 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
     create table lup.lang_codes (
         code              varchar     not null primary key
         , marc_code       varchar
+        , iso_639_3       varchar
         , name            varchar
+        , scope           varchar
+        , lang_type       varchar
         , source          varchar
     );
 
@@ -78,3 +87,23 @@ pub async fn create_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
     
 }
 
+
+pub async fn create_lang_code_replacements_table(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // Deprecated (or otherwise unwanted) language codes, and the codes that replace them.
+
+    let sql = r#"SET client_min_messages TO WARNING; 
+    create schema if not exists lup;
+
+    drop table if exists lup.lang_code_replacements;
+    create table lup.lang_code_replacements (
+        old_code          varchar     not null primary key
+        , new_code        varchar     not null
+        , notes           varchar
+    );
+
+    SET client_min_messages TO NOTICE;"#;
+
+    sqlx::raw_sql(sql).execute(pool).await?;
+    Ok(())
+}
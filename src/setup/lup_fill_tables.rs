@@ -1,31 +1,32 @@
 use sqlx::{Pool, Postgres};
+use serde::Deserialize;
 use crate::AppError;
 
 pub async fn fill_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
 
     let sql = r#"
 
-    insert into lup.ror_status_types(id, name) 
-    values (1, 'active'), (2, 'inactive'), (3, 'withdrawn');
+    insert into lup.ror_status_types(id, name, ror_value) 
+    values (1, 'active', 'active'), (2, 'inactive', 'inactive'), (3, 'withdrawn', 'withdrawn');
 
-    insert into lup.ror_org_types(id, name) 
-       values (100, 'government'), (200, 'education'), (300, 'healthcare'), 
-       (400, 'company'), (500, 'nonprofit'), (600, 'funder'),
-       (700, 'facility'), (800, 'archive'),  (900, 'other');
+    insert into lup.ror_org_types(id, name, ror_value) 
+       values (100, 'government', 'government'), (200, 'education', 'education'), (300, 'healthcare', 'healthcare'), 
+       (400, 'company', 'company'), (500, 'nonprofit', 'nonprofit'), (600, 'funder', 'funder'),
+       (700, 'facility', 'facility'), (800, 'archive', 'archive'),  (900, 'other', 'other');
     
-    insert into lup.ror_name_types(id, name) 
-        values (5, 'label'), (7, 'alias'), (10, 'acronym');
+    insert into lup.ror_name_types(id, name, ror_value) 
+        values (5, 'label', 'label'), (7, 'alias', 'alias'), (10, 'acronym', 'acronym');
     
-    insert into lup.ror_id_types(id, name) 
-       values (11, 'isni'), (12, 'wikidata'),
-       (13, 'grid'), (14, 'fundref');
+    insert into lup.ror_id_types(id, name, ror_value) 
+       values (11, 'isni', 'isni'), (12, 'wikidata', 'wikidata'),
+       (13, 'grid', 'grid'), (14, 'fundref', 'fundref');
     
-    insert into lup.ror_link_types(id, name) 
-      values (21, 'wikipedia'), (22, 'website');
+    insert into lup.ror_link_types(id, name, ror_value) 
+      values (21, 'wikipedia', 'wikipedia'), (22, 'website', 'website');
     
-    insert into lup.ror_org_rels(id, name) 
-       values (1, 'has parent'), (2, 'has child'), (3, 'is related to'),
-        (4, 'has predecessor'), (5, 'has successor');
+    insert into lup.ror_org_rels(id, name, ror_value) 
+       values (1, 'has parent', 'parent'), (2, 'has child', 'child'), (3, 'is related to', 'related'),
+        (4, 'has predecessor', 'predecessor'), (5, 'has successor', 'successor');
 
     insert into lup.countries(code, name) values
              ('AD', 'Andorra'), ('AE', 'United Arab Emirates'), ('AF', 'Afghanistan'), ('AG', 'Antigua and Barbuda'), ('AI', 'Anguilla'), 
@@ -83,60 +84,6 @@ pub async fn fill_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
              ('VN', 'Vietnam'), ('VU', 'Vanuatu'), ('WF', 'Wallis and Futuna'), ('WS', 'Samoa'), ('XK', 'Kosovo'), ('YE', 'Yemen'), 
              ('YT', 'Mayotte'), ('ZA', 'South Africa'), ('ZM', 'Zambia'), ('ZW', 'Zimbabwe');
 
-    insert into lup.lang_codes(code, marc_code, name, source) values
-           ('af', 'afr', 'Afrikaans', 'ISO 639-1'), ('am', 'amh', 'Amharic', 'ISO 639-1'), ('ar', 'ara', 'Arabic', 'ISO 639-1'),
-           ('az', 'aze', 'Azerbaijani', 'ISO 639-1'), ('be', 'bel', 'Belarusian', 'ISO 639-1'), ('bg', 'bul', 'Bulgarian', 'ISO 639-1'),
-           ('bn', 'ben', 'Bengali', 'ISO 639-1'), ('bo', 'tib', 'Tibetan', 'ISO 639-1'), ('br', 'bre', 'Breton', 'ISO 639-1'),
-           ('bs', 'bos', 'Bosnian', 'ISO 639-1'), ('ca', 'cat', 'Catalan', 'ISO 639-1'), ('ce', 'che', 'Chechen', 'ISO 639-1'),
-           ('co', 'cos', 'Corsican', 'ISO 639-1'), ('cs', 'cze', 'Czech', 'ISO 639-1'), ('cy', 'wel', 'Welsh', 'ISO 639-1'),
-           ('da', 'dan', 'Danish', 'ISO 639-1'), ('de', 'ger', 'German', 'ISO 639-1'), ('el', 'gre', 'Greek', 'ISO 639-1'),
-           ('en', 'eng', 'English', 'ISO 639-1'), ('eo', 'epo', 'Esperanto', 'PubMed'), ('es', 'spa', 'Spanish', 'ISO 639-1'),
-           ('et', 'est', 'Estonian', 'ISO 639-1'), ('eu', 'baq', 'Basque', 'ISO 639-1'), ('fa', 'per', 'Persian', 'ISO 639-1'),
-           ('fi', 'fin', 'Finnish', 'ISO 639-1'), ('fr', 'fre', 'French', 'ISO 639-1'), ('ga', 'gle', 'Irish Gaelic', 'ISO 639-1'),
-           ('gd', 'gla', 'Scottish Gaelic', 'ISO 639-1'), ('gl', 'glg', 'Galician', 'ISO 639-1'), ('gu', 'guj', 'Gujarati', 'ISO 639-1'),
-           ('ha', 'hau', 'Hausa', 'ISO 639-1'), ('he', 'heb', 'Hebrew', 'ISO 639-1'), ('hi', 'hin', 'Hindi', 'ISO 639-1'),
-           ('hr', 'hrv', 'Croatian', 'ISO 639-1'), ('hu', 'hun', 'Hungarian', 'ISO 639-1'), ('hy', 'arm', 'Armenian', 'ISO 639-1'),
-           ('id', 'ind', 'Indonesian', 'ISO 639-1'), ('is', 'ice', 'Icelandic', 'ISO 639-1'), ('it', 'ita', 'Italian', 'ISO 639-1'),
-           ('iu', 'iku', 'Inuktitut', 'ISO 639-1'), ('ja', 'jpn', 'Japanese', 'ISO 639-1'), ('jv', 'jav', 'Javanese', 'ISO 639-1'),
-           ('ka', 'geo', 'Georgian', 'ISO 639-1'), ('kk', 'kaz', 'Kazakh', 'ISO 639-1'), ('kl', 'kal', 'Greenlandic, Kalaallisut', 'ISO 639-1'),
-           ('km', 'khm', 'Central Khmer', 'ISO 639-1'), ('kn', 'kan', 'Kannada', 'ISO 639-1'), ('ko', 'kor', 'Korean', 'ISO 639-1'), 
-           ('ks', 'kas', 'Kashmiri', 'ISO 639-1'), ('ku', 'kur', 'Kurdish', 'ISO 639-1'), ('la', 'lat', 'Latin', 'ECRIN'),
-           ('lb', 'ltz', 'Luxembourgish', 'ISO 639-1'), ('lo', 'lao', 'Lao', 'ISO 639-1'), ('lt', 'lit', 'Lithuanian', 'ISO 639-1'),
-           ('lv', 'lav', 'Latvian', 'ISO 639-1'), ('mi', 'mao', 'Maori', 'ISO 639-1'), ('mk', 'mac', 'Macedonian', 'ISO 639-1');
-           
-    insert into lup.lang_codes(code, marc_code, name, source) values
-           ('ml', 'mal', 'Malayalam', 'ISO 639-1'), ('mn', 'mon', 'Mongolian', 'ISO 639-1'), ('mr', 'mar', 'Marathi', 'ISO 639-1'),
-           ('ms', 'may', 'Malay', 'ISO 639-1'), ('mt', 'mlt', 'Maltese', 'ISO 639-1'), ('mu', 'mul', 'Multiple languages', 'PubMed'),
-           ('my', 'bur', 'Burmese', 'ISO 639-1'), ('ne', 'nep', 'Nepali', 'ISO 639-1'), ('nl', 'dut', 'Dutch', 'ISO 639-1'),  
-           ('no', 'nor', 'Norwegian', 'ISO 639-1'), ('os', 'oss', 'Ossetian', 'ISO 639-1'), ('pa', 'pan', 'Punjabi', 'ISO 639-1'),
-           ('pl', 'pol', 'Polish', 'ISO 639-1'), ('ps', 'pus', 'Pashto', 'ISO 639-1'), ('pt', 'por', 'Portuguese', 'ISO 639-1'),
-           ('qu', 'que', 'Quechua', 'ISO 639-1'), ('rm', 'roh', 'Romansh', 'ISO 639-1'), ('ro', 'rum', 'Romanian, Moldavian', 'ISO 639-1'),
-           ('ru', 'rus', 'Russian', 'ISO 639-1'), ('rw', 'kin', 'Kinyarwanda', 'ISO 639-1'), ('se', 'sme', 'Northern Sami', 'ISO 639-1'),
-           ('si', 'sin', 'Sinhalese', 'ISO 639-1'), ('sk', 'slo', 'Slovak', 'ISO 639-1'), ('sl', 'slv', 'Slovenian', 'ISO 639-1'),
-           ('sm', 'smo', 'Samoan', 'ISO 639-1'), ('sn', 'sna', 'Shona', 'ISO 639-1'), ('so', 'som', 'Somali', 'ISO 639-1'),
-           ('sq', 'alb', 'Albanian', 'ISO 639-1'), ('sr', 'srp', 'Serbian', 'ISO 639-1'), ('sv', 'swe', 'Swedish', 'ISO 639-1'),
-           ('sw', 'swa', 'Swahili', 'ISO 639-1'), ('ta', 'tam', 'Tamil', 'ISO 639-1'), ('te', 'tel', 'Telugu', 'ISO 639-1'),
-           ('tg', 'tgk', 'Tajik', 'ISO 639-1'), ('th', 'tha', 'Thai', 'ISO 639-1'), ('tk', 'tuk', 'Turkmen', 'ISO 639-1'),
-           ('to', 'ton', 'Tongan', 'ISO 639-1'), ('tr', 'tur', 'Turkish', 'ISO 639-1'), ('tt', 'tat', 'Tatar', 'ISO 639-1'), 
-           ('ty', 'tah', 'Tahitian', 'ISO 639-1'), ('uk', 'ukr', 'Ukrainian', 'ISO 639-1'), ('un', 'und', 'Undetermined', 'PubMed'),
-           ('ur', 'urd', 'Urdu', 'ISO 639-1'), ('uz', 'uzb', 'Uzbek', 'ISO 639-1'), ('vi', 'vie', 'Vietnamese', 'ISO 639-1'),
-           ('xh', 'xho', 'Xhosa', 'ISO 639-1'), ('yo', 'yor', 'Yoruba', 'ISO 639-1'), ('zh', 'chi', 'Chinese', 'ISO 639-1'),
-           ('zu', 'zul', 'Zulu', 'ISO 639-1');
-
-    insert into lup.lang_codes(code, marc_code, name, source) values
-           ('aa', 'aar', 'Afar', 'ISO 639-1'), ('ab', 'abk', 'Abkhazian', 'ISO 639-1'), ('as', 'asm', 'Assamese', 'ISO 639-1'),
-           ('ba', 'bak', 'Bashkir', 'ISO 639-1'), ('bi', 'bis', 'Bislama', 'ISO 639-1'), ('ch', 'cha', 'Chamorro', 'ISO 639-1'),
-           ('cu', 'chu', 'Church Slavonic', 'ISO 639-1'), ('dv', 'div', 'Divehi', 'ISO 639-1'), ('dz', 'dzo', 'Dzongkha', 'ISO 639-1'),
-           ('fo', 'fao', 'Faroese', 'ISO 639-1'), ('fy', 'fry', 'Western Frisian', 'ISO 639-1'), ('gv', 'glv', 'Manx', 'ISO 639-1'),
-           ('ht', 'hat', 'Haitian', 'ISO 639-1'), ('ki', 'kik', 'Kikuyu', 'ISO 639-1'), ('kr', 'kau', 'Kanuri', 'ISO 639-1'),
-           ('ky', 'kir', 'Kyrgyz', 'ISO 639-1'), ('lu', 'lub', 'Luba-Katanga', 'ISO 639-1'), ('mg', 'mlg', 'Malagasy', 'ISO 639-1'),
-           ('na', 'nau', 'Nauru', 'ISO 639-1'), ('nb', 'nob', 'Norwegian Bokmål', 'ISO 639-1'), ('nn', 'nno', 'Norwegian Nynorsk', 'ISO 639-1'),
-           ('ny', 'nya', 'Chichewa', 'ISO 639-1'), ('oc', 'oci', 'Occitan', 'ISO 639-1'), ('oj', 'oji', 'Ojibwa', 'ISO 639-1'),
-           ('om', 'orm', 'Oromo', 'ISO 639-1'), ('or', 'ori', 'Oriya', 'ISO 639-1'), ('sa', 'san', 'Sanskrit', 'ISO 639-1'),
-           ('sd', 'snd', 'Sindhi', 'ISO 639-1'), ('st', 'sot', 'Southern Sotho', 'ISO 639-1'), ('ti', 'tir', 'Tigrinya', 'ISO 639-1'),
-           ('tl', 'tgl', 'Tagalog', 'ISO 639-1'), ('ug', 'uig', 'Uighur', 'ISO 639-1');
-
-
     insert into lup.lang_scripts(code, unicode_name, iso_name, dir, chars, notes, hex_start, hex_end, ascii_start, ascii_end, source) 
          values 
          ('Adlm', 'Adlam',  'Adlam', 'RtL', 88, 'Used in parts of West and Central Africa', '1E900', '1E95F', 125184, 125279, 'ISO 15924'),
@@ -216,4 +163,101 @@ pub async fn fill_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
     sqlx::raw_sql(sql).execute(pool).await?;
     Ok(())
     
-}
\ No newline at end of file
+}
+
+
+// The language codes are loaded from a csv file, derived from the ISO 639
+// tables, that is compiled into the program. The code is the ISO 639-1 code
+// where one exists, otherwise the ISO 639-3 code. The marc code is the
+// ISO 639-2 (bibliographic) code.
+
+static LANG_CODES_CSV: &str = include_str!("iso_639_lang_codes.csv");
+
+#[derive(Debug, Deserialize)]
+struct LangCode {
+    code: String,
+    marc_code: Option<String>,
+    iso_639_3: Option<String>,
+    name: String,
+    scope: Option<String>,
+    lang_type: Option<String>,
+    source: String,
+}
+
+fn read_lang_codes() -> Result<Vec<LangCode>, AppError> {
+    let mut rdr = csv::Reader::from_reader(LANG_CODES_CSV.as_bytes());
+    let mut lang_codes = Vec::new();
+    for result in rdr.deserialize() {
+        let lc: LangCode = result?;
+        lang_codes.push(lc);
+    }
+    Ok(lang_codes)
+}
+
+pub async fn fill_lang_codes(pool: &Pool<Postgres>) -> Result<usize, AppError> {
+
+    let lang_codes = read_lang_codes()?;
+
+    let mut codes = Vec::new();
+    let mut marc_codes = Vec::new();
+    let mut iso_639_3s = Vec::new();
+    let mut names = Vec::new();
+    let mut scopes = Vec::new();
+    let mut lang_types = Vec::new();
+    let mut sources = Vec::new();
+    for lc in lang_codes.iter() {
+        codes.push(lc.code.clone());
+        marc_codes.push(lc.marc_code.clone());
+        iso_639_3s.push(lc.iso_639_3.clone());
+        names.push(lc.name.clone());
+        scopes.push(lc.scope.clone());
+        lang_types.push(lc.lang_type.clone());
+        sources.push(lc.source.clone());
+    }
+
+    let sql = r#"insert into lup.lang_codes (code, marc_code, iso_639_3, name, scope, lang_type, source)
+                 select * from unnest($1::varchar[], $2::varchar[], $3::varchar[], $4::varchar[],
+                                      $5::varchar[], $6::varchar[], $7::varchar[])"#;
+    sqlx::query(sql).bind(&codes).bind(&marc_codes).bind(&iso_639_3s).bind(&names)
+    .bind(&scopes).bind(&lang_types).bind(&sources)
+    .execute(pool).await?;
+    Ok(codes.len())
+}
+
+
+pub async fn fill_lang_code_replacements(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // The ISO 639-1 codes that have been withdrawn or replaced, as they still
+    // appear in some ROR names. Further replacements can be added to the table
+    // directly, and are applied to every name during processing.
+
+    let sql = r#"insert into lup.lang_code_replacements(old_code, new_code, notes)
+    values ('sh', 'sr', 'Serbo-Croatian, deprecated in ISO 639-1 (2000)'),
+           ('iw', 'he', 'Hebrew, deprecated in ISO 639-1 (1989)'),
+           ('in', 'id', 'Indonesian, deprecated in ISO 639-1 (1989)'),
+           ('ji', 'yi', 'Yiddish, deprecated in ISO 639-1 (1989)'),
+           ('jw', 'jv', 'Javanese, deprecated in ISO 639-1 (2001)'),
+           ('mo', 'ro', 'Moldavian, deprecated in ISO 639-1 (2008)');"#;
+
+    sqlx::raw_sql(sql).execute(pool).await?;
+    Ok(())
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn check_lang_codes_csv_is_readable_and_unique() {
+        let lang_codes = read_lang_codes().unwrap();
+        let codes: HashSet<&str> = lang_codes.iter().map(|lc| lc.code.as_str()).collect();
+        assert_eq!(codes.len(), lang_codes.len());
+        assert!(codes.contains("en"));
+        assert!(codes.contains("sr"));
+        assert!(codes.contains("yue"));
+        assert!(lang_codes.iter().all(|lc| lc.code.len() == 2 || lc.code.len() == 3));
+    }
+}
@@ -8,6 +8,7 @@
 
 pub mod env_reader;
 pub mod log_helper;
+pub mod data_version;
 mod cli_reader;
 mod lup_create_tables;
 mod lup_fill_tables;
@@ -35,6 +36,7 @@ use std::fs;
 use std::time::Duration;
 use regex::Regex;
 use sqlx::ConnectOptions;
+use data_version::DataVersion;
 
 #[derive(Debug)]
 pub struct CliPars {
@@ -42,29 +44,79 @@ pub struct CliPars {
     pub source_file: String,
     pub data_version: String,
     pub data_date: String,
+    pub source_format: String,
+    pub changes_since: String,
+    pub resolve_id: String,
     pub flags: Flags, 
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Flags {
     pub import_ror: bool,
+    pub strict_import: bool,
+    pub force_import: bool,
     pub process_data: bool,
     pub export_text: bool,
     pub export_csv: bool,
     pub export_full_csv: bool,
+    pub export_trends: bool,
+    pub export_changes: bool,
+    pub export_rel_issues: bool,
+    pub resolve_id: bool,
+    pub batch_import: bool,
+    pub dry_run: bool,
     pub create_lookups: bool,
     pub create_summary: bool,
     pub test_run: bool,
 }
 
+// Whether snapshots of the src data of each version are kept in the hst schema,
+// and, if they are, whether all or only the latest n versions are retained.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotRetention {
+    Off,
+    Latest(usize),
+    All,
+}
+
+// When the metrics of a version are compared with those of the previous version,
+// changes of at least 'pc' percent are flagged, if the earlier value was at least 'min_base'.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeltaThreshold {
+    pub pc: f64,
+    pub min_base: i64,
+}
+
+// Whether category values in the ror data (e.g. a new org type) that are not in the
+// lup tables are added to those tables, or the records with them are not transferred.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnmappedCategories {
+    Add,
+    Reject,
+}
+
+#[derive(Clone)]
 pub struct InitParams {
     pub data_folder: PathBuf,
     pub log_folder: PathBuf,
     pub output_folder: PathBuf,
     pub source_file_name: String,
     pub output_file_name: String,
-    pub data_version: String,
+    pub data_version: Option<DataVersion>,
     pub data_date: String,
+    pub baseline_date: NaiveDate,
+    pub source_format: String,
+    pub import_batch_size: usize,
+    pub import_concurrency: usize,
+    pub snapshot_retention: SnapshotRetention,
+    pub delta_threshold: DeltaThreshold,
+    pub unmapped_categories: UnmappedCategories,
+    pub dup_name_rules_path: PathBuf,
+    pub changes_since: Option<DataVersion>,
+    pub resolve_id: String,
     pub flags: Flags,
 }
 
@@ -87,8 +139,18 @@ pub async fn get_params(args: Vec<OsString>) -> Result<InitParams, AppError> {
             output_folder: PathBuf::new(),
             source_file_name: "".to_string(),
             output_file_name: "".to_string(),
-            data_version: "".to_string(),
+            data_version: None,
             data_date: "".to_string(),
+            baseline_date: env_reader::fetch_baseline_date(),
+            source_format: "".to_string(),
+            import_batch_size: env_reader::fetch_import_batch_size(),
+            import_concurrency: env_reader::fetch_import_concurrency(),
+            snapshot_retention: env_reader::fetch_snapshot_retention(),
+            delta_threshold: env_reader::fetch_delta_threshold(),
+            unmapped_categories: env_reader::fetch_unmapped_categories(),
+            dup_name_rules_path: env_reader::fetch_dup_name_rules_path(),
+            changes_since: None,
+            resolve_id: "".to_string(),
             flags: cli_pars.flags,
         })
     }
@@ -112,7 +174,7 @@ pub async fn get_params(args: Vec<OsString>) -> Result<InitParams, AppError> {
         {   
             data_folder_good = false;
         }
-        if !data_folder_good && cli_pars.flags.import_ror { 
+        if !data_folder_good && (cli_pars.flags.import_ror || cli_pars.flags.batch_import) { 
             let msg = "Required data folder does not exists or is not accessible";
             let cf_err = CustomError::new(msg);
             return Result::Err(AppError::CsErr(cf_err));
@@ -202,11 +264,40 @@ pub async fn get_params(args: Vec<OsString>) -> Result<InitParams, AppError> {
             }
         }
 
+        // The version code must be parseable, so that versions can be ordered and registered.
+
+        let data_version = match data_version.as_str() {
+            "" => None,
+            code => match DataVersion::parse(code) {
+                Some(dv) => Some(dv),
+                None => {
+                    let msg = format!("Data version '{}' is not a valid version code (e.g. v1.59, v1.45.1)", code);
+                    let cf_err = CustomError::new(&msg);
+                    return Result::Err(AppError::CsErr(cf_err));
+                },
+            },
+        };
+
+        // The version given for the changed records export must also be a valid code.
+
+        let changes_since = match cli_pars.changes_since.as_str() {
+            "" => None,
+            code => match DataVersion::parse(code) {
+                Some(dv) => Some(dv),
+                None => {
+                    let msg = format!("Version '{}' given for --changes-since is not a valid version code", code);
+                    let cf_err = CustomError::new(&msg);
+                    return Result::Err(AppError::CsErr(cf_err));
+                },
+            },
+        };
+
         // get the output file name - if anywhere it is in the .env variables
         
         let mut output_file_name =  env_reader::fetch_output_file_name();
         if output_file_name == "" {
-            output_file_name = format!("{} summary", data_version).to_string()
+            let vcode = data_version.as_ref().map(|v| v.code()).unwrap_or_default();
+            output_file_name = format!("{} summary", vcode).to_string()
         }
         let datetime_string = Local::now().format("%m-%d %H%M%S").to_string();
         output_file_name = format!("{} at {}.txt", output_file_name, datetime_string);
@@ -221,6 +312,16 @@ pub async fn get_params(args: Vec<OsString>) -> Result<InitParams, AppError> {
             output_file_name,
             data_version,
             data_date,
+            baseline_date: env_reader::fetch_baseline_date(),
+            source_format: cli_pars.source_format,
+            import_batch_size: env_reader::fetch_import_batch_size(),
+            import_concurrency: env_reader::fetch_import_concurrency(),
+            snapshot_retention: env_reader::fetch_snapshot_retention(),
+            delta_threshold: env_reader::fetch_delta_threshold(),
+            unmapped_categories: env_reader::fetch_unmapped_categories(),
+            dup_name_rules_path: env_reader::fetch_dup_name_rules_path(),
+            changes_since,
+            resolve_id: cli_pars.resolve_id,
             flags: cli_pars.flags,
         })
     }
@@ -281,17 +382,47 @@ pub async fn create_lup_tables(pool : &Pool<Postgres>) -> Result<(), AppError>
             return Err(e)
             },
     };
+    match lup_fill_tables::fill_lang_codes(pool).await {
+        Ok(n) => info!("{} language codes added to lup.lang_codes", n),
+        Err(e) => {
+            error!("An error occured while loading the language codes into lup.lang_codes: {}", e);
+            return Err(e)
+            },
+    };
+    create_lang_code_replacements(pool).await
+}
+
+
+pub async fn create_lang_code_replacements(pool : &Pool<Postgres>) -> Result<(), AppError>
+{
+    // Called when the lup tables are created, and when processing data
+    // if the replacements table is not yet present.
+
+    match lup_create_tables::create_lang_code_replacements_table(pool).await {
+        Ok(()) => {},
+        Err(e) => {
+            error!("An error occured while creating the lup.lang_code_replacements table: {}", e);
+            return Err(e)
+            },
+    };
+    match lup_fill_tables::fill_lang_code_replacements(pool).await {
+        Ok(()) => info!("Language code replacements added to lup.lang_code_replacements"),
+        Err(e) => {
+            error!("An error occured while inserting data into lup.lang_code_replacements: {}", e);
+            return Err(e)
+            },
+    };
     Ok(())
 }
 
 
-fn is_compliant_file_name(input: &str) -> bool {
+pub(crate) fn is_compliant_file_name(input: &str) -> bool {
     let file_name_pattern = r#"^v[0-9]+(\.[0-9]+){0,2}(-| )20[0-9]{2}-?[01][0-9]-?[0-3][0-9]"#;
     let re = Regex::new(file_name_pattern).unwrap();
     re.is_match(input)
 }
 
-fn get_data_version(input: &str) -> String {
+pub(crate) fn get_data_version(input: &str) -> String {
 
     let version_pattern = r#"^v[0-9]+(\.[0-9]+){0,2}"#;
     let re = Regex::new(version_pattern).unwrap();
@@ -304,7 +435,7 @@ fn get_data_version(input: &str) -> String {
     }
 }
 
-fn get_data_date(input: &str) -> String {            
+pub(crate) fn get_data_date(input: &str) -> String {            
     
     let date_pattern = r#"20[0-9]{2}-?[01][0-9]-?[0-3][0-9]"#;
     let re = Regex::new(date_pattern).unwrap();
@@ -378,6 +509,19 @@ mod tests {
       assert_eq!(get_data_date(&test_file_name), "2025-01-23");
    }
    
+   #[test]
+   fn check_file_name_regex_works_with_archives () {
+      let test_file_name = "v1.59-2025-01-23-ror-data.zip".to_string();
+      assert_eq!(is_compliant_file_name(&test_file_name), true);
+      assert_eq!(get_data_version(&test_file_name), "v1.59");
+      assert_eq!(get_data_date(&test_file_name), "2025-01-23");
+
+      let test_file_name = "v1.59-2025-01-23-ror-data_schema_v2.json.gz".to_string();
+      assert_eq!(is_compliant_file_name(&test_file_name), true);
+      assert_eq!(get_data_version(&test_file_name), "v1.59");
+      assert_eq!(get_data_date(&test_file_name), "2025-01-23");
+   }
+   
    #[test]
     fn check_file_name_regex_works_7 () {
         let test_file_name = "1.50 2024-12-11.json".to_string();
@@ -436,7 +580,7 @@ mod tests {
             assert_eq!(res.source_file_name, "v1.58 20241211.json");
             let lt = Local::now().format("%m-%d %H%M%S").to_string();
             assert_eq!(res.output_file_name, format!("results 25.json at {}.txt", lt));
-            assert_eq!(res.data_version, "v1.58");
+            assert_eq!(res.data_version, DataVersion::parse("v1.58"));
             assert_eq!(res.data_date, "2024-12-11");
         }
        ).await;
@@ -477,7 +621,7 @@ mod tests {
             assert_eq!(res.source_file_name, "schema2 data.json");
             let lt = Local::now().format("%m-%d %H%M%S").to_string();
             assert_eq!(res.output_file_name, format!("results 27.json at {}.txt", lt));
-            assert_eq!(res.data_version, "v1.60");
+            assert_eq!(res.data_version, DataVersion::parse("v1.60"));
             assert_eq!(res.data_date, "2026-12-25");
         }
        ).await;
@@ -515,7 +659,7 @@ mod tests {
             assert_eq!(res.output_folder, PathBuf::new());
             assert_eq!(res.source_file_name, "".to_string());
             assert_eq!(res.output_file_name, "".to_string());
-            assert_eq!(res.data_version, "".to_string());
+            assert_eq!(res.data_version, None);
             assert_eq!(res.data_date, "".to_string());
         }
        ).await;
@@ -556,7 +700,7 @@ mod tests {
             assert_eq!(res.source_file_name, "schema2 data.json");
             let lt = Local::now().format("%m-%d %H%M%S").to_string();
             assert_eq!(res.output_file_name, format!("results 28.json at {}.txt", lt));
-            assert_eq!(res.data_version, "v1.60");
+            assert_eq!(res.data_version, DataVersion::parse("v1.60"));
             assert_eq!(res.data_date, "2026-12-25");
         }
       ).await;
@@ -596,7 +740,7 @@ mod tests {
             assert_eq!(res.source_file_name, "schema2 data.json");
             let lt = Local::now().format("%m-%d %H%M%S").to_string();
             assert_eq!(res.output_file_name, format!("results 28.json at {}.txt", lt));
-            assert_eq!(res.data_version, "v1.60");
+            assert_eq!(res.data_version, DataVersion::parse("v1.60"));
             assert_eq!(res.data_date, "2026-12-25");
         }
       ).await;
@@ -654,7 +798,7 @@ mod tests {
             assert_eq!(res.source_file_name, "schema2 data.json");
             let lt = Local::now().format("%m-%d %H%M%S").to_string();
             assert_eq!(res.output_file_name, format!("results 28.json at {}.txt", lt));
-            assert_eq!(res.data_version, "v1.60");
+            assert_eq!(res.data_version, DataVersion::parse("v1.60"));
             assert_eq!(res.data_date, "2026-12-25");
 
             }
@@ -2,10 +2,15 @@ mod smm_storer;
 mod smm_structs;
 pub mod smm_helper;
 mod smm_create_tables;
+pub mod smm_version_registry;
+pub mod smm_org_changes;
+pub mod smm_version_deltas;
+mod smm_dup_names;
 
 use log::{info, error};
 use sqlx::{Pool, Postgres};
 use crate::AppError;
+use crate::setup::DeltaThreshold;
 
 pub async fn create_smm_tables(pool : &Pool<Postgres>) -> Result<(), AppError>
 {
@@ -16,14 +21,61 @@ pub async fn create_smm_tables(pool : &Pool<Postgres>) -> Result<(), AppError>
             return Err(e)
             },
     };
+    match smm_version_registry::ensure_registry(pool).await {
+        Ok(()) => info!("Version registry available in smm schema"),
+        Err(e) => {
+            error!("An error occured while creating the version registry: {}", e);
+            return Err(e)
+            },
+    };
+    match smm_org_changes::ensure_tables(pool).await {
+        Ok(()) => info!("Organisation change tables available in smm schema"),
+        Err(e) => {
+            error!("An error occured while creating the organisation change tables: {}", e);
+            return Err(e)
+            },
+    };
+    match smm_version_deltas::ensure_tables(pool).await {
+        Ok(()) => info!("Version delta tables available in smm schema"),
+        Err(e) => {
+            error!("An error occured while creating the version delta tables: {}", e);
+            return Err(e)
+            },
+    };
+    match smm_dup_names::ensure_tables(pool).await {
+        Ok(()) => info!("Duplicate name statistics table available in smm schema"),
+        Err(e) => {
+            error!("An error occured while creating the duplicate name statistics table: {}", e);
+            return Err(e)
+            },
+    };
     Ok(())
 }
 
-pub async fn summarise_data(pool : &Pool<Postgres>) -> Result<(), AppError>
+pub async fn processing_required(vcode: &str, pool : &Pool<Postgres>) -> Result<bool, AppError>
+{
+    // Used when the import of a file has been skipped, as it was imported earlier.
+    // The data still needs processing unless the version is already in smm.version_summaries.
+
+    let sql = r#"select to_regclass('smm.version_summaries') is not null"#;
+    let exists: bool = sqlx::query_scalar(sql).fetch_one(pool).await?;
+    if !exists {
+        return Ok(true)
+    }
+    let sql = r#"select exists (select 1 from smm.version_summaries where vcode = $1)"#;
+    let summarised: bool = sqlx::query_scalar(sql).bind(vcode).fetch_one(pool).await?;
+    if summarised {
+        info!("Version {} has already been processed and summarised - the processing is skipped", vcode);
+        info!("Use --force to import and process the file again");
+    }
+    Ok(!summarised)
+}
+
+pub async fn summarise_data(delta_threshold: &DeltaThreshold, pool : &Pool<Postgres>) -> Result<(), AppError>
 {
-    // Store data into smm tables.
+    // Store data into smm tables, and compare it with that of the previous version.
 
-    match smm_storer::store_summary_data(pool).await
+    match smm_storer::store_summary_data(delta_threshold, pool).await
     {
         Ok(()) => {
             info!("All summary data transferred to smm tables"); 
@@ -0,0 +1,54 @@
+/***************************************************************************
+ * Keeps, for each version, the numbers of duplicated names found during
+ * processing, by group of duplicates (e.g. '2 name types'): the number of
+ * sets of duplicates, the number of names in them, the number deleted by
+ * the duplicate name rules, and the number of sets the rules left
+ * unresolved. The figures are taken from src.dup_names and
+ * src.dup_names_deleted, and stored in smm.dup_name_stats.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use crate::AppError;
+
+
+pub async fn ensure_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let sql = r#"SET client_min_messages TO WARNING;
+    create schema if not exists smm;
+
+    create table if not exists smm.dup_name_stats
+    (
+          vcode             varchar     not null
+        , dup_group         varchar     not null
+        , num_sets          int         not null
+        , num_names         int         not null
+        , num_deleted       int         not null
+        , num_unresolved    int         not null
+    );
+    create index if not exists dup_name_stats_idx on smm.dup_name_stats(vcode);
+    SET client_min_messages TO NOTICE;"#;
+
+    sqlx::raw_sql(sql).execute(pool).await?;
+    Ok(())
+}
+
+
+pub async fn store_dup_name_stats(vcode: &str, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let sql = r#"insert into smm.dup_name_stats (vcode, dup_group, num_sets, num_names, num_deleted, num_unresolved)
+    select $1, s.dup_type, s.num_sets, s.num_names, coalesce(d.num_deleted, 0), s.num_unresolved
+    from
+        (select dup_type, count(*) as num_sets, sum(num_names) as num_names,
+        count(*) filter (where num_kept > 1) as num_unresolved
+        from src.dup_names
+        group by dup_type) s
+    left join
+        (select dup_group, count(*) as num_deleted
+        from src.dup_names_deleted
+        group by dup_group) d
+    on s.dup_type = d.dup_group
+    order by s.dup_type;"#;
+
+    sqlx::query(sql).bind(vcode).execute(pool).await?;
+    Ok(())
+}
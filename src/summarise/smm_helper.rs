@@ -14,8 +14,13 @@ pub async fn delete_any_existing_data(vcode: &String, pool: &Pool<Postgres>) ->
                 DELETE from smm.ranked_distributions {}
                 DELETE from smm.singletons {}
                 DELETE from smm.org_type_and_lang_code {}
-                DELETE from smm.org_type_and_relationships {}"#
-                , wc, wc, wc, wc, wc, wc, wc);
+                DELETE from smm.org_type_and_relationships {}
+                DELETE from smm.org_fingerprints {}
+                DELETE from smm.org_changes {}
+                DELETE from smm.dup_name_stats {}
+                DELETE from smm.metric_deltas {}
+                DELETE from smm.version_deltas {}"#
+                , wc, wc, wc, wc, wc, wc, wc, wc, wc, wc, wc, wc);
 
    sqlx::raw_sql(&del_sql).execute(pool).await?;
    Ok(())
@@ -270,6 +275,29 @@ pub async fn create_count_distributions(sdv: &str, num_orgs_str: &String, pool:
     let rows: Vec<DistribRow> = sqlx::query_as(&sql).fetch_all(pool).await?;
     store_distrib(rows, "predecessor orgs", pool).await?;
 
+    // Hierarchy depth distribution - orgs in hierarchies only, by their greatest depth below a root
+
+    let sql = sdv.to_owned() + r#"depth as count, count(descendant) as num_of_orgs, 
+            round(count(descendant) * 10000 :: float / "# + num_orgs_str + r#":: float)/100 :: float as pc_of_orgs
+            from (select descendant, max(depth) as depth from src.hierarchy_paths
+                  group by descendant) h
+            group by depth
+            order by depth;"#;
+    let rows: Vec<DistribRow> = sqlx::query_as(&sql).fetch_all(pool).await?;
+    store_distrib(rows, "hierarchy depth", pool).await?;
+
+    // Family size distribution - the number of orgs (including the root) in each root's hierarchy.
+    // The numbers are of roots, so the percentages are of all the roots rather than all the orgs.
+
+    let sql = sdv.to_owned() + r#"family_size as count, count(root_id) as num_of_orgs, 
+            round(count(root_id) * 10000 :: float / sum(count(root_id)) over () :: float)/100 :: float as pc_of_orgs
+            from (select root_id, count(distinct descendant)::int as family_size from src.hierarchy_paths
+                  group by root_id) f
+            group by family_size
+            order by family_size;"#;
+    let rows: Vec<DistribRow> = sqlx::query_as(&sql).fetch_all(pool).await?;
+    store_distrib(rows, "family size", pool).await?;
+
 
     // Domains count distribution
 
@@ -433,13 +461,7 @@ pub async fn store_types_and_relationships(sdv: &str, rows: Vec<OrgRow>, pool: &
 
         // Get the data on the names linked to these organisations
 
-        let tr_sql = sdv.to_owned() + r#"case rs.rel_type
-                when 1 then 'has parent'  
-                when 2 then 'has child' 
-                when 3 then 'is related to' 
-                when 4 then 'has predecessor' 
-                when 5 then 'has successor' 
-                end as rtype, 
+        let tr_sql = sdv.to_owned() + r#"(select l.name from lup.ror_org_rels l where l.id = rs.rel_type) as rtype, 
             count(rs.id) as num_rels,
             count(distinct rs.id) as num_orgs,
             round((count(distinct rs.id) * 10000::float /"#
@@ -537,12 +559,26 @@ pub async fn store_singletons(vcode: &String, num_orgs: i64, num_names: i64, poo
     store_singleton(vcode, "nacro_ne", "Nacro names not in English, number & pc of nacro names",  
                     num_nacro_ne, Some(pc_nacro_ne), pool).await?;
 
+    let num_names_ulc = get_count(r#"select count(*) from src.names n where n.lang_code is not null
+                        and not exists (select 1 from lup.lang_codes c where c.code = n.lang_code)"#, pool).await?;
+    store_singleton(vcode, "names_ulc", "Names with lang codes not in lup, number & pc of names",  
+                    num_names_ulc, Some(get_pc(num_names_ulc, num_names)), pool).await?;
+
     store_singleton(vcode, "names_nl", "Names not in Latin, number and pc of names",  
                     num_names_nl, Some(pc_names_nl), pool).await?;
     store_singleton(vcode, "acro_nl", "Acronyms not in Latin, number and pc of acronyms",  
                     num_acro_nl, Some(pc_acro_nl), pool).await?;
     store_singleton(vcode, "nacro_nl", "Nacro names not in Latin, number and pc of nacro names",  
                     num_nacro_nl, Some(pc_nacro_nl), pool).await?;          
+
+    // Names that mix scripts, and the organisations that have them
+
+    let num_names_mxs = get_count("select count(*) from src.names where is_mixed_script", pool).await?;
+    let num_orgs_mxs = get_count("select count(distinct id) from src.names where is_mixed_script", pool).await?;
+    store_singleton(vcode, "names_mxs", "Names in mixed scripts, number and pc of names",  
+                    num_names_mxs, Some(get_pc(num_names_mxs, num_names)), pool).await?;
+    store_singleton(vcode, "orgs_mxs", "Orgs with mixed script names, number & pc of orgs",  
+                    num_orgs_mxs, Some(get_pc(num_orgs_mxs, num_orgs)), pool).await?;
     
     // Relationship data points
 
@@ -551,6 +587,13 @@ pub async fn store_singletons(vcode: &String, num_orgs: i64, num_names: i64, poo
     store_singleton(vcode, "parch", "Orgs both parent and child, number & pc of total orgs",  
                         parch_orgs, Some(parch_orgs_pc), pool).await?;
 
+    let mpar_orgs = get_count("select count(distinct id) from src.hierarchy_issues where issue_type = 'multiple parents'", pool).await?;
+    let cycle_orgs = get_count("select count(distinct id) from src.hierarchy_issues where issue_type = 'cycle'", pool).await?;
+    store_singleton(vcode, "hier_mpar", "Orgs with more than one parent, number & pc of total orgs",  
+                        mpar_orgs, Some(get_pc(mpar_orgs, num_orgs)), pool).await?;
+    store_singleton(vcode, "hier_cyc", "Orgs in parent-child cycles, number & pc of total orgs",  
+                        cycle_orgs, Some(get_pc(cycle_orgs, num_orgs)), pool).await?;
+
     let par_no_child = get_rel_imbalance(1, 2, pool).await.unwrap();
     let par_no_parent = get_rel_imbalance(2, 1, pool).await.unwrap();
     let non_recip_pc = par_no_child + par_no_parent;
@@ -0,0 +1,203 @@
+/***************************************************************************
+ * Detects the changes to individual organisations between a version and
+ * the previous version in the series (as given by the version registry,
+ * ignoring any excluded versions). The comparison uses 'fingerprints' of
+ * each organisation - its status, and the (sorted) lists of its names,
+ * locations and relationships - taken from the ror tables, and kept, for
+ * each version, in smm.org_fingerprints. This means earlier versions can
+ * be compared without retaining all of their data.
+ * Each change found is stored as a typed row in smm.org_changes, with the
+ * old and new values where relevant, and the number of each type of change
+ * is added to the version's record in smm.version_summaries.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use log::info;
+use crate::AppError;
+use crate::setup::data_version::DataVersion;
+use super::smm_version_registry::select_previous_version;
+
+// The change types, the version_summaries column holding the number of each,
+// and a short description for the text report.
+
+pub const CHANGE_TYPES: [(&str, &str, &str); 9] = [
+    ("added", "n_added", "Organisations added"),
+    ("removed", "n_removed", "Organisations no longer present"),
+    ("withdrawn", "n_withdrawn", "Organisations withdrawn"),
+    ("status_changed", "n_status_changed", "Other changes of status"),
+    ("name_added", "n_names_added", "Names added to existing orgs"),
+    ("name_removed", "n_names_removed", "Names removed from existing orgs"),
+    ("location_changed", "n_locs_changed", "Existing orgs with changed locations"),
+    ("relationship_added", "n_rels_added", "Relationships added to existing orgs"),
+    ("relationship_removed", "n_rels_removed", "Relationships removed from existing orgs"),
+];
+
+
+pub async fn ensure_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // The fingerprints and changes are kept permanently, like the registry. The
+    // change counts are added to version_summaries if not already present.
+
+    let mut sql = r#"SET client_min_messages TO WARNING;
+    create schema if not exists smm;
+
+    create table if not exists smm.org_fingerprints
+    (
+          vcode             varchar     not null
+        , id                varchar     not null
+        , status            varchar     not null
+        , names             varchar[]   not null
+        , locations         varchar[]   not null
+        , relationships     varchar[]   not null
+    );
+    create index if not exists org_fingerprints_idx on smm.org_fingerprints(vcode, id);
+
+    create table if not exists smm.org_changes
+    (
+          vcode             varchar     not null
+        , prev_vcode        varchar     not null
+        , id                varchar     not null
+        , change_type       varchar     not null
+        , old_value         varchar     null
+        , new_value         varchar     null
+    );
+    create index if not exists org_changes_idx on smm.org_changes(vcode, change_type);
+
+    alter table smm.version_summaries add column if not exists prev_vcode varchar null;
+    "#.to_string();
+
+    for (_, col, _) in CHANGE_TYPES {
+        sql += &format!("alter table smm.version_summaries add column if not exists {} int null;\n", col);
+    }
+    sql += "SET client_min_messages TO NOTICE;";
+    sqlx::raw_sql(&sql).execute(pool).await?;
+    Ok(())
+}
+
+
+async fn store_fingerprints(vcode: &str, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let sql = r#"delete from smm.org_fingerprints where vcode = $1;"#;
+    sqlx::query(sql).bind(vcode).execute(pool).await?;
+
+    let sql = format!(r#"insert into smm.org_fingerprints (vcode, id, status, names, locations, relationships)
+                 select $1, fp.* from ({}) fp;"#, get_fingerprints_sql());
+    sqlx::query(&sql).bind(vcode).execute(pool).await?;
+    Ok(())
+}
+
+
+pub fn get_fingerprints_sql <'a>() -> &'a str {
+
+    // The fingerprint of each organisation in the ror tables. Also used by the changes 
+    // feed, to find modified records that have no last modified date (i.e. from v1 data).
+
+    r#"with nm as (
+        select id, array_agg(name_type || '|' || coalesce(lang, '') || '|' || value
+                             order by name_type, lang, value) as names
+        from ror.names group by id),
+    lc as (
+        select id, array_agg(coalesce(geonames_id::varchar, '') || ' ' || coalesce(name, '')
+                             order by geonames_id) as locations
+        from ror.locations group by id),
+    rl as (
+        select id, array_agg(rel_type || ': ' || related_id
+                             order by rel_type, related_id) as relationships
+        from ror.relationships group by id)
+    select c.id, c.status, coalesce(nm.names, '{}')::varchar[] as names,
+    coalesce(lc.locations, '{}')::varchar[] as locations, coalesce(rl.relationships, '{}')::varchar[] as relationships
+    from ror.core_data c
+    left join nm on c.id = nm.id
+    left join lc on c.id = lc.id
+    left join rl on c.id = rl.id"#
+}
+
+
+pub async fn get_previous_version(dv: &DataVersion, pool: &Pool<Postgres>) -> Result<Option<DataVersion>, AppError> {
+
+    // The latest earlier version, not excluded from the series, that has fingerprints.
+
+    let sql = r#"select distinct f.vcode from smm.org_fingerprints f
+                 inner join smm.version_registry vr on f.vcode = vr.vcode
+                 where not vr.excluded_from_series"#;
+    let vcodes: Vec<String> = sqlx::query_scalar(sql).fetch_all(pool).await?;
+    Ok(select_previous_version(dv, &vcodes))
+}
+
+
+async fn store_changes(vcode: &str, prev_vcode: &str, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let sql = r#"delete from smm.org_changes where vcode = $1;"#;
+    sqlx::query(sql).bind(vcode).execute(pool).await?;
+
+    // Each statement compares the fingerprints of the current (c) and previous (p) versions.
+
+    let statements = [
+        r#"select c.id, 'added', null, c.status from cur c
+           where not exists (select 1 from prev p where p.id = c.id)"#,
+        r#"select p.id, 'removed', p.status, null from prev p
+           where not exists (select 1 from cur c where c.id = p.id)"#,
+        r#"select c.id, case when c.status = 'withdrawn' then 'withdrawn' else 'status_changed' end,
+           p.status, c.status from cur c inner join prev p on c.id = p.id
+           where c.status <> p.status"#,
+        r#"select c.id, 'name_added', null, x from cur c inner join prev p on c.id = p.id,
+           unnest(c.names) x where not x = any(p.names)"#,
+        r#"select c.id, 'name_removed', x, null from cur c inner join prev p on c.id = p.id,
+           unnest(p.names) x where not x = any(c.names)"#,
+        r#"select c.id, 'location_changed', array_to_string(p.locations, '; '), array_to_string(c.locations, '; ')
+           from cur c inner join prev p on c.id = p.id
+           where c.locations <> p.locations"#,
+        r#"select c.id, 'relationship_added', null, x from cur c inner join prev p on c.id = p.id,
+           unnest(c.relationships) x where not x = any(p.relationships)"#,
+        r#"select c.id, 'relationship_removed', x, null from cur c inner join prev p on c.id = p.id,
+           unnest(p.relationships) x where not x = any(c.relationships)"#,
+    ];
+
+    for select_sql in statements {
+        let sql = format!(r#"with cur as (select * from smm.org_fingerprints where vcode = $1),
+                             prev as (select * from smm.org_fingerprints where vcode = $2)
+                             insert into smm.org_changes (vcode, prev_vcode, id, change_type, old_value, new_value)
+                             select $1, $2, ch.* from ({}) ch;"#, select_sql);
+        sqlx::query(&sql).bind(vcode).bind(prev_vcode).execute(pool).await?;
+    }
+    Ok(())
+}
+
+
+async fn store_change_counts(vcode: &str, prev_vcode: Option<&str>, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // With no previous version the counts are left as nulls.
+
+    let mut sets = vec!["prev_vcode = $2".to_string()];
+    if prev_vcode.is_some() {
+        for (change_type, col, _) in CHANGE_TYPES {
+            sets.push(format!("{} = (select count(*) from smm.org_changes where vcode = $1 and change_type = '{}')",
+                              col, change_type));
+        }
+    }
+    let sql = format!("update smm.version_summaries set {} where vcode = $1", sets.join(", "));
+    sqlx::query(&sql).bind(vcode).bind(prev_vcode).execute(pool).await?;
+    Ok(())
+}
+
+
+pub async fn detect_changes(dv: &DataVersion, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let vcode = dv.code();
+    store_fingerprints(&vcode, pool).await?;
+
+    match get_previous_version(dv, pool).await? {
+        Some(prev) => {
+            let prev_vcode = prev.code();
+            store_changes(&vcode, &prev_vcode, pool).await?;
+            store_change_counts(&vcode, Some(&prev_vcode), pool).await?;
+            info!("Changes to organisations since {} stored", prev_vcode);
+        },
+        None => {
+            store_change_counts(&vcode, None, pool).await?;
+            info!("No earlier version available to compare organisations with");
+        },
+    }
+    Ok(())
+}
+
@@ -1,11 +1,18 @@
 use super::smm_helper;
+use super::smm_version_registry;
+use super::smm_org_changes;
+use super::smm_version_deltas;
+use super::smm_dup_names;
 use super::smm_structs::FileParams;
 use sqlx::{Pool, Postgres};
 use chrono::NaiveDate;
 use crate::AppError;
+use crate::error_defs::CustomError;
+use crate::setup::DeltaThreshold;
+use crate::setup::data_version::DataVersion;
 use log::info;
 
-pub async fn store_summary_data (pool: &Pool<Postgres>) -> Result<(), AppError> {
+pub async fn store_summary_data (delta_threshold: &DeltaThreshold, pool: &Pool<Postgres>) -> Result<(), AppError> {
     
     // Obtain the data version and date (as previously stored in table during import process)
     // and derive standard first two items of many sql statements below.
@@ -18,9 +25,21 @@ pub async fn store_summary_data (pool: &Pool<Postgres>) -> Result<(), AppError>
     let vdays = fp.vdays;
     let sdv = "select \'".to_string() + &vcode + "\' as vcode, ";
 
+    let dv = match DataVersion::parse(&vcode) {
+        Some(dv) => dv,
+        None => {
+            let msg = format!("Version '{}' is not a valid version code and cannot be summarised", vcode);
+            return Err(AppError::CsErr(CustomError::new(&msg)));
+        },
+    };
+
     // Delete existing data in smm. tables and construct the initial version
     // summary table by obtaining record counts of all src tables.                  
 
+    smm_version_registry::ensure_registry(pool).await?;
+    smm_org_changes::ensure_tables(pool).await?;
+    smm_version_deltas::ensure_tables(pool).await?;
+    smm_dup_names::ensure_tables(pool).await?;
     smm_helper::delete_any_existing_data(&vcode, pool).await?;
 
     let num_orgs = smm_helper::get_count("select count(*) from src.core_data", pool).await?;
@@ -70,5 +89,17 @@ pub async fn store_summary_data (pool: &Pool<Postgres>) -> Result<(), AppError>
 
     smm_helper::store_singletons(&vcode, num_orgs, num_names, pool).await?;
 
+    smm_dup_names::store_dup_name_stats(&vcode, pool).await?;
+    info!("Duplicate name statistics stored");
+
+    // Add the version to the registry, so it can be placed in the series of versions,
+    // and compare its organisations, and its metrics, with those of the previous version in that series.
+
+    smm_version_registry::register_version(&dv, vdate, pool).await?;
+    info!("Version {} registered", vcode);
+
+    smm_org_changes::detect_changes(&dv, pool).await?;
+    smm_version_deltas::compute_deltas(&dv, delta_threshold, pool).await?;
+
     Ok(())
 }
@@ -0,0 +1,262 @@
+/***************************************************************************
+ * Compares the summary metrics of a version with those of the previous
+ * version in the series (as given by the version registry, ignoring any
+ * excluded versions). Every count held in the smm tables for a version -
+ * the entity numbers in version_summaries, the numbers of attributes and
+ * orgs in attributes_summary, the numbers in count_distributions,
+ * ranked_distributions and singletons - is treated as a metric, identified
+ * by its group, name and measure. For each metric the absolute and
+ * percentage changes are stored in smm.metric_deltas, and the metric is
+ * flagged if the change is at or beyond the configured threshold (e.g. a
+ * sudden drop in the number of wikidata ids). Very small metrics, with an
+ * earlier value below the configured minimum, are not flagged. A summary
+ * row for each version, with the threshold used, is kept in
+ * smm.version_deltas.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use std::collections::BTreeMap;
+use log::info;
+use crate::AppError;
+use crate::setup::DeltaThreshold;
+use crate::setup::data_version::DataVersion;
+use super::smm_version_registry::select_previous_version;
+
+// The version_summaries columns treated as metrics, with their labels in the text report.
+
+pub const SUMMARY_METRICS: [(&str, &str); 8] = [
+    ("num_orgs", "Organisations"),
+    ("num_names", "Names"),
+    ("num_types", "Types"),
+    ("num_links", "Links"),
+    ("num_ext_ids", "External Ids"),
+    ("num_rels", "Relationships"),
+    ("num_locations", "Locations"),
+    ("num_domains", "Domains"),
+];
+
+// A metric is identified by its group, name and measure.
+
+pub type MetricKey = (String, String, String);
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct MetricRow {
+    pub metric_group: String,
+    pub metric: String,
+    pub measure: String,
+    pub value: i64,
+}
+
+#[derive(Debug, PartialEq)]
+struct MetricDelta {
+    metric_group: String,
+    metric: String,
+    measure: String,
+    prev_value: Option<i64>,
+    value: Option<i64>,
+    abs_delta: i64,
+    pc_delta: Option<f64>,
+    flagged: bool,
+}
+
+
+pub async fn ensure_tables(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // Like the org changes, the deltas are kept permanently.
+
+    let sql = r#"SET client_min_messages TO WARNING;
+    create schema if not exists smm;
+
+    create table if not exists smm.version_deltas
+    (
+          vcode             varchar     not null primary key
+        , prev_vcode        varchar     null
+        , threshold_pc      real        not null
+        , min_base          int         not null
+        , num_metrics       int         not null
+        , num_flagged       int         not null
+    );
+
+    create table if not exists smm.metric_deltas
+    (
+          vcode             varchar     not null
+        , prev_vcode        varchar     not null
+        , metric_group      varchar     not null
+        , metric            varchar     not null
+        , measure           varchar     not null
+        , prev_value        bigint      null
+        , value             bigint      null
+        , abs_delta         bigint      not null
+        , pc_delta          real        null
+        , flagged           bool        not null
+    );
+    create index if not exists metric_deltas_idx on smm.metric_deltas(vcode, metric_group);
+
+    SET client_min_messages TO NOTICE;"#;
+    sqlx::raw_sql(sql).execute(pool).await?;
+    Ok(())
+}
+
+
+async fn get_previous_version(dv: &DataVersion, pool: &Pool<Postgres>) -> Result<Option<DataVersion>, AppError> {
+
+    // The latest earlier version, not excluded from the series, that has been summarised.
+
+    let sql = r#"select vs.vcode from smm.version_summaries vs
+                 inner join smm.version_registry vr on vs.vcode = vr.vcode
+                 where not vr.excluded_from_series"#;
+    let vcodes: Vec<String> = sqlx::query_scalar(sql).fetch_all(pool).await?;
+    Ok(select_previous_version(dv, &vcodes))
+}
+
+
+pub async fn get_metrics(vcode: &str, pool: &Pool<Postgres>) -> Result<Vec<MetricRow>, AppError> {
+
+    // Also used to build the all-versions (wide format) export.
+
+    let summary_values = SUMMARY_METRICS.iter()
+        .map(|(col, _)| format!("('{}', vs.{})", col, col))
+        .collect::<Vec<String>>().join(", ");
+
+    let sql = format!(r#"select 'summary' as metric_group, m.metric, 'number' as measure, m.value::bigint as value
+                 from smm.version_summaries vs
+                 cross join lateral (values {}) as m(metric, value)
+                 where vs.vcode = $1 and m.value is not null
+                 union all
+                 select 'attributes', att_name || ': ' || name, 'number_atts', number_atts::bigint
+                 from smm.attributes_summary where vcode = $1 and number_atts is not null
+                 union all
+                 select 'attributes', att_name || ': ' || name, 'number_orgs', number_orgs::bigint
+                 from smm.attributes_summary where vcode = $1 and number_orgs is not null
+                 union all
+                 select 'counts', count_type || ': ' || count, 'num_of_orgs', num_of_orgs::bigint
+                 from smm.count_distributions where vcode = $1 and num_of_orgs is not null
+                 union all
+                 select 'ranked', dist_type || ': ' || entity, 'number', number::bigint
+                 from smm.ranked_distributions where vcode = $1 and number is not null
+                 union all
+                 select 'singletons', id, 'number', number::bigint
+                 from smm.singletons where vcode = $1 and number is not null"#, summary_values);
+
+    let rows: Vec<MetricRow> = sqlx::query_as(&sql).bind(vcode).fetch_all(pool).await?;
+    Ok(rows)
+}
+
+
+fn calculate_deltas(current: Vec<MetricRow>, previous: Vec<MetricRow>, threshold: &DeltaThreshold) -> Vec<MetricDelta> {
+
+    // A metric present in only one of the versions is treated as zero in the other.
+    // A metric that has appeared is flagged if it has reached the minimum value.
+
+    let mut pairs: BTreeMap<MetricKey, (Option<i64>, Option<i64>)> = BTreeMap::new();
+    for r in previous {
+        pairs.entry((r.metric_group, r.metric, r.measure)).or_insert((None, None)).0 = Some(r.value);
+    }
+    for r in current {
+        pairs.entry((r.metric_group, r.metric, r.measure)).or_insert((None, None)).1 = Some(r.value);
+    }
+
+    pairs.into_iter().map(|((metric_group, metric, measure), (prev_value, value))| {
+        let p = prev_value.unwrap_or(0);
+        let v = value.unwrap_or(0);
+        let abs_delta = v - p;
+        let pc_delta = if p > 0 { Some(100.0 * abs_delta as f64 / p as f64) } else { None };
+        let flagged = abs_delta != 0 && match pc_delta {
+            Some(pc) => p >= threshold.min_base && pc.abs() >= threshold.pc,
+            None => v >= threshold.min_base,
+        };
+        MetricDelta { metric_group, metric, measure, prev_value, value, abs_delta, pc_delta, flagged }
+    }).collect()
+}
+
+
+async fn store_deltas(vcode: &str, prev_vcode: &str, deltas: &[MetricDelta], pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let sql = r#"insert into smm.metric_deltas (vcode, prev_vcode, metric_group, metric, measure,
+                 prev_value, value, abs_delta, pc_delta, flagged)
+                 select $1, $2, * from unnest($3::varchar[], $4::varchar[], $5::varchar[],
+                 $6::bigint[], $7::bigint[], $8::bigint[], $9::float8[], $10::bool[])"#;
+    sqlx::query(sql).bind(vcode).bind(prev_vcode)
+    .bind(deltas.iter().map(|d| d.metric_group.clone()).collect::<Vec<String>>())
+    .bind(deltas.iter().map(|d| d.metric.clone()).collect::<Vec<String>>())
+    .bind(deltas.iter().map(|d| d.measure.clone()).collect::<Vec<String>>())
+    .bind(deltas.iter().map(|d| d.prev_value).collect::<Vec<Option<i64>>>())
+    .bind(deltas.iter().map(|d| d.value).collect::<Vec<Option<i64>>>())
+    .bind(deltas.iter().map(|d| d.abs_delta).collect::<Vec<i64>>())
+    .bind(deltas.iter().map(|d| d.pc_delta).collect::<Vec<Option<f64>>>())
+    .bind(deltas.iter().map(|d| d.flagged).collect::<Vec<bool>>())
+    .execute(pool).await?;
+    Ok(())
+}
+
+
+pub async fn compute_deltas(dv: &DataVersion, threshold: &DeltaThreshold, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let vcode = dv.code();
+    sqlx::query("delete from smm.metric_deltas where vcode = $1").bind(&vcode).execute(pool).await?;
+    sqlx::query("delete from smm.version_deltas where vcode = $1").bind(&vcode).execute(pool).await?;
+
+    let prev = get_previous_version(dv, pool).await?;
+    let (prev_vcode, num_metrics, num_flagged) = match prev {
+        Some(prev) => {
+            let prev_vcode = prev.code();
+            let current = get_metrics(&vcode, pool).await?;
+            let previous = get_metrics(&prev_vcode, pool).await?;
+            let deltas = calculate_deltas(current, previous, threshold);
+            store_deltas(&vcode, &prev_vcode, &deltas, pool).await?;
+            let num_flagged = deltas.iter().filter(|d| d.flagged).count();
+            info!("{} metrics compared with {}, {} flagged as changed by {}% or more",
+                  deltas.len(), prev_vcode, num_flagged, threshold.pc);
+            (Some(prev_vcode), deltas.len(), num_flagged)
+        },
+        None => {
+            info!("No earlier version available to compare metrics with");
+            (None, 0, 0)
+        },
+    };
+
+    let sql = r#"insert into smm.version_deltas (vcode, prev_vcode, threshold_pc, min_base, num_metrics, num_flagged)
+                 values ($1, $2, $3, $4, $5, $6)"#;
+    sqlx::query(sql).bind(&vcode).bind(prev_vcode).bind(threshold.pc as f32).bind(threshold.min_base as i32)
+    .bind(num_metrics as i32).bind(num_flagged as i32)
+    .execute(pool).await?;
+    Ok(())
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(metric: &str, value: i64) -> MetricRow {
+        MetricRow { metric_group: "attributes".to_string(), metric: metric.to_string(),
+                    measure: "number_atts".to_string(), value }
+    }
+
+    #[test]
+    fn check_deltas_and_flags() {
+        let threshold = DeltaThreshold { pc: 10.0, min_base: 100 };
+        let previous = vec![row("external id types: wikidata", 1000), row("external id types: isni", 1000),
+                            row("link types: wikipedia", 50), row("name types: alias", 200)];
+        let current = vec![row("external id types: wikidata", 700), row("external id types: isni", 1050),
+                           row("link types: wikipedia", 10), row("name types: acronym", 150)];
+        let deltas = calculate_deltas(current, previous, &threshold);
+        let get = |m: &str| deltas.iter().find(|d| d.metric == m).unwrap();
+
+        let d = get("external id types: wikidata");
+        assert_eq!((d.abs_delta, d.pc_delta, d.flagged), (-300, Some(-30.0), true));
+        let d = get("external id types: isni");
+        assert_eq!((d.abs_delta, d.pc_delta, d.flagged), (50, Some(5.0), false));
+
+        // below the minimum base, so not flagged despite the large change
+        assert!(!get("link types: wikipedia").flagged);
+
+        // metrics that disappear or appear
+        let d = get("name types: alias");
+        assert_eq!((d.value, d.abs_delta, d.pc_delta, d.flagged), (None, -200, Some(-100.0), true));
+        let d = get("name types: acronym");
+        assert_eq!((d.prev_value, d.abs_delta, d.pc_delta, d.flagged), (None, 150, None, true));
+    }
+}
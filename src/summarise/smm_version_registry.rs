@@ -0,0 +1,111 @@
+/***************************************************************************
+ * The version registry (smm.version_registry) lists each version that has
+ * been summarised, with the numeric parts of its code, so that versions can
+ * be put in their proper order, and an 'excluded_from_series' flag. Versions
+ * with the flag set are left out of the all-versions (series) outputs. The
+ * flag is set directly in the table, e.g.
+ *   update smm.version_registry set excluded_from_series = true where vcode = 'v1.57';
+ * Unlike the other smm tables the registry is never dropped, so that these
+ * settings persist. v1.57 is registered as excluded when the table is created.
+ ***************************************************************************/
+
+use sqlx::{Pool, Postgres};
+use chrono::NaiveDate;
+use log::warn;
+use crate::AppError;
+use crate::setup::data_version::DataVersion;
+
+pub async fn ensure_registry(pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    let sql = r#"SET client_min_messages TO WARNING;
+    create schema if not exists smm;
+
+    create table if not exists smm.version_registry
+    (
+          vcode                 varchar     not null primary key
+        , major                 int         not null
+        , minor                 int         not null
+        , patch                 int         not null
+        , vdate                 date        null
+        , excluded_from_series  bool        not null default false
+        , registered_at         timestamp   not null default now()
+    );
+
+    insert into smm.version_registry (vcode, major, minor, patch, excluded_from_series)
+    values ('v1.57', 1, 57, 0, true)
+    on conflict (vcode) do nothing;
+
+    SET client_min_messages TO NOTICE;"#;
+    sqlx::raw_sql(sql).execute(pool).await?;
+
+    // Register any versions already summarised (e.g. before the registry existed).
+
+    let sql = "select to_regclass('smm.version_summaries') is not null";
+    let summaries_exist: bool = sqlx::query_scalar(sql).fetch_one(pool).await?;
+    if !summaries_exist {
+        return Ok(())
+    }
+    let sql = r#"select vs.vcode, vs.vdate from smm.version_summaries vs
+                 where not exists (select 1 from smm.version_registry vr
+                                   where vr.vcode = vs.vcode and vr.vdate is not null)"#;
+    let rows: Vec<(String, NaiveDate)> = sqlx::query_as(sql).fetch_all(pool).await?;
+    for (vcode, vdate) in rows {
+        match DataVersion::parse(&vcode) {
+            Some(dv) => register_version(&dv, vdate, pool).await?,
+            None => warn!("Summarised version '{}' is not a valid version code and cannot be registered", vcode),
+        }
+    }
+    Ok(())
+}
+
+
+pub async fn register_version(dv: &DataVersion, vdate: NaiveDate, pool: &Pool<Postgres>) -> Result<(), AppError> {
+
+    // Re-registering a version updates its date but leaves its exclusion flag as it was.
+
+    let sql = r#"insert into smm.version_registry (vcode, major, minor, patch, vdate)
+                 values ($1, $2, $3, $4, $5)
+                 on conflict (vcode) do update set major = excluded.major,
+                 minor = excluded.minor, patch = excluded.patch, vdate = excluded.vdate"#;
+    sqlx::query(sql).bind(dv.code()).bind(dv.major as i32).bind(dv.minor as i32)
+    .bind(dv.patch as i32).bind(vdate)
+    .execute(pool).await?;
+    Ok(())
+}
+
+
+pub async fn remove_version(vcode: &str, pool: &Pool<Postgres>) -> Result<(), AppError> {
+    sqlx::query("delete from smm.version_registry where vcode = $1")
+    .bind(vcode).execute(pool).await?;
+    Ok(())
+}
+
+
+pub fn select_previous_version(dv: &DataVersion, vcodes: &[String]) -> Option<DataVersion> {
+
+    // The latest of the listed versions that is earlier than the given version.
+
+    vcodes.iter()
+        .filter_map(|v| DataVersion::parse(v))
+        .filter(|v| v < dv)
+        .max()
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_previous_version_selected() {
+        let vcodes: Vec<String> = ["v1.55", "v1.60", "v1.100", "v1.9", "v99"].iter().map(|s| s.to_string()).collect();
+        let dv = DataVersion::parse("v1.60").unwrap();
+        assert_eq!(select_previous_version(&dv, &vcodes), DataVersion::parse("v1.55"));
+        let dv = DataVersion::parse("v1.101").unwrap();
+        assert_eq!(select_previous_version(&dv, &vcodes), DataVersion::parse("v1.100"));
+        let dv = DataVersion::parse("v1.9").unwrap();
+        assert_eq!(select_previous_version(&dv, &vcodes), None);
+    }
+}
@@ -39,6 +39,17 @@ sqlx::query_as(sql)
 .fetch_all(pool).await.unwrap()
 }
 
+pub async fn fetch_src_record_ids (pool: &Pool<Postgres>) -> Vec<String> {
+let sql = "SELECT id FROM src.core_data order by id;".to_owned();
+sqlx::query_scalar(&sql).fetch_all(pool).await.unwrap()
+}
+
+pub async fn fetch_all_src_relationship_records (pool: &Pool<Postgres>) -> Vec<SrcRelationship> {
+let sql: &str  = "select * from src.relationships order by id, rel_type, related_id";
+sqlx::query_as(sql)
+.fetch_all(pool).await.unwrap()
+}
+
 pub async fn fetch_src_external_id_records (id: &str, pool: &Pool<Postgres>) -> Vec<SrcExternalId> {
 let sql: &str  = "select * from src.external_ids where id = $1 order by id_value";
 sqlx::query_as(sql)
@@ -1,5 +1,7 @@
 use ror1::run;
+use std::env;
 use std::ffi::OsString;
+use std::path::PathBuf;
 use std::thread;
 use std::time::Duration;
 use chrono::NaiveDate;
@@ -10,6 +12,8 @@ use ror1::setup::get_db_pool;
 use ror1::setup::env_reader;
 
 use super::src_data_access;
+use super::ror_data_access;
+use super::ror_record_structs::RorRelationship;
 use super::src_record_structs::{SrcCoreData, SrcRelationship, SrcExternalId, 
     SrcName, SrcLocation, SrcLink, SrcType, SrcAdminData};
 
@@ -290,4 +294,79 @@ async fn check_src_name_data() {
 }
 
 
+async fn import_test_file(target_file: &str, process: bool) {
 
+    let cd_path = env::current_dir().unwrap();
+    let target_path : PathBuf = [cd_path, PathBuf::from("tests"), PathBuf::from("test_data")].iter().collect();
+    let target_folder = target_path.to_str().unwrap();
+    let mut args : Vec<&str> = vec!["target/debug/ror1.exe", "-f", target_folder, "-s", target_file, 
+                                    "-v", "v99", "-d", "2030-01-01", "-r", "-z"];
+    if process {
+        args.push("-p");
+    }
+    let test_args = args.iter().map(|x| x.to_string().into()).collect::<Vec<OsString>>();
+    run(test_args).await.unwrap();
+}
+
+
+#[tokio::test]
+async fn check_csv_and_json_imports_give_same_src_data() {
+
+    // Runs after the other src tests, as it replaces the src data (with the same data, from the csv files).
+    // The csv format has no related labels - they are added from the names of the related organisations 
+    // once the data is loaded, so can only be added if those organisations are in the same file.
+
+    thread::sleep(Duration::from_secs(10));
+    let pool = fetch_db_pool().await.unwrap();
+
+    import_test_file("v99-2030-01-01-test-data_schema_v2.json", true).await;
+    let tables = ["core_data", "admin_data", "names", "relationships", "external_ids", 
+                  "links", "type", "locations", "domains"];
+    let mut json_nums = Vec::new();
+    for t in tables {
+        json_nums.push(src_data_access::fetch_src_record_num(t, &pool).await);
+    }
+    let json_rels = src_data_access::fetch_all_src_relationship_records(&pool).await;
+
+    import_test_file("v99-2030-01-01-test-data_schema_v2.csv", true).await;
+    let mut csv_nums = Vec::new();
+    for t in tables {
+        csv_nums.push(src_data_access::fetch_src_record_num(t, &pool).await);
+    }
+    let csv_rels = src_data_access::fetch_all_src_relationship_records(&pool).await;
+    let csv_ids = src_data_access::fetch_src_record_ids(&pool).await;
+
+    // A smaller file, in which the related organisations are (with one exception) also present.
+    // It is only imported, as the labels are added to the ror tables, within the import.
+
+    import_test_file("v99-2030-01-01-test-related-labels_schema_v2.csv", false).await;
+    let rmit_rels = ror_data_access::fetch_ror_relationship_records("04ttjf776", &pool).await;
+    let latrobe_rels = ror_data_access::fetch_ror_relationship_records("01rxfrp27", &pool).await;
+
+    // Restore the data from the json file before comparing, so that a failure leaves it in place
+    // for any other tests.
+
+    import_test_file("v99-2030-01-01-test-data_schema_v2.json", true).await;
+
+    assert_eq!(csv_nums, json_nums);
+    assert_eq!(csv_rels.len(), 25);
+    assert_eq!(csv_rels.len(), json_rels.len());
+    for (c, j) in csv_rels.iter().zip(json_rels.iter()) {
+        assert_eq!((&c.ror_name, c.rel_type, &c.related_id), (&j.ror_name, j.rel_type, &j.related_id));
+        assert!(!j.related_name.is_empty());
+        if csv_ids.contains(&c.related_id) {
+            assert_eq!(c.related_name, j.related_name);
+        }
+        else {
+            assert_eq!(c.related_name, "");
+        }
+    }
+
+    assert_eq!(rmit_rels, vec![RorRelationship{rel_type: "related".to_string(), 
+        related_id: "01rxfrp27".to_string(), related_label: "La Trobe University".to_string(),}]);
+    assert_eq!(latrobe_rels.len(), 2);
+    assert_eq!(latrobe_rels[0], RorRelationship{rel_type: "related".to_string(), 
+        related_id: "010mv7n52".to_string(), related_label: "".to_string(),});    // not in the file
+    assert_eq!(latrobe_rels[1], RorRelationship{rel_type: "related".to_string(), 
+        related_id: "04ttjf776".to_string(), related_label: "RMIT University".to_string(),});
+}